@@ -0,0 +1,186 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::punctuated::Punctuated;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Expr, Fields, LitInt, Path, Token};
+
+// How a field is read once its address is known
+enum FieldKind {
+    // Any Copy type, including arrays
+    Value,
+    // A type that derives RemoteStruct itself
+    Nested,
+    // Fixed size UTF-16 buffer, cut at the first NUL
+    Utf16(LitInt),
+}
+
+struct RemoteField {
+    ident: syn::Ident,
+    ty: syn::Type,
+    offset: Option<Expr>,
+    derefs: Vec<Expr>,
+    kind: FieldKind,
+    skip: bool,
+}
+
+fn parse_field(field: &syn::Field) -> syn::Result<RemoteField> {
+    let mut remote = RemoteField {
+        ident: field.ident.clone().unwrap(),
+        ty: field.ty.clone(),
+        offset: None,
+        derefs: Vec::new(),
+        kind: FieldKind::Value,
+        skip: false,
+    };
+    for attr in &field.attrs {
+        if attr.path().is_ident("offset") {
+            remote.offset = Some(attr.parse_args::<Expr>()?);
+        } else if attr.path().is_ident("deref") {
+            // #[deref] reads the pointer, #[deref(0x18, 0xD8)] follows one pointer per offset
+            match &attr.meta {
+                syn::Meta::Path(_) => remote.derefs.push(syn::parse_quote!(0)),
+                _ => remote
+                    .derefs
+                    .extend(attr.parse_args_with(Punctuated::<Expr, Token![,]>::parse_terminated)?),
+            }
+        } else if attr.path().is_ident("nested") {
+            remote.kind = FieldKind::Nested;
+        } else if attr.path().is_ident("utf16") {
+            remote.kind = FieldKind::Utf16(attr.parse_args::<LitInt>()?);
+        } else if attr.path().is_ident("skip") {
+            remote.skip = true;
+        }
+    }
+    if !remote.skip && remote.offset.is_none() {
+        return Err(Error::new_spanned(
+            field,
+            "RemoteStruct fields need #[offset(..)] or #[skip]",
+        ));
+    }
+    Ok(remote)
+}
+
+fn read_field(krate: &Path, field: &RemoteField) -> TokenStream2 {
+    let ty = &field.ty;
+    if field.skip {
+        return quote!(::std::default::Default::default());
+    }
+    let offset = field.offset.as_ref().unwrap();
+    let derefs = &field.derefs;
+    let chain = quote! {
+        #krate::pointer::PointerChain::address(address)
+            .offset((#offset) as isize)
+            #(.deref((#derefs) as isize))*
+    };
+    match &field.kind {
+        FieldKind::Value => quote!(#chain.read::<#ty>(reader)?),
+        FieldKind::Nested => quote! {
+            <#ty as #krate::remote::RemoteStruct>::read_remote(reader, #chain.resolve(reader)?)?
+        },
+        FieldKind::Utf16(len) => quote! {{
+            let units = #chain.read::<[u16; #len]>(reader)?;
+            let end = units.iter().position(|c| *c == 0).unwrap_or(units.len());
+            String::from_utf16_lossy(&units[..end])
+        }},
+    }
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let mut krate: Path = syn::parse_quote!(::core);
+    let mut serde = false;
+    for attr in &input.attrs {
+        if attr.path().is_ident("remote") {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("crate") {
+                    krate = meta.value()?.parse::<syn::LitStr>()?.parse()?;
+                    Ok(())
+                } else if meta.path.is_ident("serde") {
+                    serde = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `crate = \"..\"` or `serde`"))
+                }
+            })?;
+        }
+    }
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => fields
+                .named
+                .iter()
+                .map(parse_field)
+                .collect::<syn::Result<Vec<_>>>()?,
+            _ => {
+                return Err(Error::new_spanned(
+                    &input,
+                    "RemoteStruct needs named fields",
+                ))
+            }
+        },
+        _ => return Err(Error::new_spanned(&input, "RemoteStruct needs a struct")),
+    };
+
+    let name = &input.ident;
+    let name_str = name.to_string();
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let idents: Vec<_> = fields.iter().map(|f| &f.ident).collect();
+    let reads: Vec<_> = fields.iter().map(|f| read_field(&krate, f)).collect();
+    let shown: Vec<_> = fields
+        .iter()
+        .filter(|f| !f.skip)
+        .map(|f| &f.ident)
+        .collect();
+    let shown_str: Vec<_> = shown.iter().map(|i| i.to_string()).collect();
+    let shown_count = shown.len();
+
+    let serialize = if serde {
+        quote! {
+            impl #impl_generics ::serde::Serialize for #name #ty_generics #where_clause {
+                fn serialize<S: ::serde::Serializer>(
+                    &self,
+                    serializer: S,
+                ) -> ::std::result::Result<S::Ok, S::Error> {
+                    use ::serde::ser::SerializeStruct;
+                    let mut state = serializer.serialize_struct(#name_str, #shown_count)?;
+                    #(state.serialize_field(#shown_str, &self.#shown)?;)*
+                    state.end()
+                }
+            }
+        }
+    } else {
+        quote!()
+    };
+
+    Ok(quote! {
+        impl #impl_generics #krate::remote::RemoteStruct for #name #ty_generics #where_clause {
+            fn read_remote<R: #krate::memory::MemoryReader>(
+                reader: &R,
+                address: usize,
+            ) -> #krate::remote::Result<Self> {
+                Ok(Self {
+                    #(#idents: #reads,)*
+                })
+            }
+        }
+
+        impl #impl_generics ::std::fmt::Display for #name #ty_generics #where_clause {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                #(writeln!(f, "{}: {:?}", #shown_str, self.#shown)?;)*
+                Ok(())
+            }
+        }
+
+        #serialize
+    })
+}
+
+// Reads a struct field by field from `#[offset(..)]` instead of mirroring the
+// remote layout, see core::remote for the attributes.
+#[proc_macro_derive(RemoteStruct, attributes(remote, offset, deref, nested, utf16, skip))]
+pub fn derive_remote_struct(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
@@ -0,0 +1,417 @@
+use crate::error::ProcessError;
+use crate::memory::{MemoryAllocator, MemoryReader, MEM_FREE, PAGE_EXECUTABLE};
+use crate::process::Module;
+use anyhow::Result;
+use std::sync::Mutex;
+
+// VirtualAllocEx hands out memory in 64K steps
+pub const ALLOCATION_GRANULARITY: usize = 0x10000;
+const BLOCK_ALIGNMENT: usize = 0x10;
+// Keeps every byte of a block reachable with a rel32 from the target address
+const REL32_RANGE: usize = 0x7FF0_0000;
+
+#[derive(Debug)]
+struct Page {
+    base: usize,
+    size: usize,
+    protection: u32,
+    // (offset, size) of live blocks, sorted by offset
+    blocks: Vec<(usize, usize)>,
+}
+
+impl Page {
+    fn end(&self) -> usize {
+        self.base + self.size
+    }
+
+    // First gap that fits `size`
+    fn find(&self, size: usize) -> Option<usize> {
+        let mut offset = 0;
+        for (start, len) in &self.blocks {
+            if start - offset >= size {
+                return Some(offset);
+            }
+            offset = start + len;
+        }
+        (self.size - offset >= size).then_some(offset)
+    }
+
+    fn insert(&mut self, offset: usize, size: usize) -> usize {
+        let index = self.blocks.partition_point(|(start, _)| *start < offset);
+        self.blocks.insert(index, (offset, size));
+        self.base + offset
+    }
+}
+
+// Hands out blocks of target memory, small ones share pages with the same
+// protection. Blocks are freed when their RemoteAllocation is dropped and
+// whatever is left is freed with the allocator.
+#[derive(Debug)]
+pub struct RemoteAllocator<A: MemoryAllocator> {
+    process: A,
+    pages: Mutex<Vec<Page>>,
+}
+
+impl<A: MemoryAllocator> RemoteAllocator<A> {
+    pub fn new(process: A) -> RemoteAllocator<A> {
+        RemoteAllocator {
+            process,
+            pages: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn process(&self) -> &A {
+        &self.process
+    }
+
+    pub fn alloc(&self, size: usize, protection: u32) -> Result<RemoteAllocation<'_, A>> {
+        self.alloc_between(0, usize::MAX, size, protection)
+    }
+
+    // Every byte of the block can be reached from `address` with a rel32,
+    // for jumps out of hooked code
+    pub fn alloc_near(
+        &self,
+        address: usize,
+        size: usize,
+        protection: u32,
+    ) -> Result<RemoteAllocation<'_, A>> {
+        let low = address.saturating_sub(REL32_RANGE);
+        let high = address.saturating_add(REL32_RANGE);
+        self.alloc_between(low, high, size, protection)
+    }
+
+    // Number of pages currently taken from the target
+    pub fn page_count(&self) -> usize {
+        self.pages.lock().unwrap().len()
+    }
+
+    fn alloc_between(
+        &self,
+        low: usize,
+        high: usize,
+        size: usize,
+        protection: u32,
+    ) -> Result<RemoteAllocation<'_, A>> {
+        let size = size.max(1).next_multiple_of(BLOCK_ALIGNMENT);
+        let mut pages = self.pages.lock().unwrap();
+        for page in pages.iter_mut() {
+            if page.protection != protection || page.base < low || page.end() > high {
+                continue;
+            }
+            if let Some(offset) = page.find(size) {
+                let address = page.insert(offset, size);
+                return Ok(RemoteAllocation {
+                    allocator: self,
+                    address,
+                    size,
+                });
+            }
+        }
+
+        let page_size = size.next_multiple_of(ALLOCATION_GRANULARITY);
+        let base = if low == 0 && high == usize::MAX {
+            self.process.alloc(None, page_size, protection)?
+        } else {
+            self.alloc_page_between(low, high, page_size, protection)?
+        };
+        let mut page = Page {
+            base,
+            size: page_size,
+            protection,
+            blocks: Vec::new(),
+        };
+        let address = page.insert(0, size);
+        pages.push(page);
+        Ok(RemoteAllocation {
+            allocator: self,
+            address,
+            size,
+        })
+    }
+
+    // Tries free regions in [low, high) closest to the middle first
+    fn alloc_page_between(
+        &self,
+        low: usize,
+        high: usize,
+        size: usize,
+        protection: u32,
+    ) -> Result<usize> {
+        let target = low + (high - low) / 2;
+        let mut candidates: Vec<usize> = self
+            .process
+            .memory_regions()
+            .into_iter()
+            .filter(|info| info.state == MEM_FREE)
+            .filter_map(|info| {
+                let start = info.base.max(low).next_multiple_of(ALLOCATION_GRANULARITY);
+                let end = info.end().min(high);
+                if start >= end || end - start < size {
+                    return None;
+                }
+                let last = (end - size) & !(ALLOCATION_GRANULARITY - 1);
+                Some((target & !(ALLOCATION_GRANULARITY - 1)).clamp(start, last))
+            })
+            .collect();
+        candidates.sort_by_key(|candidate| candidate.abs_diff(target));
+        candidates
+            .into_iter()
+            .find_map(|candidate| self.process.alloc(Some(candidate), size, protection).ok())
+            .ok_or_else(|| ProcessError::NoMemoryNear(target).into())
+    }
+
+    fn release(&self, address: usize) {
+        let mut pages = self.pages.lock().unwrap();
+        let index = match pages
+            .iter()
+            .position(|page| page.base <= address && address < page.end())
+        {
+            Some(index) => index,
+            None => return,
+        };
+        let page = &mut pages[index];
+        let offset = address - page.base;
+        page.blocks.retain(|(start, _)| *start != offset);
+        if page.blocks.is_empty() {
+            let _ = self.process.free(page.base);
+            pages.remove(index);
+        }
+    }
+}
+
+impl<A: MemoryAllocator> Drop for RemoteAllocator<A> {
+    fn drop(&mut self) {
+        for page in self.pages.lock().unwrap().drain(..) {
+            let _ = self.process.free(page.base);
+        }
+    }
+}
+
+// A block of target memory, freed when dropped
+#[derive(Debug)]
+pub struct RemoteAllocation<'a, A: MemoryAllocator> {
+    allocator: &'a RemoteAllocator<A>,
+    address: usize,
+    size: usize,
+}
+
+impl<A: MemoryAllocator> RemoteAllocation<'_, A> {
+    pub fn address(&self) -> usize {
+        self.address
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn write_bytes(&self, offset: usize, buf: &[u8]) -> Result<()> {
+        if offset.saturating_add(buf.len()) > self.size {
+            return Err(ProcessError::WriteMemoryFail(self.address + offset).into());
+        }
+        self.allocator
+            .process
+            .write_bytes(self.address + offset, buf)
+    }
+
+    // Keeps the block until the allocator itself is dropped
+    pub fn leak(self) -> usize {
+        let address = self.address;
+        std::mem::forget(self);
+        address
+    }
+}
+
+impl<A: MemoryAllocator> Drop for RemoteAllocation<'_, A> {
+    fn drop(&mut self) {
+        self.allocator.release(self.address);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeCave {
+    pub address: usize,
+    pub size: usize,
+    pub section: String,
+}
+
+// Runs of at least `min_size` 0x00 or 0xCC bytes in the executable sections
+// of `module`, including the padding up to the section alignment
+pub fn find_code_caves<R: MemoryReader>(
+    reader: &R,
+    module: &Module,
+    min_size: usize,
+) -> Result<Vec<CodeCave>> {
+    let headers = module.headers(reader)?;
+    let mut caves = Vec::new();
+    for section in &headers.sections {
+        if section.protect & PAGE_EXECUTABLE == 0 {
+            continue;
+        }
+        let mut data = vec![0u8; section.size];
+        reader.read_bytes(section.base, &mut data)?;
+        let mut start = 0;
+        while start < data.len() {
+            let byte = data[start];
+            let len = data[start..].iter().take_while(|b| **b == byte).count();
+            if (byte == 0x00 || byte == 0xCC) && len >= min_size.max(1) {
+                caves.push(CodeCave {
+                    address: section.base + start,
+                    size: len,
+                    section: section.name.clone(),
+                });
+            }
+            start += len;
+        }
+    }
+    Ok(caves)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alloc::{find_code_caves, RemoteAllocator, ALLOCATION_GRANULARITY};
+    use crate::error::ProcessError;
+    use crate::image::test::build_pe;
+    use crate::image::PeImage;
+    use crate::memory::{
+        MemoryAllocator, MemoryInfo, MemoryReader, MemoryWriter, MAX_USER_ADDRESS, MEM_COMMIT,
+        PAGE_EXECUTE_READWRITE, PAGE_READWRITE,
+    };
+    use crate::process::Module;
+    use anyhow::Result;
+    use std::cell::RefCell;
+
+    // Address space bookkeeping only, nothing is stored
+    #[derive(Default)]
+    struct Fake {
+        allocations: RefCell<Vec<(usize, usize)>>,
+        next: RefCell<usize>,
+    }
+
+    impl MemoryReader for Fake {
+        fn read_bytes(&self, address: usize, _buf: &mut [u8]) -> Result<()> {
+            Err(ProcessError::ReadMemoryFail(address).into())
+        }
+
+        fn query_memory_info(&self, address: usize) -> Result<MemoryInfo> {
+            let allocations = self.allocations.borrow();
+            if let Some((base, size)) = allocations
+                .iter()
+                .find(|(base, size)| *base <= address && address < base + size)
+            {
+                return Ok(MemoryInfo {
+                    base: *base,
+                    size: *size,
+                    state: MEM_COMMIT,
+                    protect: PAGE_READWRITE,
+                    ..MemoryInfo::default()
+                });
+            }
+            let base = allocations
+                .iter()
+                .map(|(base, size)| base + size)
+                .filter(|end| *end <= address)
+                .max()
+                .unwrap_or(0);
+            let end = allocations
+                .iter()
+                .map(|(base, _)| *base)
+                .filter(|base| *base > address)
+                .min()
+                .unwrap_or(MAX_USER_ADDRESS + 1);
+            Ok(MemoryInfo::free(base, end))
+        }
+
+        fn get_module(&self, _name: &str) -> Option<Module> {
+            None
+        }
+
+        fn modules(&self) -> Vec<Module> {
+            Vec::new()
+        }
+    }
+
+    impl MemoryWriter for Fake {
+        fn write_bytes(&self, _address: usize, _buf: &[u8]) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    impl MemoryAllocator for Fake {
+        fn alloc(&self, address: Option<usize>, size: usize, _protection: u32) -> Result<usize> {
+            let base = address.unwrap_or_else(|| {
+                *self.next.borrow_mut() += 0x1000_0000_0000;
+                *self.next.borrow()
+            });
+            self.allocations.borrow_mut().push((base, size));
+            Ok(base)
+        }
+
+        fn free(&self, address: usize) -> Result<()> {
+            self.allocations
+                .borrow_mut()
+                .retain(|(base, _)| *base != address);
+            Ok(())
+        }
+    }
+
+    #[test]
+    pub fn test_remote_allocator() {
+        let allocator = RemoteAllocator::new(Fake::default());
+        let a = allocator.alloc(0x20, PAGE_READWRITE).unwrap();
+        let b = allocator.alloc(0x8, PAGE_READWRITE).unwrap();
+        assert_eq!(a.address() + 0x20, b.address());
+        assert_eq!(0x10, b.size());
+        assert!(b.write_bytes(0x8, &[0; 0x10]).is_err());
+
+        // Different protection gets its own page
+        let code = allocator.alloc(0x10, PAGE_EXECUTE_READWRITE).unwrap();
+        assert_eq!(2, allocator.page_count());
+        drop(code);
+        assert_eq!(1, allocator.page_count());
+
+        // The freed gap is reused
+        drop(a);
+        let c = allocator.alloc(0x10, PAGE_READWRITE).unwrap();
+        assert_eq!(b.address() - 0x20, c.address());
+
+        let target = 0x7FF6_0000_0000;
+        let near = allocator.alloc_near(target, 0x100, PAGE_READWRITE).unwrap();
+        assert!(near.address().abs_diff(target) < 0x8000_0000);
+        assert_eq!(0, near.address() % ALLOCATION_GRANULARITY);
+        let near2 = allocator.alloc_near(target, 0x100, PAGE_READWRITE).unwrap();
+        assert_eq!(near.address() + 0x100, near2.address());
+
+        let leaked = allocator
+            .alloc(0x10, PAGE_EXECUTE_READWRITE)
+            .unwrap()
+            .leak();
+        assert_ne!(0, leaked);
+        drop(b);
+        drop(c);
+        drop(near);
+        drop(near2);
+        assert_eq!(1, allocator.page_count());
+        drop(allocator);
+    }
+
+    #[test]
+    pub fn test_code_caves() {
+        let mut code = vec![0x90u8; 0x80];
+        code[0x10..0x30].fill(0xCC);
+        code[0x40..0x44].fill(0x00);
+        let file = build_pe(&[
+            (".text", &code, 0x60000020),
+            (".data", &[0; 0x100], 0xC0000040),
+        ]);
+        let image = PeImage::from_bytes("DarkSoulsIII.exe", file.as_slice()).unwrap();
+        let module = image.get_module("DarkSoulsIII.exe").unwrap();
+
+        let caves = find_code_caves(&image, &module, 0x10).unwrap();
+        assert_eq!(2, caves.len());
+        assert_eq!((0x140001010, 0x20), (caves[0].address, caves[0].size));
+        // Tail of .text up to the section alignment
+        assert_eq!((0x140001080, 0xF80), (caves[1].address, caves[1].size));
+        assert_eq!(".text", caves[1].section);
+    }
+}
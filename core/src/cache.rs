@@ -0,0 +1,329 @@
+use crate::error::ProcessError;
+use crate::memory::{MemoryInfo, MemoryReader, MemoryWriter};
+use crate::process::Module;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub const PAGE_SIZE: usize = 0x1000;
+// Longest run of missing pages fetched with a single read
+const MAX_RUN_PAGES: usize = 64;
+
+#[derive(Debug)]
+struct CachedPage {
+    // None when the page couldn't be read, so it isn't retried until it expires
+    data: Option<Box<[u8]>>,
+    fetched: Instant,
+    epoch: u64,
+}
+
+#[derive(Debug, Default)]
+struct PageCache {
+    pages: HashMap<usize, CachedPage>,
+    epoch: u64,
+    // Last time expired pages were dropped in ttl mode
+    swept: Option<Instant>,
+}
+
+// Read cache over another backend. Reads are served from whole pages, missing
+// neighbouring pages are fetched together. Pages expire after `ttl`, or when
+// `next_frame` is called if the cache is bound to frames.
+#[derive(Debug)]
+pub struct CachedReader<R: MemoryReader> {
+    inner: R,
+    ttl: Option<Duration>,
+    per_frame: bool,
+    cache: Mutex<PageCache>,
+}
+
+impl<R: MemoryReader> CachedReader<R> {
+    // Pages live until the next frame
+    pub fn new(inner: R) -> CachedReader<R> {
+        CachedReader {
+            inner,
+            ttl: None,
+            per_frame: true,
+            cache: Mutex::new(PageCache::default()),
+        }
+    }
+
+    // Pages live for `ttl`, frames are ignored
+    pub fn with_ttl(inner: R, ttl: Duration) -> CachedReader<R> {
+        CachedReader {
+            ttl: Some(ttl),
+            per_frame: false,
+            ..CachedReader::new(inner)
+        }
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    pub fn next_frame(&self) {
+        let mut cache = self.cache.lock().unwrap();
+        cache.epoch += 1;
+        if self.per_frame {
+            cache.pages.clear();
+        } else {
+            self.evict_expired(&mut cache);
+        }
+    }
+
+    pub fn invalidate(&self) {
+        self.cache.lock().unwrap().pages.clear();
+    }
+
+    pub fn invalidate_range(&self, address: usize, size: usize) {
+        let mut cache = self.cache.lock().unwrap();
+        for page in pages_of(address, size) {
+            cache.pages.remove(&page);
+        }
+    }
+
+    pub fn cached_pages(&self) -> usize {
+        self.cache.lock().unwrap().pages.len()
+    }
+
+    // Scatter read, every range gets its own result. All missing pages are
+    // fetched before any range is served.
+    pub fn read_many(&self, ranges: &[(usize, usize)]) -> Vec<Result<Vec<u8>>> {
+        let mut cache = self.cache.lock().unwrap();
+        let mut pages: Vec<usize> = ranges
+            .iter()
+            .flat_map(|(address, len)| pages_of(*address, *len))
+            .collect();
+        pages.sort_unstable();
+        pages.dedup();
+        self.fetch(&mut cache, &pages);
+        ranges
+            .iter()
+            .map(|(address, len)| {
+                let mut buf = vec![0; *len];
+                copy_from_pages(&cache, *address, &mut buf)?;
+                Ok(buf)
+            })
+            .collect()
+    }
+
+    fn is_fresh(&self, cache: &PageCache, page: &CachedPage) -> bool {
+        (!self.per_frame || page.epoch == cache.epoch)
+            && self.ttl.is_none_or(|ttl| page.fetched.elapsed() < ttl)
+    }
+
+    // Drops the pages past their ttl, at most once per ttl so reads don't
+    // walk the whole cache
+    fn evict_expired(&self, cache: &mut PageCache) {
+        let ttl = match self.ttl {
+            Some(ttl) => ttl,
+            None => return,
+        };
+        if cache.swept.is_some_and(|swept| swept.elapsed() < ttl) {
+            return;
+        }
+        cache.swept = Some(Instant::now());
+        cache.pages.retain(|_, page| page.fetched.elapsed() < ttl);
+    }
+
+    // Fetches every page in `pages` (sorted) that isn't cached, one read per
+    // run of adjacent missing pages
+    fn fetch(&self, cache: &mut PageCache, pages: &[usize]) {
+        self.evict_expired(cache);
+        let missing: Vec<usize> = pages
+            .iter()
+            .copied()
+            .filter(|page| {
+                cache
+                    .pages
+                    .get(page)
+                    .is_none_or(|cached| !self.is_fresh(cache, cached))
+            })
+            .collect();
+        let mut start = 0;
+        while start < missing.len() {
+            let mut end = start + 1;
+            while end < missing.len()
+                && end - start < MAX_RUN_PAGES
+                && missing[end] == missing[end - 1] + PAGE_SIZE
+            {
+                end += 1;
+            }
+            self.fetch_run(cache, &missing[start..end]);
+            start = end;
+        }
+    }
+
+    fn fetch_run(&self, cache: &mut PageCache, run: &[usize]) {
+        let fetched = Instant::now();
+        let epoch = cache.epoch;
+        let mut data = vec![0u8; run.len() * PAGE_SIZE];
+        let read = self.inner.read_bytes(run[0], &mut data);
+        let chunks: Vec<Option<Box<[u8]>>> = if read.is_ok() {
+            data.chunks(PAGE_SIZE)
+                .map(|chunk| Some(chunk.into()))
+                .collect()
+        } else {
+            // Some page in the run is unreadable, find out which
+            run.iter()
+                .map(|page| {
+                    let mut buf = vec![0u8; PAGE_SIZE];
+                    self.inner
+                        .read_bytes(*page, &mut buf)
+                        .ok()
+                        .map(|_| buf.into_boxed_slice())
+                })
+                .collect()
+        };
+        for (page, data) in run.iter().zip(chunks) {
+            cache.pages.insert(
+                *page,
+                CachedPage {
+                    data,
+                    fetched,
+                    epoch,
+                },
+            );
+        }
+    }
+}
+
+fn pages_of(address: usize, len: usize) -> impl Iterator<Item = usize> {
+    let first = address & !(PAGE_SIZE - 1);
+    let end = address.saturating_add(len);
+    (first..end).step_by(PAGE_SIZE)
+}
+
+fn copy_from_pages(cache: &PageCache, address: usize, buf: &mut [u8]) -> Result<()> {
+    let mut done = 0;
+    while done < buf.len() {
+        let current = address + done;
+        let page = current & !(PAGE_SIZE - 1);
+        let data = cache
+            .pages
+            .get(&page)
+            .and_then(|cached| cached.data.as_ref())
+            .ok_or(ProcessError::ReadMemoryFail(address))?;
+        let offset = current - page;
+        let count = (PAGE_SIZE - offset).min(buf.len() - done);
+        buf[done..done + count].copy_from_slice(&data[offset..offset + count]);
+        done += count;
+    }
+    Ok(())
+}
+
+impl<R: MemoryReader> MemoryReader for CachedReader<R> {
+    fn read_bytes(&self, address: usize, buf: &mut [u8]) -> Result<()> {
+        if address.checked_add(buf.len()).is_none() {
+            return Err(ProcessError::ReadMemoryFail(address).into());
+        }
+        let mut cache = self.cache.lock().unwrap();
+        let pages: Vec<usize> = pages_of(address, buf.len()).collect();
+        self.fetch(&mut cache, &pages);
+        copy_from_pages(&cache, address, buf)
+    }
+
+    fn query_memory_info(&self, address: usize) -> Result<MemoryInfo> {
+        self.inner.query_memory_info(address)
+    }
+
+    fn get_module(&self, name: &str) -> Option<Module> {
+        self.inner.get_module(name)
+    }
+
+    fn modules(&self) -> Vec<Module> {
+        self.inner.modules()
+    }
+
+    fn memory_regions(&self) -> Vec<MemoryInfo> {
+        self.inner.memory_regions()
+    }
+}
+
+// Writes go straight through and drop the pages they touch
+impl<R: MemoryWriter> MemoryWriter for CachedReader<R> {
+    fn write_bytes(&self, address: usize, buf: &[u8]) -> Result<()> {
+        self.invalidate_range(address, buf.len());
+        self.inner.write_bytes(address, buf)
+    }
+
+    fn protect(&self, address: usize, size: usize, protection: u32) -> Result<u32> {
+        self.inner.protect(address, size, protection)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cache::{CachedReader, PAGE_SIZE};
+    use crate::memory::{MemoryBuffer, MemoryInfo, MemoryReader};
+    use crate::process::Module;
+    use anyhow::Result;
+    use std::cell::Cell;
+    use std::time::Duration;
+
+    // Counts the reads that reach the backend
+    struct Counting {
+        buffer: MemoryBuffer,
+        reads: Cell<usize>,
+    }
+
+    impl MemoryReader for Counting {
+        fn read_bytes(&self, address: usize, buf: &mut [u8]) -> Result<()> {
+            self.reads.set(self.reads.get() + 1);
+            self.buffer.read_bytes(address, buf)
+        }
+
+        fn query_memory_info(&self, address: usize) -> Result<MemoryInfo> {
+            self.buffer.query_memory_info(address)
+        }
+
+        fn get_module(&self, name: &str) -> Option<Module> {
+            self.buffer.get_module(name)
+        }
+
+        fn modules(&self) -> Vec<Module> {
+            self.buffer.modules()
+        }
+    }
+
+    #[test]
+    pub fn test_cached_reader() {
+        let base = 0x10000usize;
+        let data: Vec<u8> = (0..PAGE_SIZE * 4).map(|i| i as u8).collect();
+        let reader = CachedReader::new(Counting {
+            buffer: MemoryBuffer::new(base, data),
+            reads: Cell::new(0),
+        });
+
+        // Three adjacent pages in one read, the second read is a hit
+        assert_eq!(0x10, reader.read::<u8>(base + 0x10).unwrap());
+        let results = reader.read_many(&[(base + 0x1FFE, 4), (base + 0x2010, 2)]);
+        assert_eq!(vec![0xFE, 0xFF, 0x00, 0x01], *results[0].as_ref().unwrap());
+        assert_eq!(vec![0x10, 0x11], *results[1].as_ref().unwrap());
+        assert_eq!(2, reader.inner().reads.get());
+        assert_eq!(3, reader.cached_pages());
+
+        // Past the end of the buffer: the run fails, then every page is tried alone
+        assert!(reader.read::<u32>(base + PAGE_SIZE * 4 - 2).is_err());
+        assert_eq!(5, reader.inner().reads.get());
+        assert!(reader.read::<u32>(base + PAGE_SIZE * 4 - 2).is_err());
+        assert_eq!(5, reader.inner().reads.get());
+
+        reader.next_frame();
+        assert_eq!(0, reader.cached_pages());
+        reader.read::<u8>(base).unwrap();
+        assert_eq!(6, reader.inner().reads.get());
+
+        let reader = CachedReader::with_ttl(reader.into_inner(), Duration::ZERO);
+        reader.read::<u8>(base).unwrap();
+        reader.read::<u8>(base).unwrap();
+        assert_eq!(8, reader.inner().reads.get());
+        // Expired pages are dropped instead of piling up
+        reader.read::<u8>(base + PAGE_SIZE * 2).unwrap();
+        assert_eq!(1, reader.cached_pages());
+    }
+}
@@ -0,0 +1,229 @@
+use crate::disasm::{read_available, MAX_INSTRUCTION_LEN};
+use crate::error::{CaptureError, ProcessError};
+use crate::memory::MemoryReader;
+use crate::pattern::Pattern;
+use anyhow::Result;
+use iced_x86::{Decoder, DecoderOptions, OpKind};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CaptureKind {
+    // Signed displacements relative to the end of the capture, as in
+    // `E8 [rel32]` or `48 8B 05 [rel32]`
+    Rel8,
+    Rel32,
+}
+
+impl CaptureKind {
+    // Bytes of the capture in the pattern
+    pub fn size(&self) -> usize {
+        match self {
+            CaptureKind::Rel8 => 1,
+            CaptureKind::Rel32 => 4,
+        }
+    }
+
+    // Pattern token of the capture: `[rel8]` or `[rel32]`
+    pub(crate) fn parse(token: &str) -> Option<CaptureKind> {
+        match token.to_ascii_lowercase().as_str() {
+            "[rel8]" => Some(CaptureKind::Rel8),
+            "[rel32]" => Some(CaptureKind::Rel32),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CaptureKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureKind::Rel8 => write!(f, "[rel8]"),
+            CaptureKind::Rel32 => write!(f, "[rel32]"),
+        }
+    }
+}
+
+// Wildcard bytes of a pattern whose value is used after the match
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Capture {
+    // From the match start
+    pub offset: usize,
+    pub kind: CaptureKind,
+}
+
+impl Capture {
+    // Absolute address the capture points to in the match at `address`
+    pub fn resolve<R: MemoryReader>(&self, reader: &R, address: usize) -> Result<usize> {
+        let field = address + self.offset;
+        let displacement = match self.kind {
+            CaptureKind::Rel8 => reader.read::<i8>(field)? as isize,
+            CaptureKind::Rel32 => reader.read::<i32>(field)? as isize,
+        };
+        Ok((field + self.kind.size()).wrapping_add_signed(displacement))
+    }
+}
+
+// Post-processing of a match. The steps run in order on a value that starts
+// as the match address:
+//
+//     capture 0          target of the first capture
+//     deref              pointer stored at the value
+//     add 0x10           value + 0x10, may be negative
+//     operand 1 at 7     operand 1 of the instruction at match + 7
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Step {
+    Capture(usize),
+    Deref,
+    Add(isize),
+    // Memory operands give their address, or their displacement when they
+    // aren't RIP-relative, branches their target and immediates their value
+    Operand { offset: usize, index: u32 },
+}
+
+impl FromStr for Step {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Step> {
+        let invalid = || CaptureError::InvalidStep(s.to_string());
+        let tokens: Vec<&str> = s.split_whitespace().collect();
+        let index = |text: &str| number(text).and_then(|value| usize::try_from(value).ok());
+        let step = match tokens.as_slice() {
+            ["capture"] => Step::Capture(0),
+            ["capture", n] => Step::Capture(index(n).ok_or_else(invalid)?),
+            ["deref"] => Step::Deref,
+            ["add", value] => Step::Add(number(value).ok_or_else(invalid)?),
+            ["operand", n, "at", offset] => Step::Operand {
+                offset: index(offset.trim_start_matches('+')).ok_or_else(invalid)?,
+                index: index(n).ok_or_else(invalid)? as u32,
+            },
+            _ => return Err(invalid().into()),
+        };
+        Ok(step)
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Capture(index) => write!(f, "capture {}", index),
+            Step::Deref => write!(f, "deref"),
+            Step::Add(value) if *value < 0 => write!(f, "add -{:#x}", value.unsigned_abs()),
+            Step::Add(value) => write!(f, "add {:#x}", value),
+            Step::Operand { offset, index } => write!(f, "operand {} at {}", index, offset),
+        }
+    }
+}
+
+// Decimal or 0x hex, with an optional minus sign
+fn number(text: &str) -> Option<isize> {
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(text) => (true, text),
+        None => (false, text),
+    };
+    let value = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => isize::from_str_radix(hex, 16).ok()?,
+        None => text.parse().ok()?,
+    };
+    Some(if negative { -value } else { value })
+}
+
+// Runs `steps` on the match of `pattern` at `address`
+pub fn resolve_match<R: MemoryReader>(
+    reader: &R,
+    pattern: &Pattern,
+    address: usize,
+    steps: &[Step],
+) -> Result<usize> {
+    let mut value = address;
+    for step in steps {
+        value = match *step {
+            Step::Capture(index) => pattern
+                .captures()
+                .get(index)
+                .ok_or(CaptureError::NoCapture(index))?
+                .resolve(reader, address)?,
+            Step::Deref => reader.read::<usize>(value)?,
+            Step::Add(add) => value.wrapping_add_signed(add),
+            Step::Operand { offset, index } => operand_value(reader, address + offset, index)?,
+        };
+    }
+    Ok(value)
+}
+
+fn operand_value<R: MemoryReader>(reader: &R, address: usize, index: u32) -> Result<usize> {
+    let code = read_available(reader, address, MAX_INSTRUCTION_LEN);
+    if code.is_empty() {
+        return Err(ProcessError::ReadMemoryFail(address).into());
+    }
+    let instruction = Decoder::with_ip(64, &code, address as u64, DecoderOptions::NONE).decode();
+    if instruction.is_invalid() || index >= instruction.op_count() {
+        return Err(CaptureError::NoOperand(address, index).into());
+    }
+    let value = match instruction.op_kind(index) {
+        // Already absolute for RIP-relative operands
+        OpKind::Memory => instruction.memory_displacement64(),
+        OpKind::NearBranch16 | OpKind::NearBranch32 | OpKind::NearBranch64 => {
+            instruction.near_branch_target()
+        }
+        OpKind::Immediate8
+        | OpKind::Immediate8_2nd
+        | OpKind::Immediate16
+        | OpKind::Immediate32
+        | OpKind::Immediate64
+        | OpKind::Immediate8to16
+        | OpKind::Immediate8to32
+        | OpKind::Immediate8to64
+        | OpKind::Immediate32to64 => instruction.immediate(index),
+        _ => return Err(CaptureError::NoOperand(address, index).into()),
+    };
+    Ok(value as usize)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::capture::{resolve_match, CaptureKind, Step};
+    use crate::memory::MemoryBuffer;
+    use crate::pattern::Pattern;
+
+    #[test]
+    pub fn test_resolve_match() {
+        let base = 0x140000000usize;
+        let mut data = vec![0u8; 0x200];
+        let code = [
+            0x48, 0x8B, 0x1D, 0xF9, 0x00, 0x00, 0x00, // mov rbx,[rip+0xF9]
+            0x48, 0x8B, 0x83, 0x90, 0x1F, 0x00, 0x00, // mov rax,[rbx+0x1F90]
+            0xEB, 0xF0, // jmp -0x10
+        ];
+        data[..code.len()].copy_from_slice(&code);
+        data[0x100..0x108].copy_from_slice(&(base + 0x180).to_le_bytes());
+        let buffer = MemoryBuffer::new(base, data);
+
+        let pattern = Pattern::parse("48 8B 1D [rel32] 48 8B 83 ?? ?? ?? ?? EB [rel8]").unwrap();
+        assert_eq!(2, pattern.captures().len());
+        assert_eq!(3, pattern.captures()[0].offset);
+        assert_eq!(CaptureKind::Rel8, pattern.captures()[1].kind);
+        assert_eq!(vec![0], pattern.search(buffer.as_slice()));
+        assert_eq!(
+            "48 8B 1D [rel32] 48 8B 83 ?? ?? ?? ?? EB [rel8]",
+            pattern.to_string()
+        );
+
+        let steps = |text: &str| -> Vec<Step> {
+            text.split(',').map(|step| step.parse().unwrap()).collect()
+        };
+        let resolve = |text: &str| resolve_match(&buffer, &pattern, base, &steps(text)).unwrap();
+        assert_eq!(base + 0x100, resolve("capture"));
+        assert_eq!(base + 0x180, resolve("capture 0, deref"));
+        assert_eq!(base + 0x170, resolve("capture 0, deref, add -0x10"));
+        assert_eq!(base, resolve("capture 1"));
+        assert_eq!(base + 0x100, resolve("operand 1 at 0"));
+        assert_eq!(0x1F90, resolve("operand 1 at +7"));
+        assert_eq!(base, resolve("operand 0 at 14"));
+
+        assert!(resolve_match(&buffer, &pattern, base, &steps("capture 2")).is_err());
+        assert!(resolve_match(&buffer, &pattern, base, &steps("operand 2 at 0")).is_err());
+        assert!("add".parse::<Step>().is_err());
+        assert!("operand 1 of 7".parse::<Step>().is_err());
+        assert_eq!("add -0x10", Step::Add(-0x10).to_string());
+    }
+}
@@ -0,0 +1,399 @@
+use crate::capture::{resolve_match, Step};
+use crate::error::DefinitionError;
+use crate::image::read_module_image;
+use crate::memory::MemoryReader;
+use crate::pattern::Pattern;
+use crate::process::Module;
+use crate::signature_set::{SignatureMatches, SignatureSet};
+use anyhow::Result;
+use iced_x86::{Decoder, DecoderOptions, OpKind};
+use std::collections::HashMap;
+use std::path::Path;
+use toml::value::Table;
+use toml::Value;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Resolve {
+    // The matched instruction itself
+    Address,
+    // Target of the RIP-relative memory operand or rel32 branch of the matched instruction
+    RipRelative,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SymbolKind {
+    Rva(usize),
+    Pattern {
+        pattern: String,
+        // From the match start to the instruction
+        offset: usize,
+        resolve: Resolve,
+        // Run on the match instead of `offset` and `resolve` when not empty
+        steps: Vec<Step>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    // None for the main module of the definitions
+    pub module: Option<String>,
+    pub kind: SymbolKind,
+    // Added to the resolved address
+    pub add: isize,
+}
+
+// Named symbols and struct field offsets for one build of a game, kept in a
+// data file so a patch only needs the file updated:
+//
+//     module = "DarkSoulsIII.exe"
+//
+//     [symbols]
+//     WorldChrMan = { rva = 0x4768E78 }
+//     GameDataMan = { pattern = "48 8B 05 ?? ?? ?? ?? 48 85 C0", resolve = "rip" }
+//
+//     [structs.WorldChrMan]
+//     player = 0x80
+//
+// A pattern symbol can also set `offset` (instruction position in the
+// pattern), `add` and `module`. `resolve` is "address" (default) or "rip".
+// Instead of those, `steps` lists crate::capture steps run on the match:
+//
+//     WorldChrMan = { pattern = "48 8B 1D [rel32] 48 8B F9", steps = ["capture 0"] }
+//
+// and a pattern with captures but neither of them resolves to its first one.
+#[derive(Debug, Clone, Default)]
+pub struct Definitions {
+    pub module: String,
+    pub symbols: Vec<Symbol>,
+    pub structs: HashMap<String, HashMap<String, usize>>,
+}
+
+impl Definitions {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Definitions> {
+        Definitions::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Definitions> {
+        let root: Value = text
+            .parse()
+            .map_err(|e: toml::de::Error| DefinitionError::Invalid(e.to_string()))?;
+        let module = root
+            .get("module")
+            .and_then(Value::as_str)
+            .ok_or_else(|| invalid("missing module"))?
+            .to_string();
+
+        let mut symbols = Vec::new();
+        for (name, value) in table(&root, "symbols")? {
+            let symbol = value
+                .as_table()
+                .ok_or_else(|| invalid(&format!("symbol {} is not a table", name)))?;
+            symbols.push(parse_symbol(name, symbol)?);
+        }
+
+        let mut structs = HashMap::new();
+        for (name, value) in table(&root, "structs")? {
+            let fields = value
+                .as_table()
+                .ok_or_else(|| invalid(&format!("struct {} is not a table", name)))?;
+            let mut offsets = HashMap::new();
+            for (field, offset) in fields {
+                let offset = offset
+                    .as_integer()
+                    .filter(|offset| *offset >= 0)
+                    .ok_or_else(|| invalid(&format!("{}.{} is not an offset", name, field)))?;
+                offsets.insert(field.clone(), offset as usize);
+            }
+            structs.insert(name.clone(), offsets);
+        }
+
+        Ok(Definitions {
+            module,
+            symbols,
+            structs,
+        })
+    }
+
+    // Resolves every symbol against the modules of `reader`. Patterns have to
+    // match exactly once in their module, all the patterns of a module are
+    // searched in one pass.
+    pub fn resolve<R: MemoryReader>(&self, reader: &R) -> Result<ResolvedDefinitions> {
+        let mut images: HashMap<&str, (Module, Vec<u8>, SignatureMatches)> = HashMap::new();
+        let mut symbols = HashMap::new();
+        for symbol in &self.symbols {
+            let module_name = symbol.module.as_deref().unwrap_or(&self.module);
+            if !images.contains_key(module_name) {
+                let module = reader
+                    .get_module(module_name)
+                    .ok_or_else(|| DefinitionError::ModuleNotFound(module_name.to_string()))?;
+                // Rva symbols don't need the image
+                let patterns = self.module_patterns(module_name);
+                let (image, matches) = match patterns.is_empty() {
+                    true => (Vec::new(), SignatureMatches::default()),
+                    false => {
+                        let image = read_module_image(reader, &module);
+                        let matches = SignatureSet::parse(&patterns)?.search(&image, module.base);
+                        (image, matches)
+                    }
+                };
+                images.insert(module_name, (module, image, matches));
+            }
+            let (module, image, matches) = &images[module_name];
+            let address = resolve_symbol(reader, symbol, module, image, matches)?;
+            symbols.insert(symbol.name.clone(), address.wrapping_add_signed(symbol.add));
+        }
+        Ok(ResolvedDefinitions {
+            symbols,
+            structs: self.structs.clone(),
+        })
+    }
+
+    // Name and pattern of every pattern symbol in `module`
+    fn module_patterns(&self, module: &str) -> Vec<(&str, &str)> {
+        self.symbols
+            .iter()
+            .filter(|symbol| symbol.module.as_deref().unwrap_or(&self.module) == module)
+            .filter_map(|symbol| match &symbol.kind {
+                SymbolKind::Pattern { pattern, .. } => {
+                    Some((symbol.name.as_str(), pattern.as_str()))
+                }
+                SymbolKind::Rva(_) => None,
+            })
+            .collect()
+    }
+}
+
+fn invalid(reason: &str) -> DefinitionError {
+    DefinitionError::Invalid(reason.to_string())
+}
+
+// Missing tables are empty
+fn table<'a>(root: &'a Value, name: &str) -> Result<Vec<(&'a String, &'a Value)>> {
+    match root.get(name) {
+        None => Ok(Vec::new()),
+        Some(Value::Table(table)) => Ok(table.iter().collect()),
+        Some(_) => Err(invalid(&format!("{} is not a table", name)).into()),
+    }
+}
+
+fn parse_symbol(name: &str, symbol: &Table) -> Result<Symbol> {
+    let integer = |key: &str| -> Result<Option<i64>> {
+        match symbol.get(key) {
+            None => Ok(None),
+            Some(value) => {
+                Ok(Some(value.as_integer().ok_or_else(|| {
+                    invalid(&format!("{}.{} is not an integer", name, key))
+                })?))
+            }
+        }
+    };
+    let string = |key: &str| -> Result<Option<String>> {
+        match symbol.get(key) {
+            None => Ok(None),
+            Some(value) => Ok(Some(
+                value
+                    .as_str()
+                    .ok_or_else(|| invalid(&format!("{}.{} is not a string", name, key)))?
+                    .to_string(),
+            )),
+        }
+    };
+
+    let kind = match (integer("rva")?, string("pattern")?) {
+        (Some(rva), None) if rva >= 0 => SymbolKind::Rva(rva as usize),
+        (None, Some(pattern)) => {
+            let compiled = Pattern::parse(&pattern)
+                .map_err(|e| invalid(&format!("{}.pattern: {}", name, e)))?;
+            let resolve = match string("resolve")?.as_deref() {
+                None | Some("address") => Resolve::Address,
+                Some("rip") => Resolve::RipRelative,
+                Some(other) => {
+                    return Err(invalid(&format!("{}.resolve {} is unknown", name, other)).into())
+                }
+            };
+            let offset = integer("offset")?.unwrap_or(0);
+            if offset < 0 {
+                return Err(invalid(&format!("{}.offset is negative", name)).into());
+            }
+            let mut steps = parse_steps(name, symbol)?;
+            let shorthand = symbol.contains_key("offset") || symbol.contains_key("resolve");
+            if shorthand && !steps.is_empty() {
+                return Err(invalid(&format!("{} has both steps and offset/resolve", name)).into());
+            }
+            if !shorthand && steps.is_empty() && !compiled.captures().is_empty() {
+                steps.push(Step::Capture(0));
+            }
+            SymbolKind::Pattern {
+                pattern,
+                offset: offset as usize,
+                resolve,
+                steps,
+            }
+        }
+        _ => return Err(invalid(&format!("{} needs either rva or pattern", name)).into()),
+    };
+    Ok(Symbol {
+        name: name.to_string(),
+        module: string("module")?,
+        kind,
+        add: integer("add")?.unwrap_or(0) as isize,
+    })
+}
+
+fn parse_steps(name: &str, symbol: &Table) -> Result<Vec<Step>> {
+    let steps = match symbol.get("steps") {
+        None => return Ok(Vec::new()),
+        Some(Value::Array(steps)) => steps,
+        Some(_) => return Err(invalid(&format!("{}.steps is not an array", name)).into()),
+    };
+    steps
+        .iter()
+        .map(|step| {
+            step.as_str()
+                .and_then(|step| step.parse().ok())
+                .ok_or_else(|| invalid(&format!("{}.steps has an invalid step {}", name, step)))
+        })
+        .collect::<std::result::Result<Vec<Step>, DefinitionError>>()
+        .map_err(Into::into)
+}
+
+fn resolve_symbol<R: MemoryReader>(
+    reader: &R,
+    symbol: &Symbol,
+    module: &Module,
+    image: &[u8],
+    matches: &SignatureMatches,
+) -> Result<usize> {
+    let (pattern, offset, resolve, steps) = match &symbol.kind {
+        SymbolKind::Rva(rva) => return Ok(module.base + rva),
+        SymbolKind::Pattern {
+            pattern,
+            offset,
+            resolve,
+            steps,
+        } => (pattern, *offset, *resolve, steps),
+    };
+    let found = matches.get(&symbol.name).unwrap_or(&[]);
+    let address = match found {
+        [found] if !steps.is_empty() => {
+            return resolve_match(reader, &Pattern::parse(pattern)?, *found, steps)
+        }
+        [found] => found + offset,
+        [] => return Err(DefinitionError::PatternNotFound(symbol.name.clone()).into()),
+        _ => {
+            return Err(DefinitionError::AmbiguousPattern(symbol.name.clone(), found.len()).into())
+        }
+    };
+    if resolve == Resolve::Address {
+        return Ok(address);
+    }
+
+    let code = image.get(address - module.base..).unwrap_or(&[]);
+    let instruction = Decoder::with_ip(64, code, address as u64, DecoderOptions::NONE).decode();
+    if instruction.is_ip_rel_memory_operand() {
+        return Ok(instruction.ip_rel_memory_address() as usize);
+    }
+    match instruction.op0_kind() {
+        OpKind::NearBranch32 | OpKind::NearBranch64 => {
+            Ok(instruction.near_branch_target() as usize)
+        }
+        _ => Err(DefinitionError::NoRelativeOperand(symbol.name.clone(), address).into()),
+    }
+}
+
+// Absolute symbol addresses of one attached process, with the struct offsets
+#[derive(Debug, Clone, Default)]
+pub struct ResolvedDefinitions {
+    symbols: HashMap<String, usize>,
+    structs: HashMap<String, HashMap<String, usize>>,
+}
+
+impl ResolvedDefinitions {
+    pub fn symbol(&self, name: &str) -> Result<usize> {
+        self.symbols
+            .get(name)
+            .copied()
+            .ok_or_else(|| DefinitionError::SymbolNotFound(name.to_string()).into())
+    }
+
+    pub fn field(&self, name: &str, field: &str) -> Result<usize> {
+        self.structs
+            .get(name)
+            .and_then(|fields| fields.get(field))
+            .copied()
+            .ok_or_else(|| {
+                DefinitionError::FieldNotFound(name.to_string(), field.to_string()).into()
+            })
+    }
+
+    pub fn symbols(&self) -> &HashMap<String, usize> {
+        &self.symbols
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::definitions::{Definitions, Resolve, SymbolKind};
+    use crate::image::test::build_pe;
+    use crate::image::PeImage;
+
+    const DEFINITIONS: &str = r#"
+module = "DarkSoulsIII.exe"
+
+[symbols]
+WorldChrMan = { rva = 0x2010 }
+GameDataMan = { pattern = "48 8B 05 ?? ?? ?? ?? 48 85 C0", resolve = "rip" }
+GameDataManLoad = { pattern = "48 85 C0 C3", add = 3 }
+GameDataManStatic = { pattern = "48 8B 05 [rel32] 48 85 C0" }
+GameDataManEnd = { pattern = "48 8B 05 [rel32]", steps = ["capture 0", "add 0x8"] }
+
+[structs.WorldChrMan]
+player = 0x80
+"#;
+
+    #[test]
+    pub fn test_definitions() {
+        let definitions = Definitions::parse(DEFINITIONS).unwrap();
+        assert_eq!("DarkSoulsIII.exe", definitions.module);
+        assert_eq!(5, definitions.symbols.len());
+        let game_data_man = definitions
+            .symbols
+            .iter()
+            .find(|s| s.name == "GameDataMan")
+            .unwrap();
+        assert!(matches!(
+            game_data_man.kind,
+            SymbolKind::Pattern {
+                resolve: Resolve::RipRelative,
+                ..
+            }
+        ));
+
+        // mov rax, [rip+0xFF9] ; test rax, rax ; ret
+        let code = [0x48, 0x8B, 0x05, 0xF9, 0x0F, 0, 0, 0x48, 0x85, 0xC0, 0xC3];
+        let file = build_pe(&[
+            (".text", &code, 0x60000020),
+            (".data", &[0; 0x20], 0xC0000040),
+        ]);
+        let image = PeImage::from_bytes("DarkSoulsIII.exe", file.as_slice()).unwrap();
+
+        let resolved = definitions.resolve(&image).unwrap();
+        assert_eq!(0x140002010, resolved.symbol("WorldChrMan").unwrap());
+        assert_eq!(0x140002000, resolved.symbol("GameDataMan").unwrap());
+        assert_eq!(0x14000100A, resolved.symbol("GameDataManLoad").unwrap());
+        assert_eq!(0x140002000, resolved.symbol("GameDataManStatic").unwrap());
+        assert_eq!(0x140002008, resolved.symbol("GameDataManEnd").unwrap());
+        assert_eq!(0x80, resolved.field("WorldChrMan", "player").unwrap());
+        assert!(resolved.symbol("ChrDbgFlags").is_err());
+        assert!(resolved.field("WorldChrMan", "chr_set").is_err());
+
+        let negative = DEFINITIONS.replace("add = 3", "offset = -7");
+        assert!(Definitions::parse(&negative).is_err());
+        let both = DEFINITIONS.replace("\"add 0x8\"]", "\"add 0x8\"], offset = 1");
+        assert!(Definitions::parse(&both).is_err());
+        let missing = DEFINITIONS.replace("48 85 C0 C3", "48 85 C0 C3 CC");
+        let definitions = Definitions::parse(&missing).unwrap();
+        assert!(definitions.resolve(&image).is_err());
+    }
+}
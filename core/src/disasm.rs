@@ -0,0 +1,276 @@
+use crate::error::ProcessError;
+use crate::memory::MemoryReader;
+use crate::process::Module;
+use anyhow::Result;
+use iced_x86::{
+    Decoder, DecoderOptions, Formatter, Instruction, IntelFormatter, MasmFormatter, NasmFormatter,
+    OpKind,
+};
+use std::fmt;
+
+// Longest x86 instruction
+pub(crate) const MAX_INSTRUCTION_LEN: usize = 15;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Syntax {
+    Nasm,
+    Intel,
+    Masm,
+}
+
+impl Syntax {
+    fn formatter(&self) -> Box<dyn Formatter> {
+        match self {
+            Syntax::Nasm => Box::new(NasmFormatter::new()),
+            Syntax::Intel => Box::new(IntelFormatter::new()),
+            Syntax::Masm => Box::new(MasmFormatter::new()),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DisasmRange {
+    // Number of instructions
+    Count(usize),
+    // Number of bytes, the last instruction may run past it
+    Bytes(usize),
+}
+
+// Absolute address an instruction refers to, with the module+offset and the
+// RTTI class found there
+#[derive(Debug, Clone, PartialEq)]
+pub struct Target {
+    pub address: usize,
+    pub symbol: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisasmInstruction {
+    pub address: usize,
+    pub bytes: Vec<u8>,
+    pub text: String,
+    // call/jmp/jcc destination
+    pub branch: Option<Target>,
+    // RIP-relative memory operand
+    pub memory: Option<Target>,
+}
+
+impl DisasmInstruction {
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn next_address(&self) -> usize {
+        self.address + self.bytes.len()
+    }
+}
+
+impl fmt::Display for DisasmInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:016X} {:<30} {}",
+            self.address,
+            hex::encode_upper(&self.bytes),
+            self.text
+        )?;
+        let symbols: Vec<&str> = [&self.branch, &self.memory]
+            .iter()
+            .filter_map(|target| target.as_ref()?.symbol.as_deref())
+            .collect();
+        if !symbols.is_empty() {
+            write!(f, " ; {}", symbols.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+pub fn disassemble<R: MemoryReader>(
+    reader: &R,
+    address: usize,
+    range: DisasmRange,
+    syntax: Syntax,
+) -> Result<Vec<DisasmInstruction>> {
+    let len = match range {
+        DisasmRange::Count(count) => count * MAX_INSTRUCTION_LEN,
+        DisasmRange::Bytes(len) => len + MAX_INSTRUCTION_LEN - 1,
+    };
+    let code = read_available(reader, address, len);
+    if code.is_empty() {
+        return Err(ProcessError::ReadMemoryFail(address).into());
+    }
+
+    let modules = reader.modules();
+    let mut formatter = syntax.formatter();
+    let mut decoder = Decoder::with_ip(64, &code, address as u64, DecoderOptions::NONE);
+    let mut instruction = Instruction::default();
+    let mut result = Vec::new();
+    while decoder.can_decode() {
+        let offset = decoder.position();
+        let done = match range {
+            DisasmRange::Count(count) => result.len() >= count,
+            DisasmRange::Bytes(len) => offset >= len,
+        };
+        if done {
+            break;
+        }
+        decoder.decode_out(&mut instruction);
+        // Cut off by the end of the readable memory
+        if instruction.is_invalid() && offset + MAX_INSTRUCTION_LEN > code.len() {
+            break;
+        }
+
+        let mut text = String::new();
+        formatter.format(&instruction, &mut text);
+        let branch = match instruction.op0_kind() {
+            OpKind::NearBranch16 | OpKind::NearBranch32 | OpKind::NearBranch64 => {
+                Some(instruction.near_branch_target() as usize)
+            }
+            _ => None,
+        };
+        let memory = if instruction.is_ip_rel_memory_operand() {
+            Some(instruction.ip_rel_memory_address() as usize)
+        } else {
+            None
+        };
+        result.push(DisasmInstruction {
+            address: instruction.ip() as usize,
+            bytes: code[offset..offset + instruction.len()].to_vec(),
+            text,
+            branch: branch.map(|target| resolve_target(reader, &modules, target)),
+            memory: memory.map(|target| resolve_target(reader, &modules, target)),
+        });
+    }
+    Ok(result)
+}
+
+// Reads as much of [address, address + len) as is readable from the start
+pub(crate) fn read_available<R: MemoryReader>(reader: &R, address: usize, len: usize) -> Vec<u8> {
+    let mut buf = vec![0u8; len];
+    if reader.read_bytes(address, &mut buf).is_ok() {
+        return buf;
+    }
+    // Stop at the end of the region
+    let end = match reader.query_memory_info(address) {
+        Ok(info) if info.end() > address => info.end().min(address + len),
+        _ => return Vec::new(),
+    };
+    buf.truncate(end - address);
+    match reader.read_bytes(address, &mut buf) {
+        Ok(_) => buf,
+        Err(_) => Vec::new(),
+    }
+}
+
+fn resolve_target<R: MemoryReader>(reader: &R, modules: &[Module], address: usize) -> Target {
+    let module = modules
+        .iter()
+        .find(|m| m.base <= address && address < m.base + m.size);
+    let symbol = module.map(|module| {
+        let mut symbol = format!("{}+{:X}", module.name, address - module.base);
+        if let Some(class) = vtable_class(reader, module, address) {
+            symbol.push_str(&format!(" (vtable {})", class));
+        } else if let Some(class) = reader
+            .read::<usize>(address)
+            .ok()
+            .and_then(|vtable| vtable_class(reader, module, vtable))
+        {
+            // Static object, its first field is the vtable
+            symbol.push_str(&format!(" ({})", class));
+        }
+        symbol
+    });
+    Target { address, symbol }
+}
+
+// Class name of the vtable at `address`, through the complete object locator
+// stored right before it
+pub fn vtable_class<R: MemoryReader>(
+    reader: &R,
+    module: &Module,
+    address: usize,
+) -> Option<String> {
+    let in_module = |address: usize| module.base <= address && address < module.base + module.size;
+    if !in_module(address) || !address.is_multiple_of(8) {
+        return None;
+    }
+    let locator = reader.read::<usize>(address - 8).ok()?;
+    if !in_module(locator) || reader.read::<u32>(locator).ok()? != 1 {
+        return None;
+    }
+    let self_offset = reader.read::<u32>(locator + 0x14).ok()? as usize;
+    if module.base + self_offset != locator {
+        return None;
+    }
+    let type_desc = module.base + reader.read::<u32>(locator + 0xC).ok()? as usize;
+    let name = reader.read_utf8_str(type_desc + 0x10, 255, &[]).ok()?;
+    demangle_class(&name)
+}
+
+// ".?AVChrIns@FD4@@" -> "FD4::ChrIns"
+fn demangle_class(name: &str) -> Option<String> {
+    let name = name
+        .strip_prefix(".?AV")
+        .or_else(|| name.strip_prefix(".?AU"))?
+        .strip_suffix("@@")?;
+    let parts: Vec<&str> = name.split('@').rev().collect();
+    Some(parts.join("::"))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::disasm::{demangle_class, disassemble, DisasmRange, Syntax};
+    use crate::memory::MemoryBuffer;
+
+    #[test]
+    pub fn test_disassemble() {
+        let base = 0x140000000usize;
+        let mut data = vec![0xCCu8; 0x500];
+        let code = [
+            0x48, 0x8B, 0x05, 0xF9, 0x00, 0x00, 0x00, // mov rax,[rip+0xF9]
+            0xE8, 0xF4, 0x00, 0x00, 0x00, // call +0xF4
+            0xC3, // ret
+        ];
+        data[..code.len()].copy_from_slice(&code);
+
+        // vtable at +0x208, complete object locator at +0x300
+        data[0x100..0x108].copy_from_slice(&(base + 0x208).to_le_bytes());
+        data[0x200..0x208].copy_from_slice(&(base + 0x300).to_le_bytes());
+        data[0x300..0x304].copy_from_slice(&1u32.to_le_bytes());
+        data[0x30C..0x310].copy_from_slice(&0x340u32.to_le_bytes());
+        data[0x314..0x318].copy_from_slice(&0x300u32.to_le_bytes());
+        data[0x350..0x362].copy_from_slice(b".?AVChrIns@FD4@@\0\0");
+        let buffer = MemoryBuffer::new(base, data).with_name("DarkSoulsIII.exe");
+
+        let instructions =
+            disassemble(&buffer, base, DisasmRange::Count(3), Syntax::Intel).unwrap();
+        assert_eq!(3, instructions.len());
+        assert_eq!(vec![0x48, 0x8B, 0x05, 0xF9, 0, 0, 0], instructions[0].bytes);
+        let memory = instructions[0].memory.as_ref().unwrap();
+        assert_eq!(base + 0x100, memory.address);
+        assert_eq!(
+            Some("DarkSoulsIII.exe+100 (FD4::ChrIns)"),
+            memory.symbol.as_deref()
+        );
+        let branch = instructions[1].branch.as_ref().unwrap();
+        assert_eq!(base + 0x100, branch.address);
+        assert!(instructions[2].branch.is_none());
+        assert_eq!("ret", instructions[2].text);
+        assert_eq!(base + 0xD, instructions[2].next_address());
+
+        let instructions = disassemble(&buffer, base, DisasmRange::Bytes(8), Syntax::Nasm).unwrap();
+        assert_eq!(2, instructions.len());
+
+        // Decoding stops at the end of the buffer
+        let end = base + 0x4FE;
+        let instructions = disassemble(&buffer, end, DisasmRange::Count(10), Syntax::Masm).unwrap();
+        assert_eq!(2, instructions.len());
+
+        assert_eq!(None, demangle_class("ChrIns"));
+        assert_eq!(Some("ChrIns".to_string()), demangle_class(".?AUChrIns@@"));
+    }
+}
@@ -12,9 +12,27 @@ pub enum ProcessError {
     #[error("Failed To Query Memory! Address: {0:#x}")]
     QueryMemoryFail(usize),
 
+    #[error("Failed To Write Memory! Address: {0:#x}")]
+    WriteMemoryFail(usize),
+
+    #[error("Failed To Change Memory Protection! Address: {0:#x}")]
+    ProtectMemoryFail(usize),
+
+    #[error("Failed To Allocate Memory! Size: {0:#x}")]
+    AllocMemoryFail(usize),
+
+    #[error("Failed To Free Memory! Address: {0:#x}")]
+    FreeMemoryFail(usize),
+
+    #[error("No Free Memory Within 2GB! Address: {0:#x}")]
+    NoMemoryNear(usize),
+
     #[error("Process Not Found! Name: {0}")]
     ProcessNotFound(String),
 
+    #[error("Ambiguous Process! Name: {0} Matches: {1:?}")]
+    AmbiguousProcess(String, Vec<u32>),
+
     #[error("Failed To Create File Mapping Name: {0}")]
     CreateFileMapping(String),
 
@@ -45,4 +63,143 @@ pub enum ShMemQError {
 
     #[error("Invalid Share Memory Message Queue")]
     InvalidMq,
-}
\ No newline at end of file
+}
+
+#[derive(Error, Debug)]
+pub enum SnapshotError {
+    #[error("Invalid Snapshot File")]
+    InvalidMagic,
+
+    #[error("Unsupported Snapshot Version: {0}")]
+    UnsupportedVersion(u32),
+
+    #[error("Corrupted Snapshot: {0}")]
+    Corrupted(String),
+}
+
+#[derive(Error, Debug)]
+pub enum MinidumpError {
+    #[error("Invalid Minidump Signature")]
+    InvalidSignature,
+
+    #[error("Corrupted Minidump at {0:X}")]
+    Corrupted(usize),
+}
+
+#[derive(Error, Debug)]
+pub enum PointerChainError {
+    #[error("Invalid Pointer Chain: {expression} At {position}: {reason}")]
+    Parse {
+        expression: String,
+        position: usize,
+        reason: String,
+    },
+
+    #[error("Module Not Found: {0}")]
+    ModuleNotFound(String),
+
+    #[error("Failed To Read Pointer At Hop {hop}! Address: {address:#x}")]
+    ReadFail { hop: usize, address: usize },
+
+    #[error("Null Pointer At Hop {hop}! Address: {address:#x}")]
+    NullPointer { hop: usize, address: usize },
+}
+
+#[derive(Error, Debug)]
+pub enum PointerMapError {
+    #[error("Invalid Pointer Map File")]
+    InvalidMagic,
+
+    #[error("Unsupported Pointer Map Version: {0}")]
+    UnsupportedVersion(u32),
+
+    #[error("Corrupted Pointer Map: {0}")]
+    Corrupted(String),
+}
+
+#[derive(Error, Debug)]
+pub enum RemoteError {
+    #[error("Null Pointer! Type: {0}")]
+    NullPointer(&'static str),
+
+    #[error("Invalid Container At {address:#x}: {reason}")]
+    InvalidContainer { address: usize, reason: String },
+}
+
+#[derive(Error, Debug)]
+pub enum ScanError {
+    #[error("Invalid Scan Value: {0}")]
+    InvalidValue(String),
+
+    #[error("Unsupported Scan For This Value Type: {0}")]
+    Unsupported(String),
+
+    #[error("Scan Cancelled")]
+    Cancelled,
+
+    #[error("Scan Worker Panicked")]
+    WorkerPanicked,
+}
+
+#[derive(Error, Debug)]
+pub enum PatternError {
+    #[error("Empty Pattern")]
+    Empty,
+
+    #[error("Invalid Pattern Token: {0}")]
+    InvalidToken(String),
+
+    #[error("Pattern Has {0} Bytes But The Mask Has {1}")]
+    MaskLength(usize, usize),
+}
+
+#[derive(Error, Debug)]
+pub enum CaptureError {
+    #[error("Pattern Has No Capture {0}")]
+    NoCapture(usize),
+
+    #[error("Instruction Has No Usable Operand {1}! Address: {0:#x}")]
+    NoOperand(usize, u32),
+
+    #[error("Invalid Resolve Step: {0}")]
+    InvalidStep(String),
+}
+
+#[derive(Error, Debug)]
+pub enum SignatureError {
+    #[error("Address Is Not In Executable Code! Address: {0:#x}")]
+    NotCode(usize),
+
+    #[error("No Unique Signature Found! Address: {0:#x}")]
+    NotUnique(usize),
+
+    #[error("Duplicate Signature Name: {0}")]
+    DuplicateName(String),
+
+    #[error("Signatures Not Matched Exactly Once! {0}")]
+    NotMatchedOnce(String),
+}
+
+#[derive(Error, Debug)]
+pub enum DefinitionError {
+    #[error("Invalid Definitions: {0}")]
+    Invalid(String),
+
+    #[error("Module Not Found: {0}")]
+    ModuleNotFound(String),
+
+    #[error("Symbol Not Found: {0}")]
+    SymbolNotFound(String),
+
+    #[error("Struct Field Not Found: {0}.{1}")]
+    FieldNotFound(String, String),
+
+    #[error("Pattern Not Found For Symbol: {0}")]
+    PatternNotFound(String),
+
+    #[error("Pattern For Symbol {0} Matches {1} Times")]
+    AmbiguousPattern(String, usize),
+
+    #[error("No RIP-Relative Operand For Symbol {0}! Address: {1:#x}")]
+    NoRelativeOperand(String, usize),
+}
@@ -1,27 +1,120 @@
+use crate::cache::CachedReader;
+use crate::definitions::Definitions;
 use crate::error::ProcessError;
-use crate::process::Process;
+use crate::memory::MemoryReader;
+use crate::msvc::StdVector;
+use crate::pointer::PointerChain;
+use crate::process::{Process, ProcessInfo, ProcessMatch};
+use crate::remote::RemoteStruct;
 use anyhow::Result;
+use std::path::Path;
 
-const PROCESS_NAME: &'static str = "DarkSoulsIII.exe";
-// const PROCESS_NAME: &'static str = "notepad.exe";
+const PROCESS_NAME: &str = "DarkSoulsIII.exe";
+// const PROCESS_NAME: &str = "notepad.exe";
+const DEFINITIONS_FILE: &str = "DarkSoulsIII.toml";
+const DEFAULT_DEFINITIONS: &str = include_str!("../definitions/DarkSoulsIII.toml");
+
+// DarkSoulsIII.toml in the working directory, or the copy built in
+pub fn load_definitions() -> Result<Definitions> {
+    if Path::new(DEFINITIONS_FILE).exists() {
+        Definitions::load(DEFINITIONS_FILE)
+    } else {
+        Definitions::parse(DEFAULT_DEFINITIONS)
+    }
+}
+
+// Addresses and offsets of the running build, resolved from the definitions
+// once when attaching
+#[derive(Debug, Copy, Clone, Default)]
+pub struct GameOffsets {
+    pub world_chr_man: usize,
+    pub session_manager: usize,
+    pub chr_set: usize,
+    pub player: usize,
+    pub chr_modules: usize,
+    pub player_game_data: usize,
+    pub chr_data_module: usize,
+    pub chr_stats: usize,
+    pub game_data: usize,
+    pub phantom_count: usize,
+    pub player_count: usize,
+}
+
+impl GameOffsets {
+    pub fn resolve<R: MemoryReader>(ps: &R, definitions: &Definitions) -> Result<GameOffsets> {
+        let resolved = definitions.resolve(ps)?;
+        Ok(GameOffsets {
+            world_chr_man: resolved.symbol("WorldChrMan")?,
+            session_manager: resolved.symbol("SprjSessionManager")?,
+            chr_set: resolved.field("WorldChrMan", "chr_set")?,
+            player: resolved.field("WorldChrMan", "player")?,
+            chr_modules: resolved.field("PlayerIns", "chr_modules")?,
+            player_game_data: resolved.field("PlayerIns", "player_game_data")?,
+            chr_data_module: resolved.field("ChrModules", "chr_data_module")?,
+            chr_stats: resolved.field("SprjChrDataModule", "stats")?,
+            game_data: resolved.field("PlayerGameDataMan", "data")?,
+            phantom_count: resolved.field("SprjSessionManager", "phantom_count")?,
+            player_count: resolved.field("SprjSessionManager", "player_count")?,
+        })
+    }
+}
 
 #[derive(Debug, Clone)]
-pub struct GameData {
-    pub ps: Process,
+pub struct GameData<R: MemoryReader = Process> {
+    pub ps: R,
+    offsets: GameOffsets,
     world_chr_man: WorldChrMan,
 }
 
 impl GameData {
+    // Fails when several instances are running, pick one with from_pid
     pub fn init() -> Result<GameData> {
-        let process = Process::from_name(PROCESS_NAME)
-            .ok_or(ProcessError::ProcessNotFound(PROCESS_NAME.to_string()))?;
-        let world_chr_man = WorldChrMan::init(&process)?;
+        GameData::from_reader(Process::open(&ProcessMatch::Exact(PROCESS_NAME))?)
+    }
+
+    pub fn from_pid(pid: u32) -> Result<GameData> {
+        let process =
+            Process::from_pid(pid).ok_or(ProcessError::ProcessNotFound(pid.to_string()))?;
+        GameData::from_reader(process)
+    }
+
+    pub fn instances() -> Vec<ProcessInfo> {
+        Process::find_all(&ProcessMatch::Exact(PROCESS_NAME))
+    }
+
+    // For the overlay, memory is read once per frame in page sized batches
+    pub fn init_cached() -> Result<GameData<CachedReader<Process>>> {
+        let process = Process::open(&ProcessMatch::Exact(PROCESS_NAME))?;
+        GameData::from_reader(CachedReader::new(process))
+    }
+}
+
+impl<R: MemoryReader> GameData<CachedReader<R>> {
+    pub fn refresh_frame(&mut self) -> Result<()> {
+        self.ps.next_frame();
+        self.refresh_world_char_man_data()
+    }
+}
+
+impl<R: MemoryReader> GameData<R> {
+    pub fn from_reader(ps: R) -> Result<GameData<R>> {
+        GameData::with_definitions(ps, &load_definitions()?)
+    }
+
+    pub fn with_definitions(ps: R, definitions: &Definitions) -> Result<GameData<R>> {
+        let offsets = GameOffsets::resolve(&ps, definitions)?;
+        let world_chr_man = WorldChrMan::init(offsets, &ps)?;
         Ok(Self {
-            ps: process,
+            ps,
+            offsets,
             world_chr_man,
         })
     }
 
+    pub fn offsets(&self) -> &GameOffsets {
+        &self.offsets
+    }
+
     pub fn refresh_world_char_man_data(&mut self) -> Result<()> {
         self.world_chr_man.refresh_data(&self.ps)
     }
@@ -33,7 +126,7 @@ impl GameData {
 
 #[derive(Debug, Clone, Default)]
 pub struct WorldChrMan {
-    image_base: usize,
+    offsets: GameOffsets,
     world_char_man: usize,
 
     // Data
@@ -42,20 +135,22 @@ pub struct WorldChrMan {
 }
 
 impl WorldChrMan {
-    pub fn init(ps: &Process) -> Result<WorldChrMan> {
-        let mut man = WorldChrMan::default();
-        man.image_base = ps
-            .get_module(PROCESS_NAME)
-            .ok_or(ProcessError::ModuleNotFound)?
-            .base;
-        Ok(man)
+    pub fn init<R: MemoryReader>(offsets: GameOffsets, _ps: &R) -> Result<WorldChrMan> {
+        Ok(WorldChrMan {
+            offsets,
+            ..Default::default()
+        })
     }
 
-    pub fn refresh_data(&mut self, ps: &Process) -> Result<()> {
-        self.world_char_man = ps.read::<usize>(self.image_base + 0x4768E78)?;
-        let player_ptr = ps.read::<usize>(self.world_char_man + 0x80)?;
-        self.player_ins = PlayerIns::init(player_ptr, ps)?;
-        self.session_info_man = SessionInfoMan::init(self.world_char_man, ps)?;
+    pub fn refresh_data<R: MemoryReader>(&mut self, ps: &R) -> Result<()> {
+        self.world_char_man = PointerChain::address(self.offsets.world_chr_man)
+            .deref(0)
+            .resolve(ps)?;
+        let player_ptr = PointerChain::address(self.world_char_man)
+            .offset(self.offsets.player as isize)
+            .read::<usize>(ps)?;
+        self.player_ins = PlayerIns::init(player_ptr, self.offsets, ps)?;
+        self.session_info_man = SessionInfoMan::init(self.world_char_man, self.offsets, ps)?;
         self.player_ins.refresh_data(ps)?;
         self.session_info_man.refresh_data(self.world_char_man, ps)
     }
@@ -63,6 +158,7 @@ impl WorldChrMan {
 
 #[derive(Debug, Clone, Default)]
 pub struct PlayerIns {
+    offsets: GameOffsets,
     player_ins: usize,
     sprj_chr_data_module: usize,
 
@@ -71,150 +167,154 @@ pub struct PlayerIns {
     pub player_game_data: PlayerGameDataMan,
 }
 
-#[derive(Debug, Copy, Clone, Default)]
-#[repr(packed)]
+#[derive(RemoteStruct, Debug, Clone, Default)]
+#[remote(crate = "crate")]
+#[cfg_attr(feature = "serde", remote(serde))]
 pub struct ChrStats {
+    #[offset(0x0)]
     pub hp: u32,
+    #[offset(0x4)]
     pub max_hp: u32,
+    #[offset(0x8)]
     pub base_max_hp: u32,
+    #[offset(0xC)]
     pub mp: u32,
+    #[offset(0x10)]
     pub max_mp: u32,
+    #[offset(0x14)]
     pub base_max_mp: u32,
+    #[offset(0x18)]
     pub sp: u32,
+    #[offset(0x1C)]
     pub max_sp: u32,
+    #[offset(0x20)]
     pub base_max_sp: u32,
 }
 
-#[derive(Debug, Copy, Clone, Default)]
-#[repr(packed)]
+#[derive(RemoteStruct, Debug, Clone, Default)]
+#[remote(crate = "crate")]
+#[cfg_attr(feature = "serde", remote(serde))]
 pub struct ChrAttributes {
+    #[offset(0x0)]
     pub vigor: u32,
+    #[offset(0x4)]
     pub attunement: u32,
+    #[offset(0x8)]
     pub endurance: u32,
+    #[offset(0xC)]
     pub strength: u32,
+    #[offset(0x10)]
     pub dexterity: u32,
+    #[offset(0x14)]
     pub intelligence: u32,
+    #[offset(0x18)]
     pub faith: u32,
+    #[offset(0x1C)]
     pub luck: u32,
-    pub unknown_1: u32,
-    pub unknown_2: u32,
+    #[offset(0x28)]
     pub vitality: u32,
+    #[offset(0x2C)]
     pub soul_level: u32,
-    pub unknown_3: u32,
-    pub unknown_4: u32,
-    pub unknown_5: u32,
-    pub unknown_6: u32,
-    pub unknown_7: u32,
-    pub name_bytes: [u16; 16],
-}
-
-impl ChrAttributes {
-    pub fn name_string(&self) -> String {
-        let name_bytes = self.name_bytes;
-        String::from_utf16_lossy(name_bytes.as_ref())
-            .trim_matches('\0')
-            .to_string()
-    }
-    pub fn vigor_string(&self) -> String {
-        let v = self.vigor;
-        v.to_string()
-    }
-    pub fn attunement_string(&self) -> String {
-        let v = self.attunement;
-        v.to_string()
-    }
-    pub fn endurance_string(&self) -> String {
-        let v = self.endurance;
-        v.to_string()
-    }
-    pub fn strength_string(&self) -> String {
-        let v = self.strength;
-        v.to_string()
-    }
-    pub fn dexterity_string(&self) -> String {
-        let v = self.dexterity;
-        v.to_string()
-    }
-
-    pub fn intelligence_string(&self) -> String {
-        let v = self.intelligence;
-        v.to_string()
-    }
-    pub fn faith_string(&self) -> String {
-        let v = self.faith;
-        v.to_string()
-    }
-    pub fn luck_string(&self) -> String {
-        let v = self.luck;
-        v.to_string()
-    }
-    pub fn vitality_string(&self) -> String {
-        let v = self.vitality;
-        v.to_string()
-    }
-    pub fn soul_level_string(&self) -> String {
-        let v = self.soul_level;
-        v.to_string()
-    }
+    #[offset(0x44)]
+    #[utf16(16)]
+    pub name: String,
 }
 
-#[derive(Debug, Copy, Clone, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct PlayerGameDataMan {
     player_game_data: usize,
 
     pub data: PlayerGameData,
 }
 
-#[derive(Debug, Copy, Clone, Default)]
-#[repr(packed)]
+#[derive(RemoteStruct, Debug, Clone, Default)]
+#[remote(crate = "crate")]
+#[cfg_attr(feature = "serde", remote(serde))]
 pub struct PlayerGameData {
+    #[offset(0x0)]
     pub hp: u32,
+    #[offset(0x4)]
     pub max_hp: u32,
+    #[offset(0x8)]
     pub base_max_hp: u32,
+    #[offset(0xC)]
     pub mp: u32,
+    #[offset(0x10)]
     pub max_mp: u32,
+    #[offset(0x14)]
     pub base_max_mp: u32,
+    #[offset(0x18)]
     pub max_sp: u32,
+    #[offset(0x1C)]
     pub sp: u32,
+    #[offset(0x20)]
     pub base_max_sp: u32,
-    pub unknown_1: u32,
-    pub unknown_2: u32,
+    #[offset(0x2C)]
+    #[nested]
     pub attributes: ChrAttributes,
 }
 
 impl PlayerGameDataMan {
-    pub fn init(player_game_data: usize, _ps: &Process) -> Result<PlayerGameDataMan> {
-        let mut man = PlayerGameDataMan::default();
-        man.player_game_data = player_game_data;
-        Ok(man)
+    pub fn init<R: MemoryReader>(player_game_data: usize, _ps: &R) -> Result<PlayerGameDataMan> {
+        Ok(PlayerGameDataMan {
+            player_game_data,
+            ..Default::default()
+        })
     }
 
-    pub fn refresh_data(&mut self, ps: &Process) -> Result<()> {
-        self.data = ps.read::<PlayerGameData>(self.player_game_data)?;
+    pub fn refresh_data<R: MemoryReader>(&mut self, ps: &R) -> Result<()> {
+        self.data = PlayerGameData::read_remote(ps, self.player_game_data)?;
         Ok(())
     }
 }
 
 impl PlayerIns {
-    pub fn init(player_ins: usize, ps: &Process) -> Result<PlayerIns> {
-        let mut man = PlayerIns::default();
-        man.player_ins = player_ins;
-        man.sprj_chr_data_module =
-            ps.read::<usize>(ps.read::<usize>(player_ins + 0x1F90)? + 0x18)?;
-        man.player_game_data =
-            PlayerGameDataMan::init(ps.read::<usize>(player_ins + 0x1FA0)? + 0x18, ps)?;
-        Ok(man)
+    pub fn init<R: MemoryReader>(
+        player_ins: usize,
+        offsets: GameOffsets,
+        ps: &R,
+    ) -> Result<PlayerIns> {
+        let sprj_chr_data_module = PointerChain::address(player_ins)
+            .offset(offsets.chr_modules as isize)
+            .deref(offsets.chr_data_module as isize)
+            .read::<usize>(ps)?;
+        let player_game_data = PointerChain::address(player_ins)
+            .offset(offsets.player_game_data as isize)
+            .deref(offsets.game_data as isize)
+            .resolve(ps)?;
+        Ok(PlayerIns {
+            offsets,
+            player_ins,
+            sprj_chr_data_module,
+            player_game_data: PlayerGameDataMan::init(player_game_data, ps)?,
+            ..Default::default()
+        })
+    }
+
+    // Address of the PlayerIns in the game
+    pub fn address(&self) -> usize {
+        self.player_ins
     }
 
-    pub fn refresh_data(&mut self, ps: &Process) -> Result<()> {
-        self.chr_stats = ps.read::<ChrStats>(self.sprj_chr_data_module + 0xd8)?;
+    pub fn refresh_data<R: MemoryReader>(&mut self, ps: &R) -> Result<()> {
+        self.chr_stats =
+            ChrStats::read_remote(ps, self.sprj_chr_data_module + self.offsets.chr_stats)?;
         self.player_game_data.refresh_data(ps)?;
         Ok(())
     }
 }
 
+// Element of the std::vector at WorldChrMan.chr_set
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct ChrSetEntry {
+    player_ins: usize,
+    _unknown: [u8; 0x30],
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct SessionInfoMan {
+    offsets: GameOffsets,
     misc_player_count: usize,
     misc_phantoms_count: usize,
     world_char_man: usize,
@@ -225,28 +325,30 @@ pub struct SessionInfoMan {
 }
 
 impl SessionInfoMan {
-    pub fn init(world_char_man: usize, ps: &Process) -> Result<SessionInfoMan> {
+    pub fn init<R: MemoryReader>(
+        world_char_man: usize,
+        offsets: GameOffsets,
+        ps: &R,
+    ) -> Result<SessionInfoMan> {
         let mut man = SessionInfoMan::default();
-        let base = ps
-            .get_module(PROCESS_NAME)
-            .ok_or(ProcessError::ModuleNotFound)?
-            .base;
-        man.misc_player_count = ps.read::<usize>(base + 0x4743AB0)? + 0xD38;
-        man.misc_phantoms_count = ps.read::<usize>(base + 0x4743AB0)? + 0xD28;
+        let session_manager = ps.read::<usize>(offsets.session_manager)?;
+        man.offsets = offsets;
+        man.misc_player_count = session_manager + offsets.player_count;
+        man.misc_phantoms_count = session_manager + offsets.phantom_count;
         man.world_char_man = world_char_man;
 
         Ok(man)
     }
 
-    pub fn refresh_data(&mut self, world_char_man: usize, ps: &Process) -> Result<()> {
+    pub fn refresh_data<R: MemoryReader>(&mut self, world_char_man: usize, ps: &R) -> Result<()> {
         self.world_char_man = world_char_man;
-        self.players_base = ps.read::<usize>(self.world_char_man + 0x40)?;
-        let online_players_count = ps.read::<u32>(self.misc_player_count)?;
+        let entries =
+            ps.read::<StdVector<ChrSetEntry>>(self.world_char_man + self.offsets.chr_set)?;
+        self.players_base = entries.first().address();
+        let online_players_count = ps.read::<u32>(self.misc_player_count)? as usize;
         let mut players = Vec::new();
-        for i in 0..online_players_count {
-            let offset = i * 0x38;
-            let player_ins_ptr = ps.read::<usize>(self.players_base + offset as usize)?;
-            let mut player_ins = PlayerIns::init(player_ins_ptr, ps)?;
+        for entry in entries.read(ps)?.iter().take(online_players_count) {
+            let mut player_ins = PlayerIns::init(entry.player_ins, self.offsets, ps)?;
             player_ins.refresh_data(ps)?;
             players.push(player_ins);
         }
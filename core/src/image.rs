@@ -0,0 +1,692 @@
+use crate::error::ProcessError;
+use crate::memory::{
+    MemoryBuffer, MemoryInfo, MemoryReader, MAX_USER_ADDRESS, MEM_COMMIT, MEM_IMAGE, PAGE_EXECUTE,
+    PAGE_EXECUTE_READ, PAGE_EXECUTE_READWRITE, PAGE_NOACCESS, PAGE_READONLY, PAGE_READWRITE,
+};
+use crate::process::Module;
+use anyhow::{anyhow, Result};
+use pelite::image::{
+    IMAGE_DATA_DIRECTORY, IMAGE_DIRECTORY_ENTRY_EXCEPTION, IMAGE_DIRECTORY_ENTRY_EXPORT,
+    IMAGE_DIRECTORY_ENTRY_IMPORT, IMAGE_DIRECTORY_ENTRY_TLS, IMAGE_DOS_HEADER, IMAGE_DOS_SIGNATURE,
+    IMAGE_EXPORT_DIRECTORY, IMAGE_IMPORT_DESCRIPTOR, IMAGE_NT_HEADERS64,
+    IMAGE_NT_HEADERS_SIGNATURE, IMAGE_NT_OPTIONAL_HDR64_MAGIC, IMAGE_ORDINAL_FLAG64,
+    IMAGE_SCN_MEM_EXECUTE, IMAGE_SCN_MEM_READ, IMAGE_SCN_MEM_WRITE, IMAGE_SECTION_HEADER,
+    IMAGE_TLS_DIRECTORY64,
+};
+use pelite::pe64::{Pe, PeFile};
+use std::mem;
+use std::path::Path;
+
+#[derive(Debug, Clone)]
+pub struct ImageSection {
+    pub name: String,
+    pub base: usize,
+    pub size: usize,
+    pub protect: u32,
+}
+
+// A PE file laid out the way the loader maps it: headers and sections at
+// ImageBase + RVA, zero filled up to SizeOfImage. Relocations are not applied.
+#[derive(Debug, Clone)]
+pub struct PeImage {
+    pub name: String,
+    pub image_base: usize,
+    sections: Vec<ImageSection>,
+    image: MemoryBuffer,
+}
+
+fn section_protect(characteristics: u32) -> u32 {
+    let read = characteristics & IMAGE_SCN_MEM_READ != 0;
+    let write = characteristics & IMAGE_SCN_MEM_WRITE != 0;
+    let exec = characteristics & IMAGE_SCN_MEM_EXECUTE != 0;
+    match (read, write, exec) {
+        (_, true, true) => PAGE_EXECUTE_READWRITE,
+        (true, false, true) => PAGE_EXECUTE_READ,
+        (false, false, true) => PAGE_EXECUTE,
+        (_, true, false) => PAGE_READWRITE,
+        (true, false, false) => PAGE_READONLY,
+        (false, false, false) => PAGE_NOACCESS,
+    }
+}
+
+fn align_up(value: usize, alignment: usize) -> usize {
+    match alignment {
+        0 => value,
+        _ => value.next_multiple_of(alignment),
+    }
+}
+
+impl PeImage {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<PeImage> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)?;
+        let name = path
+            .file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        PeImage::from_bytes(&name, bytes.as_slice())
+    }
+
+    pub fn from_bytes(name: &str, bytes: &[u8]) -> Result<PeImage> {
+        let pe = PeFile::from_bytes(bytes).map_err(|e| anyhow!("Invalid PE File: {}", e))?;
+        let optional_header = pe.optional_header();
+        let image_base = optional_header.ImageBase as usize;
+        let section_alignment = optional_header.SectionAlignment as usize;
+        let size_of_headers = optional_header.SizeOfHeaders as usize;
+
+        let mut image = vec![0u8; optional_header.SizeOfImage as usize];
+        let headers_len = size_of_headers.min(bytes.len()).min(image.len());
+        image[..headers_len].copy_from_slice(&bytes[..headers_len]);
+
+        let mut sections = vec![ImageSection {
+            name: String::new(),
+            base: image_base,
+            size: align_up(size_of_headers, section_alignment),
+            protect: PAGE_READONLY,
+        }];
+        for section in pe.section_headers() {
+            let rva = section.VirtualAddress as usize;
+            let raw = section.PointerToRawData as usize;
+            let raw_size = (section.SizeOfRawData as usize).min(section.VirtualSize as usize);
+            if raw_size > 0 && raw + raw_size <= bytes.len() && rva + raw_size <= image.len() {
+                image[rva..rva + raw_size].copy_from_slice(&bytes[raw..raw + raw_size]);
+            }
+            sections.push(ImageSection {
+                name: String::from_utf8_lossy(&section.Name)
+                    .trim_end_matches('\0')
+                    .to_string(),
+                base: image_base + rva,
+                size: align_up(section.VirtualSize as usize, section_alignment),
+                protect: section_protect(section.Characteristics),
+            });
+        }
+
+        Ok(PeImage {
+            name: name.to_string(),
+            image_base,
+            sections,
+            image: MemoryBuffer::new(image_base, image).with_name(name),
+        })
+    }
+
+    pub fn size(&self) -> usize {
+        self.image.size()
+    }
+
+    // The headers are reported as a section without a name
+    pub fn sections(&self) -> &[ImageSection] {
+        self.sections.as_slice()
+    }
+
+    pub fn section(&self, name: &str) -> Option<&ImageSection> {
+        self.sections.iter().find(|s| s.name == name)
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        self.image.as_slice()
+    }
+}
+
+impl MemoryReader for PeImage {
+    fn read_bytes(&self, address: usize, buf: &mut [u8]) -> Result<()> {
+        self.image.read_bytes(address, buf)
+    }
+
+    fn query_memory_info(&self, address: usize) -> Result<MemoryInfo> {
+        if address > MAX_USER_ADDRESS {
+            return Err(ProcessError::QueryMemoryFail(address).into());
+        }
+        let section = match self
+            .sections
+            .iter()
+            .find(|s| s.base <= address && address < s.base + s.size)
+        {
+            Some(section) => section,
+            None => {
+                let base = self
+                    .sections
+                    .iter()
+                    .map(|s| s.base + s.size)
+                    .filter(|end| *end <= address)
+                    .max()
+                    .unwrap_or(0);
+                let end = self
+                    .sections
+                    .iter()
+                    .map(|s| s.base)
+                    .filter(|start| *start > address)
+                    .min()
+                    .unwrap_or(MAX_USER_ADDRESS + 1);
+                return Ok(MemoryInfo::free(base, end));
+            }
+        };
+        Ok(MemoryInfo {
+            base: section.base,
+            allocation_base: self.image_base,
+            allocation_protect: PAGE_EXECUTE_READWRITE,
+            size: section.size,
+            state: MEM_COMMIT,
+            protect: section.protect,
+            mem_type: MEM_IMAGE,
+        })
+    }
+
+    fn get_module(&self, name: &str) -> Option<Module> {
+        self.image.get_module(name)
+    }
+
+    fn modules(&self) -> Vec<Module> {
+        self.image.modules()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ImageExport {
+    pub name: Option<String>,
+    pub ordinal: u16,
+    pub address: usize,
+    // "OTHER.DLL.Function" when the export points into another module
+    pub forwarder: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImageImport {
+    pub module: String,
+    pub name: Option<String>,
+    pub ordinal: Option<u16>,
+    // The IAT slot and what the loader wrote into it
+    pub iat: usize,
+    pub address: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImageTls {
+    pub start: usize,
+    pub end: usize,
+    pub index: usize,
+    pub callbacks: Vec<usize>,
+}
+
+// An entry of the exception directory (.pdata), addresses are absolute
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RuntimeFunction {
+    pub begin: usize,
+    pub end: usize,
+    pub unwind_info: usize,
+}
+
+// PE headers of a loaded module, read through any MemoryReader so it works
+// on a live process as well as on dumps and snapshots that kept the headers.
+#[derive(Debug, Clone)]
+pub struct ModuleHeaders {
+    pub base: usize,
+    pub preferred_base: usize,
+    pub size_of_image: usize,
+    pub entry_point: usize,
+    pub time_date_stamp: u32,
+    pub checksum: u32,
+    pub sections: Vec<ImageSection>,
+    pub exports: Vec<ImageExport>,
+    pub imports: Vec<ImageImport>,
+    pub tls: Option<ImageTls>,
+    pub exception: Vec<RuntimeFunction>,
+}
+
+// The whole module as it is mapped, sections that can't be read are left zeroed
+pub fn read_module_image<R: MemoryReader>(reader: &R, module: &Module) -> Vec<u8> {
+    let mut image = vec![0u8; module.size];
+    if reader.read_bytes(module.base, &mut image).is_ok() {
+        return image;
+    }
+    if let Ok(headers) = module.headers(reader) {
+        for section in &headers.sections {
+            let start = section.base - module.base;
+            let end = (start + section.size).min(image.len());
+            if start < end {
+                let _ = reader.read_bytes(section.base, &mut image[start..end]);
+            }
+        }
+    }
+    image
+}
+
+// Reads up to a NUL without crossing into a page that may not be mapped
+fn read_c_str<R: MemoryReader>(reader: &R, address: usize) -> Result<String> {
+    let mut bytes = Vec::new();
+    let mut current = address;
+    while bytes.len() < 0x1000 {
+        let mut chunk = [0u8; 0x40];
+        let len = chunk.len().min(0x1000 - (current & 0xFFF));
+        reader.read_bytes(current, &mut chunk[..len])?;
+        match chunk[..len].iter().position(|b| *b == 0) {
+            Some(end) => {
+                bytes.extend_from_slice(&chunk[..end]);
+                break;
+            }
+            None => bytes.extend_from_slice(&chunk[..len]),
+        }
+        current += len;
+    }
+    Ok(String::from_utf8_lossy(&bytes).to_string())
+}
+
+fn read_vec<R: MemoryReader, T: Copy + Default>(
+    reader: &R,
+    address: usize,
+    count: usize,
+) -> Result<Vec<T>> {
+    let mut items = vec![T::default(); count];
+    if count > 0 {
+        reader.read_ptr(items.as_mut_ptr(), address, count)?;
+    }
+    Ok(items)
+}
+
+impl ModuleHeaders {
+    pub fn read<R: MemoryReader>(reader: &R, base: usize) -> Result<ModuleHeaders> {
+        let dos = reader.read::<IMAGE_DOS_HEADER>(base)?;
+        if dos.e_magic != IMAGE_DOS_SIGNATURE {
+            return Err(anyhow!("Invalid PE Header at {:X}", base));
+        }
+        let nt_address = base + dos.e_lfanew as usize;
+        let nt = reader.read::<IMAGE_NT_HEADERS64>(nt_address)?;
+        if nt.Signature != IMAGE_NT_HEADERS_SIGNATURE
+            || nt.OptionalHeader.Magic != IMAGE_NT_OPTIONAL_HDR64_MAGIC
+        {
+            return Err(anyhow!("Invalid PE Header at {:X}", base));
+        }
+        let optional_header = nt.OptionalHeader;
+        let size_of_image = optional_header.SizeOfImage as usize;
+
+        let mut headers = ModuleHeaders {
+            base,
+            preferred_base: optional_header.ImageBase as usize,
+            size_of_image,
+            entry_point: base + optional_header.AddressOfEntryPoint as usize,
+            time_date_stamp: nt.FileHeader.TimeDateStamp,
+            checksum: optional_header.CheckSum,
+            sections: Vec::new(),
+            exports: Vec::new(),
+            imports: Vec::new(),
+            tls: None,
+            exception: Vec::new(),
+        };
+
+        let section_address = nt_address
+            + mem::size_of::<u32>()
+            + mem::size_of_val(&nt.FileHeader)
+            + nt.FileHeader.SizeOfOptionalHeader as usize;
+        for i in 0..nt.FileHeader.NumberOfSections as usize {
+            let section = reader.read::<IMAGE_SECTION_HEADER>(
+                section_address + i * mem::size_of::<IMAGE_SECTION_HEADER>(),
+            )?;
+            headers.sections.push(ImageSection {
+                name: String::from_utf8_lossy(&section.Name)
+                    .trim_end_matches('\0')
+                    .to_string(),
+                base: base + section.VirtualAddress as usize,
+                size: align_up(
+                    section.VirtualSize as usize,
+                    optional_header.SectionAlignment as usize,
+                ),
+                protect: section_protect(section.Characteristics),
+            });
+        }
+
+        // Directories outside the image are treated as missing
+        let directory_address = nt_address + mem::size_of::<IMAGE_NT_HEADERS64>();
+        let directory_count = optional_header.NumberOfRvaAndSizes.min(16) as usize;
+        let directory = |index: usize| -> Option<(usize, usize)> {
+            if index >= directory_count {
+                return None;
+            }
+            let entry = reader
+                .read::<IMAGE_DATA_DIRECTORY>(
+                    directory_address + index * mem::size_of::<IMAGE_DATA_DIRECTORY>(),
+                )
+                .ok()?;
+            let (rva, size) = (entry.VirtualAddress as usize, entry.Size as usize);
+            if rva == 0 || size == 0 || rva + size > size_of_image {
+                return None;
+            }
+            Some((rva, size))
+        };
+
+        if let Some((rva, size)) = directory(IMAGE_DIRECTORY_ENTRY_EXPORT) {
+            headers.exports = headers.read_exports(reader, rva, size)?;
+        }
+        if let Some((rva, _)) = directory(IMAGE_DIRECTORY_ENTRY_IMPORT) {
+            headers.imports = headers.read_imports(reader, rva)?;
+        }
+        if let Some((rva, _)) = directory(IMAGE_DIRECTORY_ENTRY_TLS) {
+            let tls = reader.read::<IMAGE_TLS_DIRECTORY64>(base + rva)?;
+            let mut callbacks = Vec::new();
+            if tls.AddressOfCallBacks != 0 {
+                let mut address = tls.AddressOfCallBacks as usize;
+                while let Ok(callback) = reader.read::<usize>(address) {
+                    if callback == 0 || callbacks.len() >= 0x100 {
+                        break;
+                    }
+                    callbacks.push(callback);
+                    address += mem::size_of::<usize>();
+                }
+            }
+            headers.tls = Some(ImageTls {
+                start: tls.StartAddressOfRawData as usize,
+                end: tls.EndAddressOfRawData as usize,
+                index: tls.AddressOfIndex as usize,
+                callbacks,
+            });
+        }
+        if let Some((rva, size)) = directory(IMAGE_DIRECTORY_ENTRY_EXCEPTION) {
+            let entries: Vec<u32> = read_vec(reader, base + rva, size / 12 * 3)?;
+            headers.exception = entries
+                .chunks_exact(3)
+                .map(|e| RuntimeFunction {
+                    begin: base + e[0] as usize,
+                    end: base + e[1] as usize,
+                    unwind_info: base + e[2] as usize,
+                })
+                .collect();
+        }
+        Ok(headers)
+    }
+
+    fn check_rva(&self, rva: usize, len: usize) -> Result<usize> {
+        match rva.checked_add(len) {
+            Some(end) if end <= self.size_of_image => Ok(self.base + rva),
+            _ => Err(anyhow!("Invalid PE Header at {:X}", self.base)),
+        }
+    }
+
+    fn read_exports<R: MemoryReader>(
+        &self,
+        reader: &R,
+        rva: usize,
+        size: usize,
+    ) -> Result<Vec<ImageExport>> {
+        let dir = reader.read::<IMAGE_EXPORT_DIRECTORY>(self.base + rva)?;
+        let function_count = dir.NumberOfFunctions as usize;
+        let name_count = dir.NumberOfNames as usize;
+        let functions: Vec<u32> = read_vec(
+            reader,
+            self.check_rva(dir.AddressOfFunctions as usize, function_count * 4)?,
+            function_count,
+        )?;
+        let names: Vec<u32> = read_vec(
+            reader,
+            self.check_rva(dir.AddressOfNames as usize, name_count * 4)?,
+            name_count,
+        )?;
+        let ordinals: Vec<u16> = read_vec(
+            reader,
+            self.check_rva(dir.AddressOfNameOrdinals as usize, name_count * 2)?,
+            name_count,
+        )?;
+
+        let mut exports = Vec::new();
+        for (index, function) in functions.iter().enumerate() {
+            let function = *function as usize;
+            if function == 0 {
+                continue;
+            }
+            let name = match ordinals.iter().position(|o| *o as usize == index) {
+                Some(i) => Some(read_c_str(reader, self.base + names[i] as usize)?),
+                None => None,
+            };
+            let forwarder = if function >= rva && function < rva + size {
+                Some(read_c_str(reader, self.base + function)?)
+            } else {
+                None
+            };
+            exports.push(ImageExport {
+                name,
+                ordinal: (dir.Base as usize + index) as u16,
+                address: self.base + function,
+                forwarder,
+            });
+        }
+        Ok(exports)
+    }
+
+    fn read_imports<R: MemoryReader>(&self, reader: &R, rva: usize) -> Result<Vec<ImageImport>> {
+        let mut imports = Vec::new();
+        let mut descriptor_address = self.base + rva;
+        loop {
+            let descriptor = reader.read::<IMAGE_IMPORT_DESCRIPTOR>(descriptor_address)?;
+            if descriptor.is_null() {
+                break;
+            }
+            let module = read_c_str(reader, self.base + descriptor.Name as usize)?;
+            let iat = self.check_rva(descriptor.FirstThunk as usize, 8)?;
+            // Bound or already patched IATs lose their names, the lookup table keeps them
+            let lookup = match descriptor.OriginalFirstThunk {
+                0 => iat,
+                thunk => self.check_rva(thunk as usize, 8)?,
+            };
+            for index in 0.. {
+                let thunk = reader.read::<u64>(lookup + index * 8)?;
+                if thunk == 0 {
+                    break;
+                }
+                // Without a lookup table a loaded IAT holds addresses, not
+                // name RVAs, so the import stays unnamed
+                let hint_name = match lookup == iat {
+                    true => None,
+                    false => self.check_rva(thunk as usize, 2).ok(),
+                };
+                let (name, ordinal) = if lookup != iat && thunk & IMAGE_ORDINAL_FLAG64 != 0 {
+                    (None, Some(thunk as u16))
+                } else if let Some(hint_name) = hint_name {
+                    (Some(read_c_str(reader, hint_name + 2)?), None)
+                } else {
+                    (None, None)
+                };
+                imports.push(ImageImport {
+                    module: module.clone(),
+                    name,
+                    ordinal,
+                    iat: iat + index * 8,
+                    address: reader.read::<usize>(iat + index * 8)?,
+                });
+            }
+            descriptor_address += mem::size_of::<IMAGE_IMPORT_DESCRIPTOR>();
+        }
+        Ok(imports)
+    }
+
+    pub fn section(&self, name: &str) -> Option<&ImageSection> {
+        self.sections.iter().find(|s| s.name == name)
+    }
+
+    pub fn export(&self, name: &str) -> Option<&ImageExport> {
+        self.exports
+            .iter()
+            .find(|e| e.name.as_ref().map(|n| n == name).unwrap_or(false))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::image::{PeImage, RuntimeFunction};
+    use crate::memory::{MemoryReader, RegionFilter, PAGE_EXECUTE_READ, PAGE_READWRITE};
+    use pelite::image::{
+        IMAGE_DIRECTORY_ENTRY_EXCEPTION, IMAGE_DIRECTORY_ENTRY_EXPORT, IMAGE_DIRECTORY_ENTRY_IMPORT,
+    };
+
+    pub const IMAGE_BASE: u64 = 0x140000000;
+
+    // Minimal PE32+ with one section per entry, sections are placed at
+    // 0x1000, 0x2000, ... and their data at FileAlignment in the file.
+    pub fn build_pe(sections: &[(&str, &[u8], u32)]) -> Vec<u8> {
+        let mut file = vec![0u8; 0x400];
+        file[0..2].copy_from_slice(b"MZ");
+        file[0x3C..0x40].copy_from_slice(&0x40u32.to_le_bytes());
+        file[0x40..0x44].copy_from_slice(b"PE\0\0");
+        file[0x44..0x46].copy_from_slice(&0x8664u16.to_le_bytes());
+        file[0x46..0x48].copy_from_slice(&(sections.len() as u16).to_le_bytes());
+        file[0x54..0x56].copy_from_slice(&0xF0u16.to_le_bytes());
+        file[0x56..0x58].copy_from_slice(&0x22u16.to_le_bytes());
+        let opt = 0x58;
+        file[opt..opt + 2].copy_from_slice(&0x20Bu16.to_le_bytes());
+        file[opt + 24..opt + 32].copy_from_slice(&IMAGE_BASE.to_le_bytes());
+        file[opt + 32..opt + 36].copy_from_slice(&0x1000u32.to_le_bytes());
+        file[opt + 36..opt + 40].copy_from_slice(&0x200u32.to_le_bytes());
+        let size_of_image = 0x1000 * (sections.len() as u32 + 1);
+        file[opt + 56..opt + 60].copy_from_slice(&size_of_image.to_le_bytes());
+        file[opt + 60..opt + 64].copy_from_slice(&0x400u32.to_le_bytes());
+        file[opt + 108..opt + 112].copy_from_slice(&16u32.to_le_bytes());
+
+        for (i, (name, data, characteristics)) in sections.iter().enumerate() {
+            assert!(data.len() <= 0x1000);
+            let header = opt + 0xF0 + i * 40;
+            let raw = file.len() as u32;
+            let raw_size = data.len().next_multiple_of(0x200) as u32;
+            let rva = 0x1000 * (i as u32 + 1);
+            file[header..header + name.len()].copy_from_slice(name.as_bytes());
+            file[header + 8..header + 12].copy_from_slice(&(data.len() as u32).to_le_bytes());
+            file[header + 12..header + 16].copy_from_slice(&rva.to_le_bytes());
+            file[header + 16..header + 20].copy_from_slice(&raw_size.to_le_bytes());
+            file[header + 20..header + 24].copy_from_slice(&raw.to_le_bytes());
+            file[header + 36..header + 40].copy_from_slice(&characteristics.to_le_bytes());
+            file.extend_from_slice(data);
+            file.resize((raw + raw_size) as usize, 0);
+        }
+        file
+    }
+
+    pub fn put(buf: &mut [u8], offset: usize, bytes: &[u8]) {
+        buf[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    // Points data directory `index` of a build_pe file at `rva`
+    pub fn set_directory(file: &mut [u8], index: usize, rva: u32, size: u32) {
+        let entry = 0x58 + 112 + index * 8;
+        put(file, entry, &rva.to_le_bytes());
+        put(file, entry + 4, &size.to_le_bytes());
+    }
+
+    #[test]
+    pub fn test_pe_image_sections() {
+        let code = [0x48u8, 0x8B, 0x05, 0x78, 0x56, 0x34, 0x12, 0xC3];
+        let data = 0xDEADBEEFu32.to_le_bytes();
+        let file = build_pe(&[(".text", &code, 0x60000020), (".data", &data, 0xC0000040)]);
+        let image = PeImage::from_bytes("DarkSoulsIII.exe", file.as_slice()).unwrap();
+
+        assert_eq!(IMAGE_BASE as usize, image.image_base);
+        assert_eq!(0x3000, image.size());
+        assert_eq!(0x8B48, image.read::<u16>(0x140001000).unwrap());
+        assert_eq!(0xDEADBEEF, image.read::<u32>(0x140002000).unwrap());
+        assert_eq!(0, image.read::<u32>(0x140002004).unwrap());
+
+        let text = image.section(".text").unwrap();
+        assert_eq!(PAGE_EXECUTE_READ, text.protect);
+        let info = image.query_memory_info(0x140002008).unwrap();
+        assert_eq!(0x140002000, info.base);
+        assert_eq!(PAGE_READWRITE, info.protect);
+
+        let module = image.get_module("DarkSoulsIII.exe").unwrap();
+        assert_eq!(0x140000000, module.base);
+        assert_eq!(0x3000, module.size);
+    }
+
+    #[test]
+    pub fn test_pe_image_regions() {
+        let file = build_pe(&[(".text", &[0xC3], 0x60000020), (".data", &[0], 0xC0000040)]);
+        let image = PeImage::from_bytes("DarkSoulsIII.exe", file.as_slice()).unwrap();
+
+        assert_eq!(3, image.regions(RegionFilter::default()).count());
+        let writable: Vec<_> = image
+            .regions(RegionFilter {
+                writable: true,
+                image: true,
+                ..Default::default()
+            })
+            .collect();
+        assert_eq!(1, writable.len());
+        assert_eq!(0x140002000, writable[0].info.base);
+        assert_eq!(
+            "DarkSoulsIII.exe",
+            writable[0].module.as_ref().unwrap().name
+        );
+        let executable = image.regions(RegionFilter {
+            executable: true,
+            private: true,
+            ..Default::default()
+        });
+        assert_eq!(0, executable.count());
+    }
+
+    #[test]
+    pub fn test_module_headers() {
+        let mut rdata = vec![0u8; 0x120];
+        // Export directory with one named function
+        put(&mut rdata, 0x0C, &0x2080u32.to_le_bytes());
+        put(&mut rdata, 0x10, &1u32.to_le_bytes());
+        put(&mut rdata, 0x14, &1u32.to_le_bytes());
+        put(&mut rdata, 0x18, &1u32.to_le_bytes());
+        put(&mut rdata, 0x1C, &0x2028u32.to_le_bytes());
+        put(&mut rdata, 0x20, &0x202Cu32.to_le_bytes());
+        put(&mut rdata, 0x24, &0x2030u32.to_le_bytes());
+        put(&mut rdata, 0x28, &0x1000u32.to_le_bytes());
+        put(&mut rdata, 0x2C, &0x2090u32.to_le_bytes());
+        // Import descriptor for KERNEL32.dll!Sleep
+        put(&mut rdata, 0x40, &0x20C0u32.to_le_bytes());
+        put(&mut rdata, 0x4C, &0x20A0u32.to_le_bytes());
+        put(&mut rdata, 0x50, &0x20D0u32.to_le_bytes());
+        put(&mut rdata, 0x80, b"game.dll");
+        put(&mut rdata, 0x90, b"GetWorldChrMan");
+        put(&mut rdata, 0xA0, b"KERNEL32.dll");
+        put(&mut rdata, 0xB2, b"Sleep");
+        put(&mut rdata, 0xC0, &0x20B0u64.to_le_bytes());
+        put(&mut rdata, 0xD0, &0x7FF812345678u64.to_le_bytes());
+        // USER32.dll without a lookup table, its IAT is already bound
+        put(&mut rdata, 0x60, &0x2100u32.to_le_bytes());
+        put(&mut rdata, 0x64, &0x2110u32.to_le_bytes());
+        put(&mut rdata, 0x100, b"USER32.dll");
+        put(&mut rdata, 0x110, &0x7FF8AAAA0000u64.to_le_bytes());
+        // One RUNTIME_FUNCTION
+        put(&mut rdata, 0xE0, &0x1000u32.to_le_bytes());
+        put(&mut rdata, 0xE4, &0x1001u32.to_le_bytes());
+        put(&mut rdata, 0xE8, &0x20F0u32.to_le_bytes());
+
+        let mut file = build_pe(&[
+            (".text", &[0xC3], 0x60000020),
+            (".rdata", &rdata, 0x40000040),
+        ]);
+        put(&mut file, 0x48, &0x5B1F3C2Au32.to_le_bytes());
+        set_directory(&mut file, IMAGE_DIRECTORY_ENTRY_EXPORT, 0x2000, 0x28);
+        set_directory(&mut file, IMAGE_DIRECTORY_ENTRY_IMPORT, 0x2040, 0x3C);
+        set_directory(&mut file, IMAGE_DIRECTORY_ENTRY_EXCEPTION, 0x20E0, 12);
+        let image = PeImage::from_bytes("game.dll", file.as_slice()).unwrap();
+        let headers = image
+            .get_module("game.dll")
+            .unwrap()
+            .headers(&image)
+            .unwrap();
+
+        assert_eq!(0x5B1F3C2A, headers.time_date_stamp);
+        assert_eq!(0x140002000, headers.section(".rdata").unwrap().base);
+        let export = headers.export("GetWorldChrMan").unwrap();
+        assert_eq!(0x140001000, export.address);
+        assert_eq!(1, export.ordinal);
+        assert_eq!(2, headers.imports.len());
+        assert_eq!("KERNEL32.dll", headers.imports[0].module);
+        assert_eq!(Some("Sleep".to_string()), headers.imports[0].name);
+        assert_eq!(0x1400020D0, headers.imports[0].iat);
+        assert_eq!(0x7FF812345678, headers.imports[0].address);
+        assert_eq!("USER32.dll", headers.imports[1].module);
+        assert_eq!(None, headers.imports[1].name);
+        assert_eq!(None, headers.imports[1].ordinal);
+        assert_eq!(0x7FF8AAAA0000, headers.imports[1].address);
+        assert!(headers.tls.is_none());
+        assert_eq!(
+            vec![RuntimeFunction {
+                begin: 0x140001000,
+                end: 0x140001001,
+                unwind_info: 0x1400020F0,
+            }],
+            headers.exception
+        );
+    }
+}
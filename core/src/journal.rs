@@ -0,0 +1,222 @@
+use crate::memory::{MemoryInfo, MemoryReader, MemoryWriter};
+use crate::process::Module;
+use anyhow::Result;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct JournalEntry {
+    pub address: usize,
+    pub original: Vec<u8>,
+    pub written: Vec<u8>,
+}
+
+// Records the original bytes of every write so edits can be reverted, last
+// write first. With auto unprotect, writes to read-only and code pages go
+// through write_bytes_unprotected.
+#[derive(Debug)]
+pub struct WriteJournal<W: MemoryWriter> {
+    inner: W,
+    auto_unprotect: bool,
+    entries: Mutex<Vec<JournalEntry>>,
+}
+
+impl<W: MemoryWriter> WriteJournal<W> {
+    pub fn new(inner: W) -> WriteJournal<W> {
+        WriteJournal {
+            inner,
+            auto_unprotect: false,
+            entries: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn with_auto_unprotect(mut self, enabled: bool) -> WriteJournal<W> {
+        self.auto_unprotect = enabled;
+        self
+    }
+
+    pub fn inner(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn entries(&self) -> Vec<JournalEntry> {
+        self.entries.lock().unwrap().clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Forgets every entry, the writes stay
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    fn write_raw(&self, address: usize, buf: &[u8]) -> Result<()> {
+        if self.auto_unprotect {
+            self.inner.write_bytes_unprotected(address, buf)
+        } else {
+            self.inner.write_bytes(address, buf)
+        }
+    }
+
+    // Reverts the last write, None when there is nothing left
+    pub fn undo(&self) -> Result<Option<JournalEntry>> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = match entries.last() {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        self.write_raw(entry.address, &entry.original)?;
+        Ok(entries.pop())
+    }
+
+    // Reverts writes until `len` entries are left, pass len() to get a mark
+    pub fn rollback_to(&self, len: usize) -> Result<()> {
+        while self.len() > len {
+            self.undo()?;
+        }
+        Ok(())
+    }
+
+    pub fn rollback(&self) -> Result<()> {
+        self.rollback_to(0)
+    }
+}
+
+impl<W: MemoryWriter> MemoryReader for WriteJournal<W> {
+    fn read_bytes(&self, address: usize, buf: &mut [u8]) -> Result<()> {
+        self.inner.read_bytes(address, buf)
+    }
+
+    fn query_memory_info(&self, address: usize) -> Result<MemoryInfo> {
+        self.inner.query_memory_info(address)
+    }
+
+    fn get_module(&self, name: &str) -> Option<Module> {
+        self.inner.get_module(name)
+    }
+
+    fn modules(&self) -> Vec<Module> {
+        self.inner.modules()
+    }
+
+    fn memory_regions(&self) -> Vec<MemoryInfo> {
+        self.inner.memory_regions()
+    }
+}
+
+impl<W: MemoryWriter> MemoryWriter for WriteJournal<W> {
+    fn write_bytes(&self, address: usize, buf: &[u8]) -> Result<()> {
+        let mut original = vec![0u8; buf.len()];
+        self.inner.read_bytes(address, &mut original)?;
+        self.write_raw(address, buf)?;
+        self.entries.lock().unwrap().push(JournalEntry {
+            address,
+            original,
+            written: buf.to_vec(),
+        });
+        Ok(())
+    }
+
+    fn protect(&self, address: usize, size: usize, protection: u32) -> Result<u32> {
+        self.inner.protect(address, size, protection)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::ProcessError;
+    use crate::journal::WriteJournal;
+    use crate::memory::{
+        MemoryInfo, MemoryReader, MemoryWriter, MEM_COMMIT, MEM_IMAGE, PAGE_EXECUTE_READ,
+        PAGE_EXECUTE_READWRITE, PAGE_READWRITE,
+    };
+    use crate::process::Module;
+    use anyhow::Result;
+    use std::cell::{Cell, RefCell};
+
+    // One page of memory that honours its protection
+    struct Page {
+        data: RefCell<Vec<u8>>,
+        protect: Cell<u32>,
+    }
+
+    impl MemoryReader for Page {
+        fn read_bytes(&self, address: usize, buf: &mut [u8]) -> Result<()> {
+            let data = self.data.borrow();
+            let slice = data
+                .get(address..address + buf.len())
+                .ok_or(ProcessError::ReadMemoryFail(address))?;
+            buf.copy_from_slice(slice);
+            Ok(())
+        }
+
+        fn query_memory_info(&self, _address: usize) -> Result<MemoryInfo> {
+            Ok(MemoryInfo {
+                size: self.data.borrow().len(),
+                state: MEM_COMMIT,
+                protect: self.protect.get(),
+                mem_type: MEM_IMAGE,
+                ..MemoryInfo::default()
+            })
+        }
+
+        fn get_module(&self, _name: &str) -> Option<Module> {
+            None
+        }
+
+        fn modules(&self) -> Vec<Module> {
+            Vec::new()
+        }
+    }
+
+    impl MemoryWriter for Page {
+        fn write_bytes(&self, address: usize, buf: &[u8]) -> Result<()> {
+            if self.protect.get() != PAGE_READWRITE && self.protect.get() != PAGE_EXECUTE_READWRITE
+            {
+                return Err(ProcessError::WriteMemoryFail(address).into());
+            }
+            self.data.borrow_mut()[address..address + buf.len()].copy_from_slice(buf);
+            Ok(())
+        }
+
+        fn protect(&self, _address: usize, _size: usize, protection: u32) -> Result<u32> {
+            Ok(self.protect.replace(protection))
+        }
+    }
+
+    #[test]
+    pub fn test_write_journal() {
+        let page = Page {
+            data: RefCell::new(vec![0x90; 0x1000]),
+            protect: Cell::new(PAGE_EXECUTE_READ),
+        };
+
+        let journal = WriteJournal::new(page);
+        assert!(journal.write::<u8>(0x10, &0xCC).is_err());
+        assert!(journal.is_empty());
+
+        let journal = WriteJournal::new(journal.inner).with_auto_unprotect(true);
+        journal.write::<u8>(0x10, &0xCC).unwrap();
+        journal.write::<u32>(0x10, &0xDEADBEEF).unwrap();
+        assert_eq!(PAGE_EXECUTE_READ, journal.inner().protect.get());
+        assert_eq!(0xDEADBEEF, journal.read::<u32>(0x10).unwrap());
+
+        let mark = journal.len();
+        journal.write_bytes(0x100, &[1, 2, 3]).unwrap();
+        journal.rollback_to(mark).unwrap();
+        assert_eq!([0x90, 0x90, 0x90], journal.read::<[u8; 3]>(0x100).unwrap());
+
+        let entry = journal.undo().unwrap().unwrap();
+        assert_eq!(vec![0xCC, 0x90, 0x90, 0x90], entry.original);
+        assert_eq!(0xCC, journal.read::<u8>(0x10).unwrap());
+        journal.rollback().unwrap();
+        assert_eq!(0x90909090, journal.read::<u32>(0x10).unwrap());
+        assert!(journal.undo().unwrap().is_none());
+        assert_eq!(PAGE_EXECUTE_READ, journal.inner().protect.get());
+    }
+}
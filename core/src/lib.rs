@@ -1,10 +1,33 @@
+pub mod alloc;
+pub mod cache;
+pub mod capture;
+pub mod definitions;
+pub mod disasm;
 pub mod error;
 pub mod game;
+pub mod image;
+pub mod journal;
+pub mod memory;
+pub mod minidump;
 pub mod misc;
+pub mod msvc;
+#[cfg(windows)]
 pub mod overlay;
 pub mod pattern;
+pub mod pattern_scan;
+pub mod pointer;
+pub mod pointer_scan;
 pub mod process;
+pub mod remote;
+pub mod scan_executor;
+pub mod signature;
+pub mod signature_set;
+pub mod snapshot;
+#[cfg(windows)]
 pub mod sync;
+pub mod value_scan;
+#[cfg(windows)]
 pub mod window;
+pub mod xref;
 
 pub extern crate hex;
@@ -0,0 +1,386 @@
+use crate::error::ProcessError;
+use crate::process::Module;
+use anyhow::Result;
+use std::mem;
+use std::sync::Arc;
+
+// Values of MEMORY_BASIC_INFORMATION, every backend reports regions in Win32 terms
+pub const MEM_COMMIT: u32 = 0x1000;
+pub const MEM_RESERVE: u32 = 0x2000;
+pub const MEM_FREE: u32 = 0x10000;
+pub const MEM_PRIVATE: u32 = 0x20000;
+pub const MEM_MAPPED: u32 = 0x40000;
+pub const MEM_IMAGE: u32 = 0x1000000;
+
+pub const PAGE_NOACCESS: u32 = 0x01;
+pub const PAGE_READONLY: u32 = 0x02;
+pub const PAGE_READWRITE: u32 = 0x04;
+pub const PAGE_WRITECOPY: u32 = 0x08;
+pub const PAGE_EXECUTE: u32 = 0x10;
+pub const PAGE_EXECUTE_READ: u32 = 0x20;
+pub const PAGE_EXECUTE_READWRITE: u32 = 0x40;
+pub const PAGE_EXECUTE_WRITECOPY: u32 = 0x80;
+pub const PAGE_GUARD: u32 = 0x100;
+
+const PAGE_WRITABLE: u32 =
+    PAGE_READWRITE | PAGE_WRITECOPY | PAGE_EXECUTE_READWRITE | PAGE_EXECUTE_WRITECOPY;
+pub(crate) const PAGE_EXECUTABLE: u32 =
+    PAGE_EXECUTE | PAGE_EXECUTE_READ | PAGE_EXECUTE_READWRITE | PAGE_EXECUTE_WRITECOPY;
+
+pub const MAX_USER_ADDRESS: usize = 0x7FFF_FFFF_FFFF;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct MemoryInfo {
+    pub base: usize,
+    pub allocation_base: usize,
+    pub allocation_protect: u32,
+    pub size: usize,
+    pub state: u32,
+    pub protect: u32,
+    pub mem_type: u32,
+}
+
+impl MemoryInfo {
+    pub fn free(base: usize, end: usize) -> MemoryInfo {
+        MemoryInfo {
+            base,
+            size: end - base,
+            state: MEM_FREE,
+            protect: PAGE_NOACCESS,
+            ..MemoryInfo::default()
+        }
+    }
+
+    pub fn end(&self) -> usize {
+        self.base + self.size
+    }
+
+    pub fn is_readable(&self) -> bool {
+        self.state == MEM_COMMIT && self.protect & (PAGE_NOACCESS | PAGE_GUARD) == 0
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.is_readable() && self.protect & PAGE_WRITABLE != 0
+    }
+
+    pub fn is_executable(&self) -> bool {
+        self.is_readable() && self.protect & PAGE_EXECUTABLE != 0
+    }
+}
+
+// Requirements a region has to meet, unset fields accept anything
+#[derive(Debug, Copy, Clone, Default)]
+pub struct RegionFilter {
+    pub readable: bool,
+    pub writable: bool,
+    pub executable: bool,
+    pub image: bool,
+    pub private: bool,
+}
+
+impl RegionFilter {
+    pub fn matches(&self, info: &MemoryInfo) -> bool {
+        info.state == MEM_COMMIT
+            && (!self.readable || info.is_readable())
+            && (!self.writable || info.is_writable())
+            && (!self.executable || info.is_executable())
+            && (!self.image || info.mem_type == MEM_IMAGE)
+            && (!self.private || info.mem_type == MEM_PRIVATE)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Region {
+    pub info: MemoryInfo,
+    pub module: Option<Module>,
+}
+
+pub struct Regions {
+    infos: std::vec::IntoIter<MemoryInfo>,
+    modules: Vec<Module>,
+    filter: RegionFilter,
+}
+
+impl Iterator for Regions {
+    type Item = Region;
+
+    fn next(&mut self) -> Option<Region> {
+        let filter = self.filter;
+        let info = self.infos.find(|info| filter.matches(info))?;
+        let module = self
+            .modules
+            .iter()
+            .find(|m| m.base <= info.base && info.base < m.base + m.size)
+            .cloned();
+        Some(Region { info, module })
+    }
+}
+
+pub trait MemoryReader {
+    fn read_bytes(&self, address: usize, buf: &mut [u8]) -> Result<()>;
+
+    fn query_memory_info(&self, address: usize) -> Result<MemoryInfo>;
+
+    fn get_module(&self, name: &str) -> Option<Module>;
+
+    fn modules(&self) -> Vec<Module>;
+
+    // Every region from 0 to MAX_USER_ADDRESS, free and reserved ones included
+    fn memory_regions(&self) -> Vec<MemoryInfo> {
+        let mut regions = Vec::new();
+        let mut address = 0;
+        while address <= MAX_USER_ADDRESS {
+            let info = match self.query_memory_info(address) {
+                Ok(info) if info.end() > address => info,
+                _ => break,
+            };
+            address = info.end();
+            regions.push(info);
+        }
+        regions
+    }
+
+    // Committed regions matching `filter`, with the module they belong to
+    fn regions(&self, filter: RegionFilter) -> Regions {
+        Regions {
+            infos: self.memory_regions().into_iter(),
+            modules: self.modules(),
+            filter,
+        }
+    }
+
+    fn read<T: Copy>(&self, address: usize) -> Result<T>
+    where
+        Self: Sized,
+    {
+        let mut buffer = unsafe { mem::zeroed::<T>() };
+        self.read_ptr(&mut buffer as *mut T, address, 1)?;
+        Ok(buffer)
+    }
+
+    fn read_ptr<T: Copy>(&self, buf: *mut T, address: usize, count: usize) -> Result<()>
+    where
+        Self: Sized,
+    {
+        let bytes =
+            unsafe { std::slice::from_raw_parts_mut(buf as *mut u8, mem::size_of::<T>() * count) };
+        self.read_bytes(address, bytes)
+    }
+
+    fn read_utf8_str(&self, address: usize, max_length: usize, filter: &[char]) -> Result<String> {
+        let mut buf = vec![0u8; max_length];
+        self.read_bytes(address, buf.as_mut_slice())?;
+        let mut str = String::with_capacity(max_length);
+        'label: for b in buf {
+            let c = b as char;
+            if c == '\0' {
+                break;
+            }
+            for f in filter {
+                if c == *f {
+                    break 'label;
+                }
+            }
+            str.push(c);
+        }
+        Ok(str)
+    }
+}
+
+pub trait MemoryWriter: MemoryReader {
+    fn write_bytes(&self, address: usize, buf: &[u8]) -> Result<()>;
+
+    // Changes the protection of every page in the range and returns the old
+    // protection of the first one. Not every backend can do this.
+    fn protect(&self, address: usize, _size: usize, _protection: u32) -> Result<u32> {
+        Err(ProcessError::ProtectMemoryFail(address).into())
+    }
+
+    fn write<T: Copy>(&self, address: usize, buf: &T) -> Result<()>
+    where
+        Self: Sized,
+    {
+        let bytes = unsafe {
+            std::slice::from_raw_parts(buf as *const T as *const u8, mem::size_of::<T>())
+        };
+        self.write_bytes(address, bytes)
+    }
+
+    // Like write_bytes, but makes read-only and code pages writable first and
+    // restores their protection afterwards
+    fn write_bytes_unprotected(&self, address: usize, buf: &[u8]) -> Result<()> {
+        if self.write_bytes(address, buf).is_ok() {
+            return Ok(());
+        }
+        let end = address
+            .checked_add(buf.len())
+            .ok_or(ProcessError::WriteMemoryFail(address))?;
+        let mut changed = Vec::new();
+        let mut current = address;
+        let mut result = Ok(());
+        while current < end {
+            let info = match self.query_memory_info(current) {
+                Ok(info) if info.state == MEM_COMMIT && info.end() > current => info,
+                _ => {
+                    result = Err(ProcessError::WriteMemoryFail(current).into());
+                    break;
+                }
+            };
+            let size = info.end().min(end) - current;
+            if !info.is_writable() {
+                let protection = if info.is_executable() {
+                    PAGE_EXECUTE_READWRITE
+                } else {
+                    PAGE_READWRITE
+                };
+                match self.protect(current, size, protection) {
+                    Ok(old) => changed.push((current, size, old)),
+                    Err(e) => {
+                        result = Err(e);
+                        break;
+                    }
+                }
+            }
+            current += size;
+        }
+        if result.is_ok() {
+            result = self.write_bytes(address, buf);
+        }
+        // Every region is restored even when one fails, the write error wins
+        for (address, size, old) in changed {
+            if let Err(e) = self.protect(address, size, old) {
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+        result
+    }
+}
+
+pub trait MemoryAllocator: MemoryWriter {
+    // Reserves and commits `size` bytes at `address`, anywhere when None
+    fn alloc(&self, address: Option<usize>, size: usize, protection: u32) -> Result<usize>;
+
+    fn free(&self, address: usize) -> Result<()>;
+}
+
+// A block of memory copied out of some other backend, mapped at `base`.
+// Cheap to clone, so it can be handed to worker threads or used as a mock in tests.
+#[derive(Debug, Clone)]
+pub struct MemoryBuffer {
+    pub base: usize,
+    name: String,
+    data: Arc<Vec<u8>>,
+}
+
+impl MemoryBuffer {
+    pub fn new(base: usize, data: Vec<u8>) -> MemoryBuffer {
+        Self::from_arc(base, Arc::new(data))
+    }
+
+    pub fn from_arc(base: usize, data: Arc<Vec<u8>>) -> MemoryBuffer {
+        MemoryBuffer {
+            base,
+            name: String::new(),
+            data,
+        }
+    }
+
+    pub fn with_name(mut self, name: &str) -> MemoryBuffer {
+        self.name = name.to_string();
+        self
+    }
+
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        self.data.as_slice()
+    }
+}
+
+impl MemoryReader for MemoryBuffer {
+    fn read_bytes(&self, address: usize, buf: &mut [u8]) -> Result<()> {
+        let offset = address
+            .checked_sub(self.base)
+            .ok_or(ProcessError::ReadMemoryFail(address))?;
+        let end = offset
+            .checked_add(buf.len())
+            .ok_or(ProcessError::ReadMemoryFail(address))?;
+        if end > self.data.len() {
+            return Err(ProcessError::ReadMemoryFail(address).into());
+        }
+        buf.copy_from_slice(&self.data[offset..end]);
+        Ok(())
+    }
+
+    fn query_memory_info(&self, address: usize) -> Result<MemoryInfo> {
+        let end = self.base + self.data.len();
+        if address > MAX_USER_ADDRESS {
+            return Err(ProcessError::QueryMemoryFail(address).into());
+        }
+        if address < self.base {
+            return Ok(MemoryInfo::free(0, self.base));
+        }
+        if address >= end {
+            return Ok(MemoryInfo::free(end, MAX_USER_ADDRESS + 1));
+        }
+        Ok(MemoryInfo {
+            base: self.base,
+            allocation_base: self.base,
+            allocation_protect: PAGE_READONLY,
+            size: self.data.len(),
+            state: MEM_COMMIT,
+            protect: PAGE_READONLY,
+            mem_type: MEM_PRIVATE,
+        })
+    }
+
+    fn get_module(&self, name: &str) -> Option<Module> {
+        if !self.name.is_empty() && self.name == name {
+            Some(Module {
+                name: self.name.clone(),
+                path: self.name.clone(),
+                base: self.base,
+                size: self.data.len(),
+            })
+        } else {
+            None
+        }
+    }
+
+    fn modules(&self) -> Vec<Module> {
+        self.get_module(&self.name).into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::memory::{MemoryBuffer, MemoryReader};
+
+    #[test]
+    pub fn test_memory_buffer_read() {
+        let mut data = vec![0u8; 0x100];
+        data[0x10..0x18].copy_from_slice(&0x1122334455667788usize.to_le_bytes());
+        data[0x20..0x29].copy_from_slice(b"type_info");
+        let buffer = MemoryBuffer::new(0x140000000, data).with_name("DarkSoulsIII.exe");
+
+        assert_eq!(
+            0x1122334455667788,
+            buffer.read::<usize>(0x140000010).unwrap()
+        );
+        assert_eq!(
+            "type",
+            buffer.read_utf8_str(0x140000020, 0x10, &['_']).unwrap()
+        );
+        assert!(buffer.read::<u32>(0x1400000FE).is_err());
+        assert!(buffer.read::<u32>(0x13FFFFFFF).is_err());
+        assert_eq!(
+            0x140000000,
+            buffer.get_module("DarkSoulsIII.exe").unwrap().base
+        );
+        assert!(buffer.get_module("notepad.exe").is_none());
+    }
+}
@@ -0,0 +1,357 @@
+use crate::error::{MinidumpError, ProcessError};
+use crate::memory::{MemoryInfo, MemoryReader, MAX_USER_ADDRESS, MEM_COMMIT, PAGE_READONLY};
+use crate::process::Module;
+use anyhow::Result;
+use pelite::FileMap;
+use std::fmt;
+use std::path::Path;
+
+const MINIDUMP_SIGNATURE: u32 = 0x504D444D;
+
+const MODULE_LIST_STREAM: u32 = 4;
+const MEMORY_LIST_STREAM: u32 = 5;
+const MEMORY64_LIST_STREAM: u32 = 9;
+const MEMORY_INFO_LIST_STREAM: u32 = 16;
+
+const SIZE_OF_MODULE: usize = 108;
+const SIZE_OF_MEMORY_DESCRIPTOR: usize = 16;
+const SIZE_OF_MEMORY_DESCRIPTOR64: usize = 16;
+
+enum DumpData {
+    Mapped(FileMap),
+    Owned(Vec<u8>),
+}
+
+impl AsRef<[u8]> for DumpData {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            DumpData::Mapped(map) => map.as_ref(),
+            DumpData::Owned(data) => data.as_slice(),
+        }
+    }
+}
+
+impl fmt::Debug for DumpData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DumpData({} bytes)", self.as_ref().len())
+    }
+}
+
+// A memory range stored in the dump file at `rva`
+#[derive(Debug, Copy, Clone)]
+struct DumpRange {
+    base: usize,
+    size: usize,
+    rva: usize,
+}
+
+#[derive(Debug)]
+pub struct Minidump {
+    data: DumpData,
+    modules: Vec<Module>,
+    ranges: Vec<DumpRange>,
+    regions: Vec<MemoryInfo>,
+}
+
+impl Minidump {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Minidump> {
+        Minidump::parse(DumpData::Mapped(FileMap::open(path.as_ref())?))
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Minidump> {
+        Minidump::parse(DumpData::Owned(data))
+    }
+
+    fn parse(data: DumpData) -> Result<Minidump> {
+        let mut dump = Minidump {
+            data,
+            modules: Vec::new(),
+            ranges: Vec::new(),
+            regions: Vec::new(),
+        };
+        if dump.u32_at(0)? != MINIDUMP_SIGNATURE {
+            return Err(MinidumpError::InvalidSignature.into());
+        }
+        let stream_count = dump.u32_at(8)? as usize;
+        let directory = dump.u32_at(12)? as usize;
+        for i in 0..stream_count {
+            let entry = directory + i * 12;
+            let stream_type = dump.u32_at(entry)?;
+            let rva = dump.u32_at(entry + 8)? as usize;
+            match stream_type {
+                MODULE_LIST_STREAM => dump.parse_module_list(rva)?,
+                MEMORY_LIST_STREAM => dump.parse_memory_list(rva)?,
+                MEMORY64_LIST_STREAM => dump.parse_memory64_list(rva)?,
+                MEMORY_INFO_LIST_STREAM => dump.parse_memory_info_list(rva)?,
+                _ => {}
+            }
+        }
+        dump.ranges.sort_by_key(|r| r.base);
+        if dump.regions.is_empty() {
+            // No MemoryInfoList, describe what the memory lists cover
+            dump.regions = dump
+                .ranges
+                .iter()
+                .map(|r| MemoryInfo {
+                    base: r.base,
+                    allocation_base: r.base,
+                    allocation_protect: PAGE_READONLY,
+                    size: r.size,
+                    state: MEM_COMMIT,
+                    protect: PAGE_READONLY,
+                    mem_type: 0,
+                })
+                .collect();
+        }
+        dump.regions.sort_by_key(|r| r.base);
+        Ok(dump)
+    }
+
+    fn bytes_at(&self, offset: usize, len: usize) -> Result<&[u8]> {
+        self.data
+            .as_ref()
+            .get(offset..offset.saturating_add(len))
+            .ok_or_else(|| MinidumpError::Corrupted(offset).into())
+    }
+
+    fn u32_at(&self, offset: usize) -> Result<u32> {
+        let mut buf = [0u8; 4];
+        buf.copy_from_slice(self.bytes_at(offset, 4)?);
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn u64_at(&self, offset: usize) -> Result<u64> {
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(self.bytes_at(offset, 8)?);
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    fn string_at(&self, offset: usize) -> Result<String> {
+        let len = self.u32_at(offset)? as usize;
+        let utf16: Vec<u16> = self
+            .bytes_at(offset + 4, len)?
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect();
+        Ok(String::from_utf16_lossy(utf16.as_slice()))
+    }
+
+    fn parse_module_list(&mut self, rva: usize) -> Result<()> {
+        let count = self.u32_at(rva)? as usize;
+        for i in 0..count {
+            let entry = rva + 4 + i * SIZE_OF_MODULE;
+            let path = self.string_at(self.u32_at(entry + 20)? as usize)?;
+            let name = path.rsplit('\\').next().unwrap_or(&path).to_string();
+            self.modules.push(Module {
+                name,
+                path,
+                base: self.u64_at(entry)? as usize,
+                size: self.u32_at(entry + 8)? as usize,
+            });
+        }
+        Ok(())
+    }
+
+    fn parse_memory_list(&mut self, rva: usize) -> Result<()> {
+        let count = self.u32_at(rva)? as usize;
+        for i in 0..count {
+            let entry = rva + 4 + i * SIZE_OF_MEMORY_DESCRIPTOR;
+            let range = DumpRange {
+                base: self.u64_at(entry)? as usize,
+                size: self.u32_at(entry + 8)? as usize,
+                rva: self.u32_at(entry + 12)? as usize,
+            };
+            self.push_range(entry, range)?;
+        }
+        Ok(())
+    }
+
+    fn parse_memory64_list(&mut self, rva: usize) -> Result<()> {
+        let count = self.u64_at(rva)? as usize;
+        // Full dumps store all ranges back to back starting at BaseRva
+        let mut data_rva = self.u64_at(rva + 8)? as usize;
+        for i in 0..count {
+            let entry = rva + 16 + i * SIZE_OF_MEMORY_DESCRIPTOR64;
+            let size = self.u64_at(entry + 8)? as usize;
+            let range = DumpRange {
+                base: self.u64_at(entry)? as usize,
+                size,
+                rva: data_rva,
+            };
+            self.push_range(entry, range)?;
+            data_rva = data_rva
+                .checked_add(size)
+                .ok_or(MinidumpError::Corrupted(entry))?;
+        }
+        Ok(())
+    }
+
+    // Ranges must lie in the file and in the address space
+    fn push_range(&mut self, entry: usize, range: DumpRange) -> Result<()> {
+        let in_file = range
+            .rva
+            .checked_add(range.size)
+            .is_some_and(|end| end <= self.data.as_ref().len());
+        if !in_file || range.base.checked_add(range.size).is_none() {
+            return Err(MinidumpError::Corrupted(entry).into());
+        }
+        self.ranges.push(range);
+        Ok(())
+    }
+
+    fn parse_memory_info_list(&mut self, rva: usize) -> Result<()> {
+        let header_size = self.u32_at(rva)? as usize;
+        let entry_size = self.u32_at(rva + 4)? as usize;
+        let count = self.u64_at(rva + 8)? as usize;
+        for i in 0..count {
+            let entry = rva + header_size + i * entry_size;
+            self.regions.push(MemoryInfo {
+                base: self.u64_at(entry)? as usize,
+                allocation_base: self.u64_at(entry + 8)? as usize,
+                allocation_protect: self.u32_at(entry + 16)?,
+                size: self.u64_at(entry + 24)? as usize,
+                state: self.u32_at(entry + 32)?,
+                protect: self.u32_at(entry + 36)?,
+                mem_type: self.u32_at(entry + 40)?,
+            });
+        }
+        Ok(())
+    }
+
+    fn range_at(&self, address: usize) -> Option<&DumpRange> {
+        let index = self.ranges.partition_point(|r| r.base <= address);
+        self.ranges[..index]
+            .last()
+            .filter(|r| address < r.base + r.size)
+    }
+}
+
+impl MemoryReader for Minidump {
+    fn read_bytes(&self, address: usize, buf: &mut [u8]) -> Result<()> {
+        let mut copied = 0;
+        while copied < buf.len() {
+            let current = address + copied;
+            let range = self
+                .range_at(current)
+                .ok_or(ProcessError::ReadMemoryFail(address))?;
+            let offset = current - range.base;
+            let len = (range.size - offset).min(buf.len() - copied);
+            let data = self
+                .bytes_at(range.rva + offset, len)
+                .map_err(|_| ProcessError::ReadMemoryFail(address))?;
+            buf[copied..copied + len].copy_from_slice(data);
+            copied += len;
+        }
+        Ok(())
+    }
+
+    fn query_memory_info(&self, address: usize) -> Result<MemoryInfo> {
+        if address > MAX_USER_ADDRESS {
+            return Err(ProcessError::QueryMemoryFail(address).into());
+        }
+        let index = self.regions.partition_point(|r| r.base <= address);
+        if let Some(region) = self.regions[..index].last() {
+            if address < region.end() {
+                return Ok(*region);
+            }
+        }
+        let base = self.regions[..index].last().map(|r| r.end()).unwrap_or(0);
+        let end = self
+            .regions
+            .get(index)
+            .map(|r| r.base)
+            .unwrap_or(MAX_USER_ADDRESS + 1);
+        Ok(MemoryInfo::free(base, end))
+    }
+
+    fn get_module(&self, name: &str) -> Option<Module> {
+        self.modules.iter().find(|m| m.name == name).cloned()
+    }
+
+    fn modules(&self) -> Vec<Module> {
+        self.modules.clone()
+    }
+
+    fn memory_regions(&self) -> Vec<MemoryInfo> {
+        self.regions.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::memory::{MemoryReader, MEM_FREE};
+    use crate::minidump::Minidump;
+
+    fn put_u32(buf: &mut [u8], offset: usize, value: u32) {
+        buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_u64(buf: &mut [u8], offset: usize, value: u64) {
+        buf[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+    }
+
+    #[test]
+    pub fn test_minidump_streams() {
+        let mut dump = vec![0u8; 0x400];
+        put_u32(&mut dump, 0, 0x504D444D);
+        put_u32(&mut dump, 8, 3);
+        put_u32(&mut dump, 12, 0x20);
+        // ModuleList at 0x100, name at 0x180
+        put_u32(&mut dump, 0x20, 4);
+        put_u32(&mut dump, 0x28, 0x100);
+        put_u32(&mut dump, 0x100, 1);
+        put_u64(&mut dump, 0x104, 0x140000000);
+        put_u32(&mut dump, 0x10C, 0x1000);
+        put_u32(&mut dump, 0x118, 0x180);
+        let name: Vec<u8> = "C:\\Game\\DarkSoulsIII.exe"
+            .encode_utf16()
+            .flat_map(|c| c.to_le_bytes())
+            .collect();
+        put_u32(&mut dump, 0x180, name.len() as u32);
+        dump[0x184..0x184 + name.len()].copy_from_slice(&name);
+        // Memory64List at 0x200, two adjacent ranges stored at 0x300
+        put_u32(&mut dump, 0x2C, 9);
+        put_u32(&mut dump, 0x34, 0x200);
+        put_u64(&mut dump, 0x200, 2);
+        put_u64(&mut dump, 0x208, 0x300);
+        put_u64(&mut dump, 0x210, 0x140000000);
+        put_u64(&mut dump, 0x218, 0x10);
+        put_u64(&mut dump, 0x220, 0x140000010);
+        put_u64(&mut dump, 0x228, 0x10);
+        for i in 0..0x20 {
+            dump[0x300 + i] = i as u8;
+        }
+        // MemoryInfoList at 0x260
+        put_u32(&mut dump, 0x38, 16);
+        put_u32(&mut dump, 0x40, 0x260);
+        put_u32(&mut dump, 0x260, 16);
+        put_u32(&mut dump, 0x264, 48);
+        put_u64(&mut dump, 0x268, 1);
+        put_u64(&mut dump, 0x270, 0x140000000);
+        put_u64(&mut dump, 0x278, 0x140000000);
+        put_u64(&mut dump, 0x288, 0x1000);
+        put_u32(&mut dump, 0x290, 0x1000);
+        put_u32(&mut dump, 0x294, 0x02);
+        put_u32(&mut dump, 0x298, 0x1000000);
+
+        // A range running past the end of the file
+        let mut corrupted = dump.clone();
+        put_u64(&mut corrupted, 0x228, u64::MAX);
+        assert!(Minidump::from_bytes(corrupted).is_err());
+
+        let dump = Minidump::from_bytes(dump).unwrap();
+        let module = dump.get_module("DarkSoulsIII.exe").unwrap();
+        assert_eq!(0x140000000, module.base);
+        assert_eq!(0x1000, module.size);
+        // Crosses from the first range into the second
+        assert_eq!(
+            [0x0C, 0x0D, 0x0E, 0x0F, 0x10, 0x11, 0x12, 0x13],
+            dump.read::<[u8; 8]>(0x14000000C).unwrap()
+        );
+        assert!(dump.read::<u8>(0x140000020).is_err());
+        let info = dump.query_memory_info(0x140000800).unwrap();
+        assert_eq!(0x1000000, info.mem_type);
+        assert!(info.is_readable());
+        assert_eq!(MEM_FREE, dump.query_memory_info(0x140001000).unwrap().state);
+    }
+}
@@ -0,0 +1,472 @@
+use crate::error::RemoteError;
+use crate::memory::MemoryReader;
+use crate::remote::{RemotePtr, RemoteStruct};
+use anyhow::Result;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
+
+// Layouts of the MSVC x64 standard library in release builds. Every type has
+// the size of the remote object, so they can be read with `read::<T>` or used
+// as RemoteStruct fields, and then read their contents with `read`.
+
+// Sanity limit so a garbage container doesn't allocate gigabytes
+pub const MAX_ELEMENTS: usize = 0x100000;
+
+fn invalid(address: usize, reason: &str) -> anyhow::Error {
+    RemoteError::InvalidContainer {
+        address,
+        reason: reason.to_string(),
+    }
+    .into()
+}
+
+fn check_size(address: usize, size: usize) -> Result<()> {
+    if size > MAX_ELEMENTS {
+        return Err(invalid(address, &format!("{} elements", size)));
+    }
+    Ok(())
+}
+
+// std::vector<T>, `T` has to match the remote element size
+#[repr(C)]
+pub struct StdVector<T> {
+    first: RemotePtr<T>,
+    last: RemotePtr<T>,
+    end: RemotePtr<T>,
+}
+
+impl<T> StdVector<T> {
+    pub fn first(&self) -> RemotePtr<T> {
+        self.first
+    }
+
+    pub fn len(&self) -> usize {
+        self.last.address().saturating_sub(self.first.address()) / mem::size_of::<T>().max(1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.end.address().saturating_sub(self.first.address()) / mem::size_of::<T>().max(1)
+    }
+
+    pub fn get(&self, index: usize) -> Option<RemotePtr<T>> {
+        (index < self.len()).then(|| self.first.add(index))
+    }
+
+    fn check(&self) -> Result<usize> {
+        let (first, last, end) = (
+            self.first.address(),
+            self.last.address(),
+            self.end.address(),
+        );
+        if first > last || last > end {
+            return Err(invalid(first, "pointers out of order"));
+        }
+        if (last - first) % mem::size_of::<T>().max(1) != 0 {
+            return Err(invalid(first, "size isn't a multiple of the element size"));
+        }
+        check_size(first, self.len())?;
+        Ok(self.len())
+    }
+}
+
+impl<T: Copy> StdVector<T> {
+    // One read for the whole array
+    pub fn read<R: MemoryReader>(&self, reader: &R) -> Result<Vec<T>> {
+        let len = self.check()?;
+        self.first.read_array(reader, len)
+    }
+}
+
+// Node of std::list, the value follows the links
+const LIST_VALUE: usize = 0x10;
+
+// std::list<T>, `head` is a sentinel node without a value
+#[repr(C)]
+pub struct StdList<T> {
+    head: RemotePtr<usize>,
+    size: usize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> StdList<T> {
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    // Address of every value, in list order
+    pub fn addresses<R: MemoryReader>(&self, reader: &R) -> Result<Vec<usize>> {
+        check_size(self.head.address(), self.size)?;
+        let head = self.head.checked()?;
+        let mut addresses = Vec::with_capacity(self.size);
+        let mut node = self.head.read(reader)?;
+        while node != head {
+            if addresses.len() == self.size || node == 0 {
+                return Err(invalid(head, "broken links"));
+            }
+            addresses.push(node + LIST_VALUE);
+            node = reader.read::<usize>(node)?;
+        }
+        if addresses.len() != self.size {
+            return Err(invalid(head, "size doesn't match the nodes"));
+        }
+        Ok(addresses)
+    }
+}
+
+impl<T: Copy> StdList<T> {
+    pub fn read<R: MemoryReader>(&self, reader: &R) -> Result<Vec<T>> {
+        self.addresses(reader)?
+            .into_iter()
+            .map(|address| reader.read::<T>(address))
+            .collect()
+    }
+}
+
+impl<T: RemoteStruct> StdList<T> {
+    pub fn read_remote<R: MemoryReader>(&self, reader: &R) -> Result<Vec<T>> {
+        self.addresses(reader)?
+            .into_iter()
+            .map(|address| T::read_remote(reader, address))
+            .collect()
+    }
+}
+
+// std::pair<K, V>
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct Pair<K, V> {
+    key: K,
+    value: V,
+}
+
+// Node of the red black tree behind std::map
+const TREE_LEFT: usize = 0x0;
+const TREE_PARENT: usize = 0x8;
+const TREE_RIGHT: usize = 0x10;
+const TREE_IS_NIL: usize = 0x19;
+const TREE_VALUE: usize = 0x20;
+
+// std::map<K, V>, `head` is a sentinel whose parent is the root
+#[repr(C)]
+pub struct StdMap<K, V> {
+    head: RemotePtr<usize>,
+    size: usize,
+    _marker: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K, V> StdMap<K, V> {
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    // Address of every std::pair, in key order
+    pub fn addresses<R: MemoryReader>(&self, reader: &R) -> Result<Vec<usize>> {
+        check_size(self.head.address(), self.size)?;
+        let head = self.head.checked()?;
+        let is_nil = |node: usize| -> Result<bool> {
+            Ok(node == 0 || node == head || reader.read::<u8>(node + TREE_IS_NIL)? != 0)
+        };
+        let mut addresses = Vec::with_capacity(self.size);
+        let mut stack = Vec::new();
+        let mut node = reader.read::<usize>(head + TREE_PARENT)?;
+        while !is_nil(node)? || !stack.is_empty() {
+            while !is_nil(node)? {
+                if stack.len() > self.size {
+                    return Err(invalid(head, "broken links"));
+                }
+                stack.push(node);
+                node = reader.read::<usize>(node + TREE_LEFT)?;
+            }
+            node = stack.pop().unwrap();
+            if addresses.len() == self.size {
+                return Err(invalid(head, "size doesn't match the nodes"));
+            }
+            addresses.push(node + TREE_VALUE);
+            node = reader.read::<usize>(node + TREE_RIGHT)?;
+        }
+        if addresses.len() != self.size {
+            return Err(invalid(head, "size doesn't match the nodes"));
+        }
+        Ok(addresses)
+    }
+}
+
+impl<K: Copy, V: Copy> StdMap<K, V> {
+    pub fn read<R: MemoryReader>(&self, reader: &R) -> Result<Vec<(K, V)>> {
+        self.addresses(reader)?
+            .into_iter()
+            .map(|address| {
+                let pair = reader.read::<Pair<K, V>>(address)?;
+                Ok((pair.key, pair.value))
+            })
+            .collect()
+    }
+}
+
+// std::unordered_map<K, V>, the values live in a std::list, the buckets only
+// point into it
+#[repr(C)]
+pub struct StdUnorderedMap<K, V> {
+    max_load_factor: f32,
+    list: StdList<Pair<K, V>>,
+    buckets: StdVector<usize>,
+    _mask: usize,
+    _max_index: usize,
+}
+
+impl<K, V> StdUnorderedMap<K, V> {
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn bucket_count(&self) -> usize {
+        self.buckets.len()
+    }
+
+    pub fn max_load_factor(&self) -> f32 {
+        self.max_load_factor
+    }
+
+    // Address of every std::pair, in iteration order
+    pub fn addresses<R: MemoryReader>(&self, reader: &R) -> Result<Vec<usize>> {
+        self.list.addresses(reader)
+    }
+}
+
+impl<K: Copy, V: Copy> StdUnorderedMap<K, V> {
+    pub fn read<R: MemoryReader>(&self, reader: &R) -> Result<Vec<(K, V)>> {
+        Ok(self
+            .list
+            .read(reader)?
+            .into_iter()
+            .map(|pair| (pair.key, pair.value))
+            .collect())
+    }
+}
+
+// std::basic_string, short strings are stored inline. `N` is the inline
+// capacity in characters, including the terminator.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct StdBasicString<C: Copy, const N: usize> {
+    buf: [C; N],
+    size: usize,
+    capacity: usize,
+}
+
+pub type StdString = StdBasicString<u8, 16>;
+pub type StdWString = StdBasicString<u16, 8>;
+
+impl<C: Copy, const N: usize> StdBasicString<C, N> {
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub fn is_inline(&self) -> bool {
+        self.capacity < N
+    }
+
+    pub fn chars<R: MemoryReader>(&self, reader: &R) -> Result<Vec<C>> {
+        if self.size > self.capacity {
+            return Err(invalid(0, "size is larger than the capacity"));
+        }
+        if self.is_inline() {
+            return Ok(self.buf[..self.size].to_vec());
+        }
+        let pointer = unsafe { (self.buf.as_ptr() as *const RemotePtr<C>).read_unaligned() };
+        check_size(pointer.address(), self.size)?;
+        pointer.read_array(reader, self.size)
+    }
+}
+
+impl StdString {
+    pub fn read<R: MemoryReader>(&self, reader: &R) -> Result<String> {
+        Ok(String::from_utf8_lossy(&self.chars(reader)?).into_owned())
+    }
+}
+
+impl StdWString {
+    pub fn read<R: MemoryReader>(&self, reader: &R) -> Result<String> {
+        Ok(String::from_utf16_lossy(&self.chars(reader)?))
+    }
+}
+
+// FromSoftware's DLText::DLString, a wide string behind its allocator
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DLString {
+    allocator: usize,
+    string: StdWString,
+}
+
+impl DLString {
+    pub fn allocator(&self) -> usize {
+        self.allocator
+    }
+
+    pub fn len(&self) -> usize {
+        self.string.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.string.is_empty()
+    }
+
+    pub fn read<R: MemoryReader>(&self, reader: &R) -> Result<String> {
+        self.string.read(reader)
+    }
+}
+
+// Derives would require the element types to implement the traits too
+macro_rules! container_impls {
+    ($name:ident<$($param:ident),+>) => {
+        impl<$($param),+> Clone for $name<$($param),+> {
+            fn clone(&self) -> Self {
+                *self
+            }
+        }
+
+        impl<$($param),+> Copy for $name<$($param),+> {}
+
+        impl<$($param),+> fmt::Debug for $name<$($param),+> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{} {{ len: {} }}", stringify!($name), self.len())
+            }
+        }
+    };
+}
+
+container_impls!(StdVector<T>);
+container_impls!(StdList<T>);
+container_impls!(StdMap<K, V>);
+container_impls!(StdUnorderedMap<K, V>);
+
+impl<C: Copy, const N: usize> fmt::Debug for StdBasicString<C, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "StdBasicString {{ len: {} }}", self.size)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::memory::{MemoryBuffer, MemoryReader};
+    use crate::msvc::{StdList, StdMap, StdUnorderedMap, StdVector, StdWString};
+
+    fn put(data: &mut [u8], offset: usize, bytes: &[u8]) {
+        data[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    #[test]
+    pub fn test_msvc_containers() {
+        let base = 0x10000usize;
+        let mut data = vec![0u8; 0x1000];
+
+        // vector<u32> { 1, 2, 3 } with room for 4
+        put(&mut data, 0x000, &(base + 0x100).to_le_bytes());
+        put(&mut data, 0x008, &(base + 0x10C).to_le_bytes());
+        put(&mut data, 0x010, &(base + 0x110).to_le_bytes());
+        for (i, v) in [1u32, 2, 3].iter().enumerate() {
+            put(&mut data, 0x100 + i * 4, &v.to_le_bytes());
+        }
+
+        // list<u32> { 10, 20 }: head 0x200 -> 0x220 -> 0x240 -> head
+        put(&mut data, 0x020, &(base + 0x200).to_le_bytes());
+        put(&mut data, 0x028, &2usize.to_le_bytes());
+        put(&mut data, 0x200, &(base + 0x220).to_le_bytes());
+        put(&mut data, 0x220, &(base + 0x240).to_le_bytes());
+        put(&mut data, 0x230, &10u32.to_le_bytes());
+        put(&mut data, 0x240, &(base + 0x200).to_le_bytes());
+        put(&mut data, 0x250, &20u32.to_le_bytes());
+
+        // map<u32, u64> { 1: 100, 2: 200, 3: 300 } with 2 at the root
+        put(&mut data, 0x030, &(base + 0x300).to_le_bytes());
+        put(&mut data, 0x038, &3usize.to_le_bytes());
+        let head = base + 0x300;
+        put(&mut data, 0x308, &(base + 0x340).to_le_bytes());
+        data[0x319] = 1;
+        for (node, left, right, key) in [
+            (0x340, base + 0x380, base + 0x3C0, 2u32),
+            (0x380, head, head, 1),
+            (0x3C0, head, head, 3),
+        ] {
+            put(&mut data, node, &left.to_le_bytes());
+            put(&mut data, node + 0x10, &right.to_le_bytes());
+            put(&mut data, node + 0x20, &key.to_le_bytes());
+            put(&mut data, node + 0x28, &(key as u64 * 100).to_le_bytes());
+        }
+
+        // unordered_map<u32, u32> { 5: 50 }, only the list matters
+        put(&mut data, 0x048, &(base + 0x400).to_le_bytes());
+        put(&mut data, 0x050, &1usize.to_le_bytes());
+        put(&mut data, 0x400, &(base + 0x420).to_le_bytes());
+        put(&mut data, 0x420, &(base + 0x400).to_le_bytes());
+        put(&mut data, 0x430, &5u32.to_le_bytes());
+        put(&mut data, 0x434, &50u32.to_le_bytes());
+
+        // Inline and heap wstrings
+        for (i, c) in "Ashen".encode_utf16().enumerate() {
+            put(&mut data, 0x500 + i * 2, &c.to_le_bytes());
+        }
+        put(&mut data, 0x510, &5usize.to_le_bytes());
+        put(&mut data, 0x518, &7usize.to_le_bytes());
+        put(&mut data, 0x520, &(base + 0x600).to_le_bytes());
+        put(&mut data, 0x530, &11usize.to_le_bytes());
+        put(&mut data, 0x538, &15usize.to_le_bytes());
+        for (i, c) in "Firelink 01".encode_utf16().enumerate() {
+            put(&mut data, 0x600 + i * 2, &c.to_le_bytes());
+        }
+
+        let buffer = MemoryBuffer::new(base, data);
+        let vector = buffer.read::<StdVector<u32>>(base).unwrap();
+        assert_eq!((3, 4), (vector.len(), vector.capacity()));
+        assert_eq!(vec![1, 2, 3], vector.read(&buffer).unwrap());
+        assert_eq!(2, vector.get(1).unwrap().read(&buffer).unwrap());
+
+        let list = buffer.read::<StdList<u32>>(base + 0x20).unwrap();
+        assert_eq!(vec![10, 20], list.read(&buffer).unwrap());
+
+        let map = buffer.read::<StdMap<u32, u64>>(base + 0x30).unwrap();
+        assert_eq!(
+            vec![(1, 100), (2, 200), (3, 300)],
+            map.read(&buffer).unwrap()
+        );
+
+        let map = buffer
+            .read::<StdUnorderedMap<u32, u32>>(base + 0x40)
+            .unwrap();
+        assert_eq!(vec![(5, 50)], map.read(&buffer).unwrap());
+
+        let name = buffer.read::<StdWString>(base + 0x500).unwrap();
+        assert!(name.is_inline());
+        assert_eq!("Ashen", name.read(&buffer).unwrap());
+        let name = buffer.read::<StdWString>(base + 0x520).unwrap();
+        assert!(!name.is_inline());
+        assert_eq!("Firelink 01", name.read(&buffer).unwrap());
+
+        // A list claiming more nodes than it links
+        let mut broken = list;
+        broken.size = 3;
+        assert!(broken.read(&buffer).is_err());
+    }
+}
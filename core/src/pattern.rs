@@ -1,80 +1,329 @@
-use crate::error::ProcessError;
-use crate::process::Process;
-use anyhow::{anyhow, Result};
-use std::ops::IndexMut;
+use crate::capture::{Capture, CaptureKind};
+use crate::error::PatternError;
+use crate::memory::MemoryReader;
+use crate::pattern_scan::{readable_ranges, PatternScan};
+use anyhow::Result;
+use memchr::memchr_iter;
+use std::fmt;
+use std::str::FromStr;
 
-pub fn pattern_search(
-    pattern: String,
-    data: &[u8],
-    find_first: bool,
-    base: Option<usize>,
-) -> Result<Vec<usize>> {
-    let mut result = Vec::new();
-    if pattern.is_empty() {
-        return Ok(result);
+const FULL: u8 = 0xFF;
+// Bytes too frequent in code and data to be a good prefilter anchor
+const COMMON_BYTES: [u8; 8] = [0x00, 0xFF, 0xCC, 0x48, 0x8B, 0x89, 0x0F, 0x90];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PatternFormat {
+    // 48 8B ? ?
+    Ida,
+    // 48 8B ?? ?? with half byte wildcards: 4? ?B
+    X64dbg,
+    // 48 8B * *
+    CheatEngine,
+    // "\x48\x8B\x00\x00" with the mask "xx??"
+    Code,
+}
+
+// Byte pattern compiled once and searched many times. Each byte has a mask of
+// the bits that have to match, so `4?` is 0x40 with the mask 0xF0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    bytes: Vec<u8>,
+    masks: Vec<u8>,
+    // Wildcard slots resolved after a match, see crate::capture
+    captures: Vec<Capture>,
+    // Fully known byte that memchr looks for before comparing the rest
+    anchor: Option<usize>,
+}
+
+impl Pattern {
+    fn new(bytes: Vec<u8>, masks: Vec<u8>, captures: Vec<Capture>) -> Result<Pattern> {
+        if bytes.is_empty() {
+            return Err(PatternError::Empty.into());
+        }
+        let bytes: Vec<u8> = bytes.iter().zip(&masks).map(|(b, m)| b & m).collect();
+        let full: Vec<usize> = (0..bytes.len()).filter(|i| masks[*i] == FULL).collect();
+        let anchor = full
+            .iter()
+            .find(|i| !COMMON_BYTES.contains(&bytes[**i]))
+            .or_else(|| full.first())
+            .copied();
+        Ok(Pattern {
+            bytes,
+            masks,
+            captures,
+            anchor,
+        })
+    }
+
+    // IDA, x64dbg and Cheat Engine syntax. Tokens are separated by spaces,
+    // `?`, `??`, `*` and `**` are wildcards, `4?` and `?B` match half a byte,
+    // and a token may hold several bytes without spaces: `488B05????????`.
+    // `[rel8]` and `[rel32]` are wildcards captured for crate::capture.
+    pub fn parse(pattern: &str) -> Result<Pattern> {
+        let mut bytes = Vec::new();
+        let mut masks = Vec::new();
+        let mut captures = Vec::new();
+        for token in pattern.split_whitespace() {
+            if matches!(token, "?" | "??" | "*" | "**") {
+                bytes.push(0);
+                masks.push(0);
+                continue;
+            }
+            if let Some(kind) = CaptureKind::parse(token) {
+                captures.push(Capture {
+                    offset: bytes.len(),
+                    kind,
+                });
+                bytes.resize(bytes.len() + kind.size(), 0);
+                masks.resize(masks.len() + kind.size(), 0);
+                continue;
+            }
+            if token.len() % 2 != 0 || !token.is_ascii() {
+                return Err(PatternError::InvalidToken(token.to_string()).into());
+            }
+            for pair in token.as_bytes().chunks(2) {
+                let (high, high_mask) = nibble(pair[0], token)?;
+                let (low, low_mask) = nibble(pair[1], token)?;
+                bytes.push(high << 4 | low);
+                masks.push(high_mask << 4 | low_mask);
+            }
+        }
+        Pattern::new(bytes, masks, captures)
     }
 
-    let mut pattern_b: Vec<u8> = Vec::new();
-    let mut wild_addr = 0;
-    let mut wild_mask: Vec<bool> = Vec::new();
-    let mut index = 0;
-    for code in pattern.split_whitespace().into_iter() {
-        if code.len() != 2 {
-            return Err(anyhow!("Invalid Pattern"));
+    // Code style: raw bytes plus a mask where `x` matches and `?` is a wildcard
+    pub fn from_code(bytes: &[u8], mask: &str) -> Result<Pattern> {
+        if bytes.len() != mask.len() {
+            return Err(PatternError::MaskLength(bytes.len(), mask.len()).into());
         }
-        if code == "??" {
-            pattern_b.push(b'?');
-            wild_mask.push(true);
-            wild_addr = index;
-        } else {
-            wild_mask.push(false);
-            pattern_b
-                .push(u8::from_str_radix(code, 16).map_err(|_| anyhow!("Invalid Hex String"))?);
+        let masks = mask
+            .chars()
+            .map(|c| match c {
+                'x' | 'X' => Ok(FULL),
+                '?' | '.' => Ok(0),
+                _ => Err(PatternError::InvalidToken(c.to_string())),
+            })
+            .collect::<std::result::Result<Vec<u8>, PatternError>>()?;
+        Pattern::new(bytes.to_vec(), masks, Vec::new())
+    }
+
+    // Parses the `\x48\x8B` escapes of a code style byte string
+    pub fn from_code_str(bytes: &str, mask: &str) -> Result<Pattern> {
+        if !bytes.is_empty() && !bytes.starts_with("\\x") {
+            return Err(PatternError::InvalidToken(bytes.to_string()).into());
         }
-        index += 1;
+        let parsed = bytes
+            .split("\\x")
+            .skip(1)
+            .map(|hex| {
+                u8::from_str_radix(hex, 16).map_err(|_| PatternError::InvalidToken(hex.to_string()))
+            })
+            .collect::<std::result::Result<Vec<u8>, PatternError>>()?;
+        Pattern::from_code(&parsed, mask)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Pattern> {
+        Pattern::new(bytes.to_vec(), vec![FULL; bytes.len()], Vec::new())
     }
 
-    let mut shift: [i32; 256] = [0; 256];
-    for i in 0..256 {
-        shift[i] = -1;
+    pub fn len(&self) -> usize {
+        self.bytes.len()
     }
-    for i in (wild_addr + 1)..pattern_b.len() {
-        shift[pattern_b[i] as usize] = (pattern_b.len() - i) as i32;
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        self.bytes.as_slice()
+    }
+
+    pub fn masks(&self) -> &[u8] {
+        self.masks.as_slice()
+    }
+
+    pub fn captures(&self) -> &[Capture] {
+        self.captures.as_slice()
     }
-    for i in 0..256 {
-        if shift[i] == -1 {
-            shift[i] = (pattern_b.len() - wild_addr) as i32;
+
+    pub fn matches_at(&self, data: &[u8], offset: usize) -> bool {
+        match data.get(offset..offset + self.len()) {
+            Some(window) => window
+                .iter()
+                .zip(self.bytes.iter().zip(&self.masks))
+                .all(|(d, (b, m))| d & m == *b),
+            None => false,
         }
     }
 
-    let mask_buffer = wild_mask.as_slice();
-    let len_memory = data.len();
-    let len_pattern = pattern_b.len();
-    let mut i = 0;
-    let mut j = 0;
-    while i <= (len_memory - len_pattern) {
-        while j < len_pattern && (data[i + j] == pattern_b[j] || mask_buffer[j] == true) {
-            j += 1;
+    // Offsets of every match, overlapping ones included
+    pub fn search(&self, data: &[u8]) -> Vec<usize> {
+        let mut result = Vec::new();
+        self.search_with(data, |offset| {
+            result.push(offset);
+            true
+        });
+        result
+    }
+
+    pub fn find(&self, data: &[u8]) -> Option<usize> {
+        let mut found = None;
+        self.search_with(data, |offset| {
+            found = Some(offset);
+            false
+        });
+        found
+    }
+
+    // Calls `on_match` for every match until it returns false. Only the
+    // positions of the anchor byte are compared in full.
+    pub fn search_with<F: FnMut(usize) -> bool>(&self, data: &[u8], mut on_match: F) {
+        let len = self.len();
+        if data.len() < len {
+            return;
         }
-        if j == len_pattern {
-            if let Some(rip) = base {
-                result.push(rip + i);
-            } else {
-                result.push(i);
+        let last = data.len() - len;
+        match self.anchor {
+            Some(anchor) => {
+                let window = &data[anchor..anchor + last + 1];
+                for offset in memchr_iter(self.bytes[anchor], window) {
+                    if self.matches_at(data, offset) && !on_match(offset) {
+                        return;
+                    }
+                }
             }
-            if find_first {
-                return Ok(result);
+            None => {
+                for offset in 0..=last {
+                    if self.matches_at(data, offset) && !on_match(offset) {
+                        return;
+                    }
+                }
             }
-            i += len_pattern;
-        } else {
-            if i + len_pattern >= len_memory {
-                break;
+        }
+    }
+
+    // Matches in the readable memory of [start, start + size), read lazily
+    pub fn scan<'a, R: MemoryReader>(
+        &'a self,
+        reader: &'a R,
+        start: usize,
+        size: usize,
+    ) -> PatternScan<'a, R> {
+        PatternScan::new(reader, self, readable_ranges(reader, start, start + size))
+    }
+
+    // Half byte wildcards only exist in the x64dbg format, the other formats
+    // get a whole byte wildcard for them. Captures are written as wildcards.
+    pub fn format(&self, format: PatternFormat) -> String {
+        if format == PatternFormat::Code {
+            let (bytes, mask) = self.to_code();
+            let escaped: String = bytes.iter().map(|b| format!("\\x{:02X}", b)).collect();
+            return format!("\"{}\", \"{}\"", escaped, mask);
+        }
+        let tokens: Vec<String> = self
+            .bytes
+            .iter()
+            .zip(&self.masks)
+            .map(|(byte, mask)| match (format, *mask) {
+                (_, FULL) => format!("{:02X}", byte),
+                (PatternFormat::X64dbg, 0xF0) => format!("{:X}?", byte >> 4),
+                (PatternFormat::X64dbg, 0x0F) => format!("?{:X}", byte & 0xF),
+                (PatternFormat::Ida, _) => "?".to_string(),
+                (PatternFormat::CheatEngine, _) => "*".to_string(),
+                _ => "??".to_string(),
+            })
+            .collect();
+        tokens.join(" ")
+    }
+
+    // Bytes and mask for code style searches, wildcard bytes are zero
+    pub fn to_code(&self) -> (Vec<u8>, String) {
+        let mask = self
+            .masks
+            .iter()
+            .map(|m| if *m == FULL { 'x' } else { '?' })
+            .collect();
+        let bytes = self
+            .bytes
+            .iter()
+            .zip(&self.masks)
+            .map(|(b, m)| if *m == FULL { *b } else { 0 })
+            .collect();
+        (bytes, mask)
+    }
+}
+
+fn nibble(c: u8, token: &str) -> Result<(u8, u8)> {
+    match c {
+        b'?' => Ok((0, 0)),
+        _ => (c as char)
+            .to_digit(16)
+            .map(|digit| (digit as u8, 0xF))
+            .ok_or_else(|| PatternError::InvalidToken(token.to_string()).into()),
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Pattern> {
+        Pattern::parse(s)
+    }
+}
+
+// x64dbg format with the captures kept, so it parses back to the same pattern
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = self.format(PatternFormat::X64dbg);
+        let bytes: Vec<&str> = text.split(' ').collect();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < bytes.len() {
+            match self.captures.iter().find(|capture| capture.offset == i) {
+                Some(capture) => {
+                    tokens.push(capture.kind.to_string());
+                    i += capture.kind.size();
+                }
+                None => {
+                    tokens.push(bytes[i].to_string());
+                    i += 1;
+                }
             }
-            i += shift[data[i + len_pattern] as usize] as usize;
         }
-        j = 0;
+        write!(f, "{}", tokens.join(" "))
     }
-    Ok(result)
+}
+
+// Matches don't overlap, the search continues after the end of each match
+fn search_compiled(
+    pattern: &Pattern,
+    data: &[u8],
+    find_first: bool,
+    base: Option<usize>,
+) -> Vec<usize> {
+    let mut result = Vec::new();
+    let mut next = 0;
+    pattern.search_with(data, |offset| {
+        if offset >= next {
+            result.push(base.unwrap_or(0) + offset);
+            next = offset + pattern.len();
+        }
+        !find_first
+    });
+    result
+}
+
+pub fn pattern_search(
+    pattern: String,
+    data: &[u8],
+    find_first: bool,
+    base: Option<usize>,
+) -> Result<Vec<usize>> {
+    if pattern.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let pattern = Pattern::parse(&pattern)?;
+    Ok(search_compiled(&pattern, data, find_first, base))
 }
 
 pub fn pattern_search2(
@@ -83,96 +332,143 @@ pub fn pattern_search2(
     find_first: bool,
     base: Option<usize>,
 ) -> Result<Vec<usize>> {
-    let mut p = String::new();
-    for i in 0..pattern.len() {
-        let c = format!("{:02X}", pattern[i]);
-        p.push_str(&c);
-        if i != pattern.len() - 1 {
-            p.push_str(&*" ".to_string());
-        }
+    if pattern.is_empty() {
+        return Ok(Vec::new());
     }
-    pattern_search(p, data, find_first, base)
+    let pattern = Pattern::from_bytes(pattern)?;
+    Ok(search_compiled(&pattern, data, find_first, base))
 }
 
-pub fn remote_pattern_search2(
-    process: &Process,
+pub fn remote_pattern_search2<R: MemoryReader>(
+    process: &R,
     start: usize,
     size: usize,
     page_size: usize,
     pattern: &[u8],
     find_first: bool,
 ) -> Result<Vec<usize>> {
-    let mut p = String::new();
-    for i in 0..pattern.len() {
-        let c = format!("{:02X}", pattern[i]);
-        p.push_str(&c);
-        if i != pattern.len() - 1 {
-            p.push_str(&*" ".to_string());
-        }
+    if pattern.is_empty() {
+        return Ok(Vec::new());
     }
-    remote_pattern_search(process, start, size, page_size, p, find_first)
+    let pattern = Pattern::from_bytes(pattern)?;
+    remote_search_compiled(process, start, size, page_size, &pattern, find_first)
 }
 
-pub fn remote_pattern_search(
-    process: &Process,
+pub fn remote_pattern_search<R: MemoryReader>(
+    process: &R,
     start: usize,
     size: usize,
     page_size: usize,
     pattern: String,
     find_first: bool,
 ) -> Result<Vec<usize>> {
-    let mut result = Vec::new();
-    let pattern_size = pattern.replace(" ", "").to_string().len() / 2;
-    let page_num;
-    let is_align;
-    if size % page_size == 0 {
-        page_num = size / page_size;
-        is_align = true;
-    } else {
-        page_num = size / page_size + 1;
-        is_align = false;
-    }
-    let mut buffer: Vec<u8> = Vec::with_capacity(page_size + pattern_size);
-    buffer.resize(page_size + pattern_size, 0);
-    for i in 0..page_num {
-        let begin = start + i * page_size;
-        let end;
-        if !is_align && i == page_num - 1 {
-            end = start + size;
-        } else {
-            end = begin + page_size;
-        }
-        let buffer_size = end - begin;
-        let rip_base;
-        let success;
-        if i == 0 {
-            success = process.read_ptr(buffer.as_mut_ptr(), begin, buffer_size);
-            rip_base = begin;
-        } else {
-            let tail = buffer[page_size..].to_vec();
-            assert_eq!(tail.len(), pattern_size);
-            buffer.fill(0);
-            for i in 0..pattern_size {
-                *buffer.get_mut(0).unwrap() = *tail.get(i).unwrap();
-            }
-            success = process.read_ptr(
-                unsafe { buffer.as_mut_ptr().add(pattern_size) },
-                begin,
-                buffer_size,
-            );
-            rip_base = begin - pattern_size;
-        }
-        if success.is_err() {
+    if pattern.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let pattern = Pattern::parse(&pattern)?;
+    remote_search_compiled(process, start, size, page_size, &pattern, find_first)
+}
+
+// Streams `page_size` bytes at a time over the readable parts of the range
+fn remote_search_compiled<R: MemoryReader>(
+    process: &R,
+    start: usize,
+    size: usize,
+    page_size: usize,
+    pattern: &Pattern,
+    find_first: bool,
+) -> Result<Vec<usize>> {
+    let ranges = readable_ranges(process, start, start + size);
+    let mut result: Vec<usize> = Vec::new();
+    for address in PatternScan::new(process, pattern, ranges).with_chunk_size(page_size) {
+        if result
+            .last()
+            .is_some_and(|last| address < last + pattern.len())
+        {
             continue;
         }
-        let sub_result = pattern_search(pattern.clone(), buffer.as_slice(), find_first, None)?;
-        for offset in &sub_result {
-            result.push(rip_base + offset);
-        }
-        if find_first && !sub_result.is_empty() {
-            assert!(!result.is_empty());
-            return Ok(result[0..1].to_vec());
+        result.push(address);
+        if find_first {
+            break;
         }
     }
     Ok(result)
 }
+
+#[cfg(test)]
+mod test {
+    use crate::memory::MemoryBuffer;
+    use crate::pattern::{pattern_search, remote_pattern_search, Pattern, PatternFormat};
+
+    #[test]
+    pub fn test_pattern_syntaxes() {
+        let ida = Pattern::parse("48 8B ? ? 4? ?5").unwrap();
+        let x64dbg = Pattern::parse("48 8B ?? ?? 4? ?5").unwrap();
+        let compact = Pattern::parse("488B????4??5").unwrap();
+        let cheat_engine = Pattern::parse("48 8B * * 4? ?5").unwrap();
+        assert_eq!(ida, x64dbg);
+        assert_eq!(ida, compact);
+        assert_eq!(ida, cheat_engine);
+        assert_eq!("48 8B ?? ?? 4? ?5", ida.to_string());
+        assert_eq!("48 8B ? ? ? ?", ida.format(PatternFormat::Ida));
+        assert_eq!("48 8B * * * *", ida.format(PatternFormat::CheatEngine));
+
+        let code = Pattern::from_code_str("\\x48\\x8B\\x00\\x00", "xx??").unwrap();
+        assert_eq!(code, Pattern::parse("48 8B ?? ??").unwrap());
+        assert_eq!(
+            "\"\\x48\\x8B\\x00\\x00\", \"xx??\"",
+            code.format(PatternFormat::Code)
+        );
+        assert_eq!((vec![0x48, 0x8B, 0, 0], "xx??".to_string()), code.to_code());
+
+        assert!(Pattern::parse("").is_err());
+        assert!(Pattern::parse("48 8").is_err());
+        assert!(Pattern::parse("48 GG").is_err());
+        assert!(Pattern::from_code(&[0x48], "xx").is_err());
+    }
+
+    #[test]
+    pub fn test_pattern_search() {
+        let data = [
+            0x48, 0x8B, 0x05, 0x41, 0x15, 0x48, 0x8B, 0x48, 0x8B, 0x0D, 0x4F, 0x25,
+        ];
+        let pattern = Pattern::parse("48 8B ?? 4? ?5").unwrap();
+        assert_eq!(vec![0, 7], pattern.search(&data));
+        assert_eq!(Some(0), pattern.find(&data));
+        assert_eq!(vec![1, 6, 8], Pattern::parse("8B").unwrap().search(&data));
+
+        // Longer than the data, or ending at its last byte
+        assert!(Pattern::parse("48 8B 05 41 15 48 8B 48 8B 0D 4F 25 00")
+            .unwrap()
+            .search(&data)
+            .is_empty());
+        assert_eq!(vec![11], Pattern::parse("25").unwrap().search(&data));
+        assert_eq!(
+            vec![0; 0],
+            pattern_search("".into(), &data, false, None).unwrap()
+        );
+        assert_eq!(
+            vec![0x1007],
+            pattern_search("48 8B 0D".into(), &data, true, Some(0x1000)).unwrap()
+        );
+
+        // Overlapping matches are reported by Pattern::search only
+        let repeated = [0xAA; 5];
+        assert_eq!(
+            vec![0, 1, 2, 3],
+            Pattern::parse("AA AA").unwrap().search(&repeated)
+        );
+        assert_eq!(
+            vec![0, 2],
+            pattern_search("AA AA".into(), &repeated, false, None).unwrap()
+        );
+
+        // A match across a page boundary
+        let mut memory = vec![0u8; 0x3000];
+        memory[0xFFE..0x1002].copy_from_slice(&[0x48, 0x8B, 0x05, 0x41]);
+        memory[0x2FFE..0x3000].copy_from_slice(&[0x48, 0x8B]);
+        let buffer = MemoryBuffer::new(0x10000, memory);
+        let found = remote_pattern_search(&buffer, 0x10000, 0x3000, 0x1000, "48 8B".into(), false);
+        assert_eq!(vec![0x10FFE, 0x12FFE], found.unwrap());
+    }
+}
@@ -0,0 +1,240 @@
+use crate::memory::MemoryReader;
+use crate::pattern::Pattern;
+use std::collections::VecDeque;
+
+const PAGE_SIZE: usize = 0x1000;
+pub const DEFAULT_CHUNK_SIZE: usize = 0x10000;
+
+// Readable parts of [start, end), adjacent regions merged so a match can span
+// them. The whole range when the reader can't report its regions.
+pub fn readable_ranges<R: MemoryReader>(
+    reader: &R,
+    start: usize,
+    end: usize,
+) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    let mut address = start;
+    while address < end {
+        let info = match reader.query_memory_info(address) {
+            Ok(info) if info.end() > address => info,
+            _ if address == start => return vec![(start, end)],
+            _ => break,
+        };
+        let region_end = info.end().min(end);
+        if info.is_readable() {
+            match ranges.last_mut() {
+                Some(last) if last.1 == address => last.1 = region_end,
+                _ => ranges.push((address, region_end)),
+            }
+        }
+        address = region_end;
+    }
+    ranges
+}
+
+// Reads ranges one chunk at a time. The last `overlap` bytes of a chunk are
+// kept in front of the next one when the two are contiguous, and a page that
+// can't be read only breaks the chunks around it.
+pub(crate) struct Chunks<'a, R: MemoryReader> {
+    reader: &'a R,
+    ranges: VecDeque<(usize, usize)>,
+    chunk_size: usize,
+    overlap: usize,
+    // Bytes carried over from the previous chunk, ending at `carry_end`
+    buffer: Vec<u8>,
+    carry_end: usize,
+}
+
+impl<'a, R: MemoryReader> Chunks<'a, R> {
+    // `ranges` are [start, end) pairs in ascending order
+    pub(crate) fn new(reader: &'a R, ranges: Vec<(usize, usize)>, overlap: usize) -> Self {
+        Chunks {
+            reader,
+            ranges: ranges.into_iter().filter(|(s, e)| s < e).collect(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            overlap,
+            buffer: Vec::new(),
+            carry_end: 0,
+        }
+    }
+
+    pub(crate) fn set_chunk_size(&mut self, chunk_size: usize) {
+        self.chunk_size = chunk_size.max(1);
+    }
+
+    // Address of the data, the number of bytes carried over from the previous
+    // chunk and the data itself. None when every range is done.
+    pub(crate) fn next_chunk(&mut self) -> Option<(usize, usize, &[u8])> {
+        loop {
+            let (address, end) = *self.ranges.front()?;
+            let keep = self.overlap.min(self.buffer.len());
+            self.buffer.drain(..self.buffer.len() - keep);
+            if self.carry_end != address {
+                self.buffer.clear();
+            }
+            let carried = self.buffer.len();
+            let len = (end - address).min(self.chunk_size);
+            let read = self.read_chunk(address, len);
+
+            // Past the page that failed to read
+            let next = match read < len {
+                true => ((address + read) | (PAGE_SIZE - 1)) + 1,
+                false => address + len,
+            };
+            if next >= end {
+                self.ranges.pop_front();
+            } else {
+                self.ranges[0].0 = next;
+            }
+            if read > 0 {
+                self.carry_end = address + read;
+                return Some((address - carried, carried, &self.buffer));
+            }
+        }
+    }
+
+    // Appends [address, address + len) to the carried bytes. When the whole
+    // chunk can't be read the pages are read one by one up to the first
+    // unreadable one. Returns the number of bytes read.
+    fn read_chunk(&mut self, address: usize, len: usize) -> usize {
+        let carried = self.buffer.len();
+        self.buffer.resize(carried + len, 0);
+        if self
+            .reader
+            .read_bytes(address, &mut self.buffer[carried..])
+            .is_ok()
+        {
+            return len;
+        }
+        let mut read = 0;
+        while read < len {
+            let page_end = ((address + read) | (PAGE_SIZE - 1)) + 1;
+            let size = (page_end - address - read).min(len - read);
+            let at = carried + read;
+            let page = &mut self.buffer[at..at + size];
+            if self.reader.read_bytes(address + read, page).is_err() {
+                break;
+            }
+            read += size;
+        }
+        self.buffer.truncate(carried + read);
+        read
+    }
+}
+
+// Matches of a pattern in remote memory, read lazily one chunk at a time. The
+// last `len - 1` bytes of a chunk are searched again with the next one, so a
+// match across two chunks is found once.
+pub struct PatternScan<'a, R: MemoryReader> {
+    pattern: &'a Pattern,
+    chunks: Chunks<'a, R>,
+    pending: VecDeque<usize>,
+}
+
+impl<'a, R: MemoryReader> PatternScan<'a, R> {
+    // `ranges` are [start, end) pairs in ascending order
+    pub fn new(reader: &'a R, pattern: &'a Pattern, ranges: Vec<(usize, usize)>) -> Self {
+        PatternScan {
+            pattern,
+            chunks: Chunks::new(reader, ranges, pattern.len() - 1),
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunks.set_chunk_size(chunk_size);
+        self
+    }
+}
+
+impl<R: MemoryReader> Iterator for PatternScan<'_, R> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        loop {
+            if let Some(address) = self.pending.pop_front() {
+                return Some(address);
+            }
+            let (base, _, data) = self.chunks.next_chunk()?;
+            let pending = &mut self.pending;
+            self.pattern.search_with(data, |offset| {
+                pending.push_back(base + offset);
+                true
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::error::ProcessError;
+    use crate::memory::{MemoryBuffer, MemoryInfo, MemoryReader};
+    use crate::pattern::Pattern;
+    use crate::pattern_scan::PatternScan;
+    use crate::process::Module;
+    use anyhow::Result;
+    use std::cell::Cell;
+
+    // Fails every read that touches [hole, hole + 0x1000) and counts reads
+    pub struct HoleReader {
+        pub buffer: MemoryBuffer,
+        pub hole: usize,
+        pub reads: Cell<usize>,
+    }
+
+    impl MemoryReader for HoleReader {
+        fn read_bytes(&self, address: usize, buf: &mut [u8]) -> Result<()> {
+            self.reads.set(self.reads.get() + 1);
+            if address < self.hole + 0x1000 && self.hole < address + buf.len() {
+                return Err(ProcessError::ReadMemoryFail(address).into());
+            }
+            self.buffer.read_bytes(address, buf)
+        }
+
+        fn query_memory_info(&self, address: usize) -> Result<MemoryInfo> {
+            self.buffer.query_memory_info(address)
+        }
+
+        fn get_module(&self, name: &str) -> Option<Module> {
+            self.buffer.get_module(name)
+        }
+
+        fn modules(&self) -> Vec<Module> {
+            self.buffer.modules()
+        }
+    }
+
+    #[test]
+    pub fn test_pattern_scan() {
+        let base = 0x10000;
+        let mut memory = vec![0u8; 0x4000];
+        // Across the first two pages, across the hole and right after it
+        memory[0xFFE..0x1002].copy_from_slice(&[0x48, 0x8B, 0x05, 0x41]);
+        memory[0x1FFE..0x2002].copy_from_slice(&[0x48, 0x8B, 0x05, 0x41]);
+        memory[0x3000..0x3004].copy_from_slice(&[0x48, 0x8B, 0x05, 0x41]);
+        memory[0x3FFC..0x4000].copy_from_slice(&[0x48, 0x8B, 0x05, 0x41]);
+        let reader = HoleReader {
+            buffer: MemoryBuffer::new(base, memory),
+            hole: base + 0x2000,
+            reads: Cell::new(0),
+        };
+        let pattern = Pattern::parse("48 8B ?? 4?").unwrap();
+        let ranges = vec![(base, base + 0x4000)];
+
+        for chunk_size in [0x3, 0x800, 0x1000, 0x10000] {
+            let found: Vec<usize> = PatternScan::new(&reader, &pattern, ranges.clone())
+                .with_chunk_size(chunk_size)
+                .collect();
+            assert_eq!(vec![0x10FFE, 0x13000, 0x13FFC], found);
+        }
+
+        // Reading stops at the chunk holding the end of the first match
+        reader.reads.set(0);
+        let mut scan = PatternScan::new(&reader, &pattern, ranges).with_chunk_size(0x1000);
+        assert_eq!(Some(0x10FFE), scan.next());
+        assert_eq!(2, reader.reads.get());
+
+        let found = pattern.scan(&reader.buffer, base + 0x1000, 0x2FFF);
+        assert_eq!(vec![0x11FFE, 0x13000], found.collect::<Vec<usize>>());
+    }
+}
@@ -0,0 +1,312 @@
+use crate::error::PointerChainError;
+use crate::memory::MemoryReader;
+use anyhow::Result;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChainBase {
+    Address(usize),
+    Module(String),
+}
+
+// Cheat Engine style address: `[[DarkSoulsIII.exe+4768E78]+80]+1F90`.
+// Numbers are hex with an optional 0x prefix, module names that are valid hex
+// or contain `+-[]` have to be quoted. `offsets[0]` is added to the base,
+// every following offset is added after reading the pointer at the address so far.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointerChain {
+    base: ChainBase,
+    offsets: Vec<isize>,
+}
+
+impl PointerChain {
+    pub fn address(address: usize) -> PointerChain {
+        PointerChain {
+            base: ChainBase::Address(address),
+            offsets: vec![0],
+        }
+    }
+
+    pub fn module(name: &str, offset: isize) -> PointerChain {
+        PointerChain {
+            base: ChainBase::Module(name.to_string()),
+            offsets: vec![offset],
+        }
+    }
+
+    pub fn parse(expression: &str) -> Result<PointerChain> {
+        let mut parser = Parser {
+            input: expression,
+            pos: 0,
+        };
+        let chain = parser.chain()?;
+        parser.skip_whitespace();
+        if parser.pos < expression.len() {
+            return Err(parser.error("unexpected character"));
+        }
+        Ok(chain)
+    }
+
+    // Adds to the address without reading, wrapping like the resolve does
+    pub fn offset(mut self, offset: isize) -> PointerChain {
+        let last = self.offsets.last_mut().unwrap();
+        *last = last.wrapping_add(offset);
+        self
+    }
+
+    // Reads the pointer at the current address, then adds `offset`
+    pub fn deref(mut self, offset: isize) -> PointerChain {
+        self.offsets.push(offset);
+        self
+    }
+
+    pub fn base(&self) -> &ChainBase {
+        &self.base
+    }
+
+    pub fn offsets(&self) -> &[isize] {
+        self.offsets.as_slice()
+    }
+
+    pub fn resolve<R: MemoryReader>(&self, reader: &R) -> Result<usize> {
+        let base = match &self.base {
+            ChainBase::Address(address) => *address,
+            ChainBase::Module(name) => {
+                reader
+                    .get_module(name)
+                    .ok_or_else(|| PointerChainError::ModuleNotFound(name.clone()))?
+                    .base
+            }
+        };
+        let mut address = base.wrapping_add_signed(self.offsets[0]);
+        for (hop, offset) in self.offsets[1..].iter().enumerate() {
+            let pointer =
+                reader
+                    .read::<usize>(address)
+                    .map_err(|_| PointerChainError::ReadFail {
+                        hop: hop + 1,
+                        address,
+                    })?;
+            if pointer == 0 {
+                return Err(PointerChainError::NullPointer {
+                    hop: hop + 1,
+                    address,
+                }
+                .into());
+            }
+            address = pointer.wrapping_add_signed(*offset);
+        }
+        Ok(address)
+    }
+
+    pub fn read<T: Copy>(&self, reader: &impl MemoryReader) -> Result<T> {
+        let address = self.resolve(reader)?;
+        reader.read::<T>(address).map_err(|_| {
+            PointerChainError::ReadFail {
+                hop: self.offsets.len(),
+                address,
+            }
+            .into()
+        })
+    }
+}
+
+impl FromStr for PointerChain {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<PointerChain> {
+        PointerChain::parse(s)
+    }
+}
+
+fn write_offset(text: &mut String, offset: isize) {
+    if offset > 0 {
+        text.push_str(&format!("+{:X}", offset));
+    } else if offset < 0 {
+        text.push_str(&format!("-{:X}", offset.unsigned_abs()));
+    }
+}
+
+impl fmt::Display for PointerChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut text = match &self.base {
+            ChainBase::Address(address) => format!("{:X}", address),
+            ChainBase::Module(name) if is_plain_name(name) => name.clone(),
+            ChainBase::Module(name) => format!("\"{}\"", name),
+        };
+        write_offset(&mut text, self.offsets[0]);
+        for offset in &self.offsets[1..] {
+            text = format!("[{}]", text);
+            write_offset(&mut text, *offset);
+        }
+        f.write_str(&text)
+    }
+}
+
+fn is_token_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, '+' | '-' | '[' | ']' | '"')
+}
+
+fn parse_hex(token: &str) -> Option<usize> {
+    let digits = token
+        .strip_prefix("0x")
+        .or_else(|| token.strip_prefix("0X"))
+        .unwrap_or(token);
+    usize::from_str_radix(digits, 16).ok()
+}
+
+fn is_plain_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(is_token_char) && parse_hex(name).is_none()
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, reason: &str) -> anyhow::Error {
+        PointerChainError::Parse {
+            expression: self.input.to_string(),
+            position: self.pos,
+            reason: reason.to_string(),
+        }
+        .into()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek().filter(|c| c.is_whitespace()) {
+            self.pos += c.len_utf8();
+        }
+    }
+
+    fn token(&mut self) -> &str {
+        let start = self.pos;
+        while let Some(c) = self.peek().filter(|c| is_token_char(*c)) {
+            self.pos += c.len_utf8();
+        }
+        &self.input[start..self.pos]
+    }
+
+    fn chain(&mut self) -> Result<PointerChain> {
+        self.skip_whitespace();
+        let mut chain = match self.peek() {
+            Some('[') => {
+                self.pos += 1;
+                let inner = self.chain()?;
+                self.skip_whitespace();
+                if self.peek() != Some(']') {
+                    return Err(self.error("expected ']'"));
+                }
+                self.pos += 1;
+                inner.deref(0)
+            }
+            Some('"') => {
+                self.pos += 1;
+                let end = self.input[self.pos..]
+                    .find('"')
+                    .ok_or_else(|| self.error("unterminated module name"))?;
+                let name = &self.input[self.pos..self.pos + end];
+                self.pos += end + 1;
+                PointerChain::module(name, 0)
+            }
+            _ => {
+                let token = self.token();
+                if token.is_empty() {
+                    return Err(self.error("expected address or module"));
+                }
+                match parse_hex(token) {
+                    Some(address) => PointerChain::address(address),
+                    None => PointerChain::module(token, 0),
+                }
+            }
+        };
+        loop {
+            self.skip_whitespace();
+            let negative = match self.peek() {
+                Some('+') => false,
+                Some('-') => true,
+                _ => return Ok(chain),
+            };
+            self.pos += 1;
+            self.skip_whitespace();
+            let offset = parse_hex(self.token()).ok_or_else(|| self.error("expected offset"))?;
+            let offset = match negative {
+                false => isize::try_from(offset).ok(),
+                true => 0isize.checked_sub_unsigned(offset),
+            };
+            let last = *chain.offsets().last().unwrap();
+            let offset = offset
+                .filter(|offset| last.checked_add(*offset).is_some())
+                .ok_or_else(|| self.error("offset out of range"))?;
+            chain = chain.offset(offset);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::memory::MemoryBuffer;
+    use crate::pointer::{ChainBase, PointerChain};
+
+    #[test]
+    pub fn test_pointer_chain_parse() {
+        let chain = PointerChain::parse("[[DarkSoulsIII.exe+4768E78]+80]+1F90").unwrap();
+        assert_eq!(
+            &ChainBase::Module("DarkSoulsIII.exe".to_string()),
+            chain.base()
+        );
+        assert_eq!(&[0x4768E78, 0x80, 0x1F90], chain.offsets());
+        assert_eq!("[[DarkSoulsIII.exe+4768E78]+80]+1F90", chain.to_string());
+        assert_eq!(
+            PointerChain::module("DarkSoulsIII.exe", 0x4768E78)
+                .deref(0x80)
+                .deref(0x1F90),
+            chain
+        );
+
+        let chain = PointerChain::parse(" [ 0x7FF4AD045A58 - 10 ] ").unwrap();
+        assert_eq!(&ChainBase::Address(0x7FF4AD045A58), chain.base());
+        assert_eq!(&[-0x10, 0], chain.offsets());
+        assert_eq!("[7FF4AD045A58-10]", chain.to_string());
+        assert_eq!("\"abc\"+8", PointerChain::module("abc", 8).to_string());
+
+        assert!(PointerChain::parse("[DarkSoulsIII.exe+80").is_err());
+        assert!(PointerChain::parse("DarkSoulsIII.exe+xyz").is_err());
+        assert!(PointerChain::parse("DarkSoulsIII.exe]").is_err());
+        assert!(PointerChain::parse("DarkSoulsIII.exe-8000000000000001").is_err());
+        assert!(PointerChain::parse("DarkSoulsIII.exe+7FFFFFFFFFFFFFFF+1").is_err());
+        let chain = PointerChain::parse("DarkSoulsIII.exe-8000000000000000").unwrap();
+        assert_eq!(&[isize::MIN], chain.offsets());
+    }
+
+    #[test]
+    pub fn test_pointer_chain_resolve() {
+        let base = 0x10000usize;
+        let mut data = vec![0u8; 0x100];
+        data[0x08..0x10].copy_from_slice(&(base + 0x40).to_le_bytes());
+        data[0x50..0x58].copy_from_slice(&(base + 0x80).to_le_bytes());
+        data[0x90..0x94].copy_from_slice(&1337u32.to_le_bytes());
+        let buffer = MemoryBuffer::new(base, data).with_name("DarkSoulsIII.exe");
+
+        let chain = PointerChain::parse("[[DarkSoulsIII.exe+8]+10]+10").unwrap();
+        assert_eq!(base + 0x90, chain.resolve(&buffer).unwrap());
+        assert_eq!(1337, chain.read::<u32>(&buffer).unwrap());
+
+        // Second hop reads 0x10040 + 0x18 = 0x10058, which holds a null pointer
+        let error = PointerChain::parse("[[DarkSoulsIII.exe+8]+18]")
+            .unwrap()
+            .resolve(&buffer)
+            .unwrap_err();
+        assert_eq!("Null Pointer At Hop 2! Address: 0x10058", error.to_string());
+        assert!(PointerChain::parse("notepad.exe+8")
+            .unwrap()
+            .resolve(&buffer)
+            .is_err());
+    }
+}
@@ -0,0 +1,367 @@
+use crate::error::PointerMapError;
+use crate::memory::{MemoryReader, RegionFilter};
+use crate::pattern_scan::Chunks;
+use crate::pointer::{ChainBase, PointerChain};
+use crate::process::Module;
+use anyhow::Result;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const POINTER_MAP_MAGIC: &[u8; 8] = b"DS3PMAP\0";
+const POINTER_MAP_VERSION: u32 = 1;
+const CHUNK_SIZE: usize = 0x100000;
+
+#[derive(Debug, Copy, Clone)]
+pub struct PointerScanOptions {
+    // Pointers followed from the static base to the target
+    pub max_depth: usize,
+    // Largest offset added after each pointer
+    pub max_offset: usize,
+    pub max_results: usize,
+    // Addresses carried to the next level, the rest are dropped
+    pub max_frontier: usize,
+}
+
+impl Default for PointerScanOptions {
+    fn default() -> Self {
+        PointerScanOptions {
+            max_depth: 5,
+            max_offset: 0x1000,
+            max_results: 100000,
+            max_frontier: 1000000,
+        }
+    }
+}
+
+// Every aligned 8 byte value in readable memory that points into readable
+// memory, with the module list of the session it was taken from.
+#[derive(Debug, Clone, Default)]
+pub struct PointerMap {
+    modules: Vec<Module>,
+    // (address, value) sorted by address
+    pointers: Vec<(usize, usize)>,
+    // Indices into `pointers` sorted by value
+    by_value: Vec<usize>,
+}
+
+impl PointerMap {
+    pub fn build<R: MemoryReader>(reader: &R) -> PointerMap {
+        let filter = RegionFilter {
+            readable: true,
+            ..Default::default()
+        };
+        let regions: Vec<(usize, usize)> = reader
+            .regions(filter)
+            .map(|region| (region.info.base, region.info.end()))
+            .collect();
+        let is_valid = |value: usize| {
+            let index = regions.partition_point(|(base, _)| *base <= value);
+            index > 0 && value < regions[index - 1].1
+        };
+
+        let mut pointers = Vec::new();
+        // An unreadable page only drops the pointers in it
+        let mut chunks = Chunks::new(reader, regions.clone(), 0);
+        chunks.set_chunk_size(CHUNK_SIZE);
+        while let Some((base, _, data)) = chunks.next_chunk() {
+            let first = (8 - base % 8) % 8;
+            for (i, bytes) in data.get(first..).unwrap_or(&[]).chunks_exact(8).enumerate() {
+                let value = usize::from_le_bytes(bytes.try_into().unwrap());
+                if is_valid(value) {
+                    pointers.push((base + first + i * 8, value));
+                }
+            }
+        }
+        PointerMap::from_parts(reader.modules(), pointers)
+    }
+
+    fn from_parts(modules: Vec<Module>, mut pointers: Vec<(usize, usize)>) -> PointerMap {
+        pointers.sort_unstable();
+        let mut by_value: Vec<usize> = (0..pointers.len()).collect();
+        by_value.sort_unstable_by_key(|i| pointers[*i].1);
+        PointerMap {
+            modules,
+            pointers,
+            by_value,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pointers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pointers.is_empty()
+    }
+
+    pub fn modules(&self) -> &[Module] {
+        self.modules.as_slice()
+    }
+
+    pub fn pointer_at(&self, address: usize) -> Option<usize> {
+        self.pointers
+            .binary_search_by_key(&address, |(address, _)| *address)
+            .ok()
+            .map(|i| self.pointers[i].1)
+    }
+
+    fn module_at(&self, address: usize) -> Option<&Module> {
+        self.modules
+            .iter()
+            .find(|m| m.base <= address && address < m.base + m.size)
+    }
+
+    // Addresses holding a pointer into [low, high]
+    fn pointing_into(&self, low: usize, high: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let start = self.by_value.partition_point(|i| self.pointers[*i].1 < low);
+        self.by_value[start..]
+            .iter()
+            .map(|i| self.pointers[*i])
+            .take_while(move |(_, value)| *value <= high)
+    }
+
+    // Paths that start in a module and end at `target`, searched backwards
+    // from the target. Shorter paths come first.
+    pub fn scan(&self, target: usize, options: &PointerScanOptions) -> Vec<PointerChain> {
+        let mut results = Vec::new();
+        // Offsets from the current address to the target, last hop first
+        let mut level = vec![(target, Vec::new())];
+        for _ in 0..options.max_depth {
+            let mut next = Vec::new();
+            // Each address is expanded once per level, by its first path
+            let mut seen = HashSet::new();
+            for (address, offsets) in &level {
+                let low = address.saturating_sub(options.max_offset);
+                for (pointer, value) in self.pointing_into(low, *address) {
+                    let mut offsets: Vec<usize> = offsets.clone();
+                    offsets.push(address - value);
+                    if let Some(module) = self.module_at(pointer) {
+                        let mut chain =
+                            PointerChain::module(&module.name, (pointer - module.base) as isize);
+                        for offset in offsets.iter().rev() {
+                            chain = chain.deref(*offset as isize);
+                        }
+                        results.push(chain);
+                        if results.len() >= options.max_results {
+                            return results;
+                        }
+                    } else if next.len() < options.max_frontier && seen.insert(pointer) {
+                        next.push((pointer, offsets));
+                    }
+                }
+            }
+            level = next;
+        }
+        results
+    }
+
+    // Resolves `chain` with the pointers of this map instead of a live process
+    pub fn resolve(&self, chain: &PointerChain) -> Option<usize> {
+        let base = match chain.base() {
+            ChainBase::Address(address) => *address,
+            ChainBase::Module(name) => self.modules.iter().find(|m| &m.name == name)?.base,
+        };
+        let offsets = chain.offsets();
+        let mut address = base.wrapping_add_signed(offsets[0]);
+        for offset in &offsets[1..] {
+            address = self.pointer_at(address)?.wrapping_add_signed(*offset);
+        }
+        Some(address)
+    }
+
+    // Keeps the paths that still lead to `target` in this map, for a map
+    // taken after the game restarted
+    pub fn rescan(&self, chains: &[PointerChain], target: usize) -> Vec<PointerChain> {
+        chains
+            .iter()
+            .filter(|chain| self.resolve(chain) == Some(target))
+            .cloned()
+            .collect()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        self.write_to(&mut file)?;
+        file.flush()?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<PointerMap> {
+        PointerMap::read_from(BufReader::new(File::open(path)?))
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        writer.write_all(POINTER_MAP_MAGIC)?;
+        writer.write_all(&POINTER_MAP_VERSION.to_le_bytes())?;
+        let mut w = ZlibEncoder::new(writer, Compression::default());
+
+        w.write_all(&(self.modules.len() as u32).to_le_bytes())?;
+        for module in &self.modules {
+            for text in [&module.name, &module.path] {
+                w.write_all(&(text.len() as u32).to_le_bytes())?;
+                w.write_all(text.as_bytes())?;
+            }
+            w.write_all(&(module.base as u64).to_le_bytes())?;
+            w.write_all(&(module.size as u64).to_le_bytes())?;
+        }
+
+        w.write_all(&(self.pointers.len() as u64).to_le_bytes())?;
+        for (address, value) in &self.pointers {
+            w.write_all(&(*address as u64).to_le_bytes())?;
+            w.write_all(&(*value as u64).to_le_bytes())?;
+        }
+        w.finish()?;
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(mut reader: R) -> Result<PointerMap> {
+        let mut magic = [0u8; 8];
+        reader
+            .read_exact(&mut magic)
+            .map_err(|_| PointerMapError::InvalidMagic)?;
+        if &magic != POINTER_MAP_MAGIC {
+            return Err(PointerMapError::InvalidMagic.into());
+        }
+        let version = u32::from_le_bytes(read_array(&mut reader)?);
+        if version != POINTER_MAP_VERSION {
+            return Err(PointerMapError::UnsupportedVersion(version).into());
+        }
+        let mut r = ZlibDecoder::new(reader);
+
+        let mut modules = Vec::new();
+        for _ in 0..u32::from_le_bytes(read_array(&mut r)?) {
+            let name = read_string(&mut r)?;
+            let path = read_string(&mut r)?;
+            modules.push(Module {
+                name,
+                path,
+                base: u64::from_le_bytes(read_array(&mut r)?) as usize,
+                size: u64::from_le_bytes(read_array(&mut r)?) as usize,
+            });
+        }
+
+        let count = u64::from_le_bytes(read_array(&mut r)?) as usize;
+        let mut pointers = Vec::new();
+        for _ in 0..count {
+            let address = u64::from_le_bytes(read_array(&mut r)?) as usize;
+            let value = u64::from_le_bytes(read_array(&mut r)?) as usize;
+            pointers.push((address, value));
+        }
+        Ok(PointerMap::from_parts(modules, pointers))
+    }
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    reader
+        .read_exact(&mut buf)
+        .map_err(|e| PointerMapError::Corrupted(e.to_string()))?;
+    Ok(buf)
+}
+
+fn read_string<R: Read>(reader: &mut R) -> Result<String> {
+    let len = u32::from_le_bytes(read_array(reader)?) as usize;
+    let mut buf = vec![0u8; len];
+    reader
+        .read_exact(&mut buf)
+        .map_err(|e| PointerMapError::Corrupted(e.to_string()))?;
+    Ok(
+        String::from_utf8(buf)
+            .map_err(|_| PointerMapError::Corrupted("module name".to_string()))?,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::memory::MemoryBuffer;
+    use crate::pattern_scan::test::HoleReader;
+    use crate::pointer::PointerChain;
+    use crate::pointer_scan::{PointerMap, PointerScanOptions};
+    use crate::process::Module;
+    use std::cell::Cell;
+
+    fn map(pointers: &[(usize, usize)]) -> PointerMap {
+        let module = Module {
+            name: "DarkSoulsIII.exe".to_string(),
+            path: "DarkSoulsIII.exe".to_string(),
+            base: 0x140000000,
+            size: 0x1000,
+        };
+        PointerMap::from_parts(vec![module], pointers.to_vec())
+    }
+
+    #[test]
+    pub fn test_pointer_map_build() {
+        let base = 0x10000usize;
+        let mut data = vec![0u8; 0x3000];
+        data[0x10..0x18].copy_from_slice(&(base + 0x100).to_le_bytes());
+        data[0x120..0x128].copy_from_slice(&(base + 0x200).to_le_bytes());
+        data[0x200..0x208].copy_from_slice(&0x12345678usize.to_le_bytes());
+        data[0x1008..0x1010].copy_from_slice(&(base + 0x100).to_le_bytes());
+        data[0x2010..0x2018].copy_from_slice(&(base + 0x2000).to_le_bytes());
+        let buffer = MemoryBuffer::new(base, data).with_name("DarkSoulsIII.exe");
+
+        let map = PointerMap::build(&buffer);
+        assert_eq!(4, map.len());
+        assert_eq!(Some(base + 0x200), map.pointer_at(base + 0x120));
+
+        let mut file = Vec::new();
+        map.write_to(&mut file).unwrap();
+        let loaded = PointerMap::read_from(file.as_slice()).unwrap();
+        assert_eq!(4, loaded.len());
+        assert_eq!(Some(base + 0x100), loaded.pointer_at(base + 0x10));
+        assert_eq!("DarkSoulsIII.exe", loaded.modules()[0].name);
+        assert!(PointerMap::read_from(&file[1..]).is_err());
+
+        // A bad page in the same chunk keeps the pointers around it
+        let holed = HoleReader {
+            buffer,
+            hole: base + 0x1000,
+            reads: Cell::new(0),
+        };
+        let map = PointerMap::build(&holed);
+        assert_eq!(3, map.len());
+        assert_eq!(None, map.pointer_at(base + 0x1008));
+        assert_eq!(Some(base + 0x2000), map.pointer_at(base + 0x2010));
+    }
+
+    #[test]
+    pub fn test_pointer_scan() {
+        let target = 0x30000230;
+        let first = map(&[
+            (0x140000010, 0x20000100),
+            (0x20000120, 0x30000200),
+            // Points near the target but nothing static leads to it
+            (0x20000500, 0x30000200),
+        ]);
+        let options = PointerScanOptions {
+            max_depth: 3,
+            max_offset: 0x100,
+            max_results: 100,
+            ..Default::default()
+        };
+        let chains = first.scan(target, &options);
+        let expected = PointerChain::parse("[[DarkSoulsIII.exe+10]+20]+30").unwrap();
+        assert_eq!(vec![expected.clone()], chains);
+        assert_eq!(Some(target), first.resolve(&expected));
+
+        // After a restart the object moved but the path still holds
+        let second = map(&[(0x140000010, 0x20000800), (0x20000820, 0x30000200)]);
+        assert_eq!(vec![expected], second.rescan(&chains, target));
+        let broken = map(&[(0x140000010, 0x20000800), (0x20000820, 0x30000900)]);
+        assert!(broken.rescan(&chains, target).is_empty());
+
+        // Two heap pointers into each other's range, every level finds both
+        let cycle = map(&[(0x20000100, 0x20000100), (0x20000108, 0x20000100)]);
+        let options = PointerScanOptions {
+            max_depth: 40,
+            ..options
+        };
+        assert!(cycle.scan(0x20000108, &options).is_empty());
+    }
+}
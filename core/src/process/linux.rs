@@ -0,0 +1,381 @@
+use crate::error::ProcessError;
+use crate::memory::{
+    MemoryAllocator, MemoryInfo, MemoryReader, MemoryWriter, MEM_COMMIT, MEM_IMAGE, MEM_MAPPED,
+    MEM_PRIVATE, PAGE_EXECUTE, PAGE_EXECUTE_READ, PAGE_EXECUTE_READWRITE, PAGE_NOACCESS,
+    PAGE_READONLY, PAGE_READWRITE,
+};
+use crate::process::{Architecture, Module, ProcessInfo};
+
+use anyhow::Result;
+
+use std::fs::{File, OpenOptions};
+use std::io::Read;
+use std::os::unix::fs::FileExt;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone)]
+pub struct Process {
+    pub id: u32,
+    pub is_wow64: bool,
+    mem: Option<Arc<File>>,
+}
+
+// One line of /proc/<pid>/maps
+#[derive(Debug, Clone)]
+pub(crate) struct MapEntry {
+    pub start: usize,
+    pub end: usize,
+    pub read: bool,
+    pub write: bool,
+    pub exec: bool,
+    pub offset: usize,
+    pub path: String,
+}
+
+impl MapEntry {
+    pub fn protect(&self) -> u32 {
+        match (self.read, self.write, self.exec) {
+            (_, true, true) => PAGE_EXECUTE_READWRITE,
+            (true, false, true) => PAGE_EXECUTE_READ,
+            (false, false, true) => PAGE_EXECUTE,
+            (_, true, false) => PAGE_READWRITE,
+            (true, false, false) => PAGE_READONLY,
+            (false, false, false) => PAGE_NOACCESS,
+        }
+    }
+
+    fn is_file(&self) -> bool {
+        self.path.starts_with('/')
+    }
+
+    fn memory_info(&self, modules: &[Module]) -> MemoryInfo {
+        let image = modules
+            .iter()
+            .find(|m| m.base <= self.start && self.start < m.base + m.size);
+        let mem_type = if image.is_some() {
+            MEM_IMAGE
+        } else if self.is_file() {
+            MEM_MAPPED
+        } else {
+            MEM_PRIVATE
+        };
+        MemoryInfo {
+            base: self.start,
+            allocation_base: image.map(|m| m.base).unwrap_or(self.start),
+            allocation_protect: self.protect(),
+            size: self.end - self.start,
+            state: MEM_COMMIT,
+            protect: self.protect(),
+            mem_type,
+        }
+    }
+}
+
+// Wine reports windows paths in argv[0] and keeps the unix path in maps,
+// so take the last component with either separator.
+fn file_name(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+fn boot_time() -> Option<SystemTime> {
+    let stat = std::fs::read_to_string("/proc/stat").ok()?;
+    let line = stat.lines().find(|line| line.starts_with("btime "))?;
+    let seconds = line["btime ".len()..].trim().parse::<u64>().ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+impl Process {
+    pub fn current_process() -> Option<Process> {
+        Process::from_pid(std::process::id())
+    }
+
+    pub fn from_pid(pid: u32) -> Option<Process> {
+        let proc_dir = format!("/proc/{}", pid);
+        if !std::path::Path::new(&proc_dir).exists() {
+            return None;
+        }
+
+        let mem_path = format!("{}/mem", proc_dir);
+        let mem = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&mem_path)
+            .or_else(|_| File::open(&mem_path))
+            .ok()
+            .map(Arc::new);
+
+        Some(Process {
+            id: pid,
+            is_wow64: Self::architecture(pid) == Architecture::X86,
+            mem,
+        })
+    }
+
+    pub fn list() -> Vec<ProcessInfo> {
+        let boot_time = boot_time();
+        let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) }.max(1) as u64;
+        let mut processes = Vec::new();
+        let entries = match std::fs::read_dir("/proc") {
+            Ok(entries) => entries,
+            Err(_) => return processes,
+        };
+        for entry in entries.flatten() {
+            let pid = match entry.file_name().to_string_lossy().parse::<u32>() {
+                Ok(pid) => pid,
+                Err(_) => continue,
+            };
+            let (parent_pid, start_ticks) = match Self::stat(pid) {
+                Some(stat) => stat,
+                None => continue,
+            };
+            let argv0 = Self::argv0(pid);
+            let path = match &argv0 {
+                Some(argv0) if argv0.contains('\\') => argv0.clone(),
+                _ => std::fs::read_link(format!("/proc/{}/exe", pid))
+                    .map(|p| p.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+            };
+            let name = match &argv0 {
+                Some(argv0) => file_name(argv0).to_string(),
+                None => std::fs::read_to_string(format!("/proc/{}/comm", pid))
+                    .map(|s| s.trim_end().to_string())
+                    .unwrap_or_default(),
+            };
+            processes.push(ProcessInfo {
+                pid,
+                parent_pid,
+                name,
+                path,
+                arch: Self::architecture(pid),
+                start_time: boot_time
+                    .map(|boot| boot + Duration::from_millis(start_ticks * 1000 / ticks)),
+                window_title: None,
+            });
+        }
+        processes
+    }
+
+    // Wine reports the windows path of the exe as argv[0]
+    fn argv0(pid: u32) -> Option<String> {
+        let cmdline = std::fs::read(format!("/proc/{}/cmdline", pid)).ok()?;
+        let argv0 = cmdline.split(|b| *b == 0).next()?;
+        if argv0.is_empty() {
+            return None;
+        }
+        Some(String::from_utf8_lossy(argv0).to_string())
+    }
+
+    // Parent pid and start time in clock ticks after boot
+    fn stat(pid: u32) -> Option<(u32, u64)> {
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+        // comm may contain spaces and parentheses, the fields start after the last ')'
+        let fields: Vec<&str> = stat[stat.rfind(')')? + 1..].split_whitespace().collect();
+        Some((fields.get(1)?.parse().ok()?, fields.get(19)?.parse().ok()?))
+    }
+
+    // ELF class of the host binary, for Wine this is the preloader
+    fn architecture(pid: u32) -> Architecture {
+        let mut ident = [0u8; 5];
+        let read = File::open(format!("/proc/{}/exe", pid))
+            .and_then(|mut exe| exe.read_exact(&mut ident))
+            .is_ok();
+        match ident[4] {
+            1 if read => Architecture::X86,
+            2 if read => Architecture::X64,
+            _ => Architecture::Unknown,
+        }
+    }
+
+    pub(crate) fn maps(&self) -> Result<Vec<MapEntry>> {
+        let maps = std::fs::read_to_string(format!("/proc/{}/maps", self.id))
+            .map_err(|_| ProcessError::ProcessNotFound(self.id.to_string()))?;
+        let mut entries = Vec::new();
+        for line in maps.lines() {
+            let mut fields = line.splitn(6, ' ');
+            let range = fields.next().unwrap_or("");
+            let perms = fields.next().unwrap_or("").as_bytes();
+            let offset = fields.next().unwrap_or("0");
+            let _dev = fields.next();
+            let _inode = fields.next();
+            let path = fields.next().unwrap_or("").trim_start();
+            let (start, end) = match range.split_once('-') {
+                Some((start, end)) => (start, end),
+                None => continue,
+            };
+            if perms.len() < 3 {
+                continue;
+            }
+            entries.push(MapEntry {
+                start: usize::from_str_radix(start, 16)?,
+                end: usize::from_str_radix(end, 16)?,
+                read: perms[0] == b'r',
+                write: perms[1] == b'w',
+                exec: perms[2] == b'x',
+                offset: usize::from_str_radix(offset, 16)?,
+                path: path.to_string(),
+            });
+        }
+        Ok(entries)
+    }
+
+    pub(crate) fn modules_from_maps(&self, maps: &[MapEntry]) -> Vec<Module> {
+        let mut modules: Vec<Module> = Vec::new();
+        for (i, entry) in maps.iter().enumerate() {
+            if !entry.is_file() || entry.offset != 0 {
+                continue;
+            }
+            if modules
+                .iter()
+                .any(|m| m.base <= entry.start && entry.start < m.base + m.size)
+            {
+                continue;
+            }
+            // PE images mapped by Wine carry their own size, sections are often
+            // anonymous copies so the file mapping alone does not cover them.
+            let size = match self.pe_image_size(entry.start) {
+                Some(size) => size,
+                None => {
+                    let mut end = entry.end;
+                    for next in &maps[i + 1..] {
+                        if next.path != entry.path || next.start != end {
+                            break;
+                        }
+                        end = next.end;
+                    }
+                    end - entry.start
+                }
+            };
+            modules.push(Module {
+                name: file_name(&entry.path).to_string(),
+                path: entry.path.clone(),
+                base: entry.start,
+                size,
+            });
+        }
+        modules
+    }
+
+    fn pe_image_size(&self, base: usize) -> Option<usize> {
+        if self.read::<u16>(base).ok()? != 0x5A4D {
+            return None;
+        }
+        let nt_headers = base + self.read::<u32>(base + 0x3C).ok()? as usize;
+        if self.read::<u32>(nt_headers).ok()? != 0x4550 {
+            return None;
+        }
+        // OptionalHeader.SizeOfImage, same offset for PE32 and PE32+
+        Some(self.read::<u32>(nt_headers + 0x18 + 0x38).ok()? as usize)
+    }
+
+    fn read_vm(&self, address: usize, buf: &mut [u8]) -> bool {
+        let local = libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+        let remote = libc::iovec {
+            iov_base: address as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+        let read = unsafe { libc::process_vm_readv(self.id as _, &local, 1, &remote, 1, 0) };
+        read == buf.len() as isize
+    }
+
+    fn write_vm(&self, address: usize, buf: &[u8]) -> bool {
+        let local = libc::iovec {
+            iov_base: buf.as_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+        let remote = libc::iovec {
+            iov_base: address as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+        let written = unsafe { libc::process_vm_writev(self.id as _, &local, 1, &remote, 1, 0) };
+        written == buf.len() as isize
+    }
+}
+
+impl MemoryReader for Process {
+    fn read_bytes(&self, address: usize, buf: &mut [u8]) -> Result<()> {
+        if buf.is_empty() || self.read_vm(address, buf) {
+            return Ok(());
+        }
+        match &self.mem {
+            Some(mem) if mem.read_exact_at(buf, address as u64).is_ok() => Ok(()),
+            _ => Err(ProcessError::ReadMemoryFail(address).into()),
+        }
+    }
+
+    fn query_memory_info(&self, address: usize) -> Result<MemoryInfo> {
+        let maps = self.maps()?;
+        let modules = self.modules_from_maps(&maps);
+        let mut free_base = 0;
+        for entry in &maps {
+            if address < entry.start {
+                return Ok(MemoryInfo::free(free_base, entry.start));
+            }
+            if address < entry.end {
+                return Ok(entry.memory_info(&modules));
+            }
+            free_base = entry.end;
+        }
+        Err(ProcessError::QueryMemoryFail(address).into())
+    }
+
+    fn get_module(&self, name: &str) -> Option<Module> {
+        self.modules().into_iter().find(|m| m.name == name)
+    }
+
+    fn modules(&self) -> Vec<Module> {
+        match self.maps() {
+            Ok(maps) => self.modules_from_maps(&maps),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    // One pass over maps instead of re-reading it for every region
+    fn memory_regions(&self) -> Vec<MemoryInfo> {
+        let maps = match self.maps() {
+            Ok(maps) => maps,
+            Err(_) => return Vec::new(),
+        };
+        let modules = self.modules_from_maps(&maps);
+        let mut regions = Vec::new();
+        let mut free_base = 0;
+        for entry in &maps {
+            if free_base < entry.start {
+                regions.push(MemoryInfo::free(free_base, entry.start));
+            }
+            regions.push(entry.memory_info(&modules));
+            free_base = entry.end;
+        }
+        regions
+    }
+}
+
+impl MemoryWriter for Process {
+    // /proc/<pid>/mem ignores page protection, process_vm_writev does not
+    fn write_bytes(&self, address: usize, buf: &[u8]) -> Result<()> {
+        if let Some(mem) = &self.mem {
+            if mem.write_all_at(buf, address as u64).is_ok() {
+                return Ok(());
+            }
+        }
+        if !self.write_vm(address, buf) {
+            return Err(ProcessError::WriteMemoryFail(address).into());
+        }
+        Ok(())
+    }
+}
+
+// Changing another process' mappings needs code running inside it,
+// which this backend does not do.
+impl MemoryAllocator for Process {
+    fn alloc(&self, _address: Option<usize>, size: usize, _protection: u32) -> Result<usize> {
+        Err(ProcessError::AllocMemoryFail(size).into())
+    }
+
+    fn free(&self, address: usize) -> Result<()> {
+        Err(ProcessError::FreeMemoryFail(address).into())
+    }
+}
@@ -0,0 +1,597 @@
+use crate::error::{ProcessError, ShMemQError};
+use crate::memory::{MemoryAllocator, MemoryInfo, MemoryReader, MemoryWriter};
+use crate::process::{Architecture, Module, ProcessInfo};
+
+use anyhow::Result;
+
+use std::collections::HashMap;
+use std::env::temp_dir;
+use std::fs::OpenOptions;
+use std::io::ErrorKind;
+use std::os::windows::fs::OpenOptionsExt;
+use std::os::windows::io::AsRawHandle;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{mem, ptr};
+use winapi::shared::basetsd::SIZE_T;
+use winapi::shared::minwindef::{
+    BOOL, DWORD, FALSE, FILETIME, LPARAM, LPCVOID, LPVOID, MAX_PATH, PBOOL, PDWORD, TRUE,
+};
+use winapi::shared::ntdef::HANDLE;
+use winapi::shared::windef::HWND;
+
+use winapi::um::handleapi::{CloseHandle, DuplicateHandle, INVALID_HANDLE_VALUE};
+use winapi::um::memoryapi::{
+    CreateFileMappingW, MapViewOfFile, OpenFileMappingW, ReadProcessMemory, UnmapViewOfFile,
+    VirtualAllocEx, VirtualFreeEx, VirtualProtectEx, VirtualQueryEx, WriteProcessMemory,
+    FILE_MAP_ALL_ACCESS,
+};
+use winapi::um::processthreadsapi::{
+    GetCurrentProcess, GetCurrentProcessId, GetProcessTimes, OpenProcess,
+};
+use winapi::um::tlhelp32::{
+    CreateToolhelp32Snapshot, Module32FirstW, Module32NextW, Process32FirstW, Process32NextW,
+    MODULEENTRY32W, PROCESSENTRY32W, TH32CS_SNAPMODULE, TH32CS_SNAPMODULE32, TH32CS_SNAPPROCESS,
+};
+use winapi::um::winbase::QueryFullProcessImageNameW;
+use winapi::um::winnt::{
+    DUPLICATE_SAME_ACCESS, FILE_ATTRIBUTE_TEMPORARY, FILE_SHARE_DELETE, FILE_SHARE_READ,
+    FILE_SHARE_WRITE, MEMORY_BASIC_INFORMATION, MEM_COMMIT, MEM_RELEASE, MEM_RESERVE,
+    PAGE_READWRITE, PROCESS_ALL_ACCESS, PROCESS_QUERY_LIMITED_INFORMATION,
+};
+use winapi::um::winuser::{EnumWindows, GetWindowTextW, GetWindowThreadProcessId, IsWindowVisible};
+use winapi::um::wow64apiset::IsWow64Process;
+
+#[derive(Debug)]
+pub struct Process {
+    pub id: u32,
+    pub is_wow64: bool,
+    handle: HANDLE,
+}
+
+// Every Process owns its handle, clones duplicate it so each one closes its
+// own. The handle is only passed to Win32 calls that are safe from any
+// thread, which lets scan workers share one Process.
+unsafe impl Send for Process {}
+unsafe impl Sync for Process {}
+
+impl Process {
+    pub fn current_process() -> Option<Process> {
+        unsafe { Process::from_pid(GetCurrentProcessId()) }
+    }
+    pub fn from_pid(pid: u32) -> Option<Process> {
+        let handle = unsafe { OpenProcess(PROCESS_ALL_ACCESS, 0, pid) };
+        if handle.is_null() {
+            return None;
+        }
+
+        let mut tmp: BOOL = 0;
+        if unsafe { IsWow64Process(handle, &mut tmp as PBOOL) } == FALSE {
+            return None;
+        }
+
+        let is_wow64 = match tmp {
+            FALSE => false,
+            _ => true,
+        };
+
+        Some(Process {
+            id: pid,
+            is_wow64,
+            handle,
+        })
+    }
+
+    pub fn list() -> Vec<ProcessInfo> {
+        let mut processes = Vec::new();
+        let handle = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) };
+
+        if handle == INVALID_HANDLE_VALUE {
+            return processes;
+        }
+
+        let titles = window_titles();
+        let mut pe: PROCESSENTRY32W = unsafe { mem::zeroed() };
+        pe.dwSize = mem::size_of::<PROCESSENTRY32W>() as u32;
+        if unsafe { Process32FirstW(handle, &mut pe) } != FALSE {
+            loop {
+                let (path, arch, start_time) = query_process(pe.th32ProcessID);
+                processes.push(ProcessInfo {
+                    pid: pe.th32ProcessID,
+                    parent_pid: pe.th32ParentProcessID,
+                    name: String::from_utf16_lossy(&pe.szExeFile)
+                        .trim_matches('\0')
+                        .to_string(),
+                    path,
+                    arch,
+                    start_time,
+                    window_title: titles.get(&pe.th32ProcessID).cloned(),
+                });
+
+                if unsafe { Process32NextW(handle, &mut pe) } == FALSE {
+                    break;
+                }
+            }
+        }
+        unsafe { CloseHandle(handle) };
+        processes
+    }
+
+    pub fn open_shmemq(&self, _name: &str, _create: bool, _size: usize) -> Result<()> {
+        Ok(())
+    }
+}
+
+// Image path, architecture and creation time, empty for processes we may not open
+fn query_process(pid: u32) -> (String, Architecture, Option<SystemTime>) {
+    let handle = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, pid) };
+    if handle.is_null() {
+        return (String::new(), Architecture::Unknown, None);
+    }
+
+    let mut buf = [0u16; MAX_PATH * 2];
+    let mut len = buf.len() as DWORD;
+    let path = match unsafe { QueryFullProcessImageNameW(handle, 0, buf.as_mut_ptr(), &mut len) } {
+        FALSE => String::new(),
+        _ => String::from_utf16_lossy(&buf[..len as usize]),
+    };
+
+    let mut wow64: BOOL = FALSE;
+    let arch = match unsafe { IsWow64Process(handle, &mut wow64) } {
+        FALSE => Architecture::Unknown,
+        _ if wow64 != FALSE => Architecture::X86,
+        _ => Architecture::X64,
+    };
+
+    let mut creation: FILETIME = unsafe { mem::zeroed() };
+    let mut exit: FILETIME = unsafe { mem::zeroed() };
+    let mut kernel: FILETIME = unsafe { mem::zeroed() };
+    let mut user: FILETIME = unsafe { mem::zeroed() };
+    let start_time = match unsafe {
+        GetProcessTimes(handle, &mut creation, &mut exit, &mut kernel, &mut user)
+    } {
+        FALSE => None,
+        // 100ns ticks since 1601-01-01
+        _ => (((creation.dwHighDateTime as u64) << 32 | creation.dwLowDateTime as u64) / 10)
+            .checked_sub(11_644_473_600_000_000)
+            .map(|micros| UNIX_EPOCH + Duration::from_micros(micros)),
+    };
+
+    unsafe { CloseHandle(handle) };
+    (path, arch, start_time)
+}
+
+// Title of the first visible top level window of each process
+fn window_titles() -> HashMap<u32, String> {
+    unsafe extern "system" fn callback(hwnd: HWND, lparam: LPARAM) -> BOOL {
+        let titles = &mut *(lparam as *mut HashMap<u32, String>);
+        if IsWindowVisible(hwnd) == FALSE {
+            return TRUE;
+        }
+        let mut pid: DWORD = 0;
+        GetWindowThreadProcessId(hwnd, &mut pid);
+        let mut buf = [0u16; 256];
+        let len = GetWindowTextW(hwnd, buf.as_mut_ptr(), buf.len() as i32);
+        if len > 0 {
+            titles
+                .entry(pid)
+                .or_insert_with(|| String::from_utf16_lossy(&buf[..len as usize]));
+        }
+        TRUE
+    }
+
+    let mut titles = HashMap::new();
+    unsafe {
+        EnumWindows(
+            Some(callback),
+            &mut titles as *mut HashMap<u32, String> as LPARAM,
+        )
+    };
+    titles
+}
+
+impl Clone for Process {
+    fn clone(&self) -> Self {
+        let mut handle: HANDLE = ptr::null_mut();
+        // A failed duplicate leaves a null handle, reads on it fail
+        unsafe {
+            DuplicateHandle(
+                GetCurrentProcess(),
+                self.handle,
+                GetCurrentProcess(),
+                &mut handle,
+                0,
+                FALSE,
+                DUPLICATE_SAME_ACCESS,
+            )
+        };
+        Process {
+            id: self.id,
+            is_wow64: self.is_wow64,
+            handle,
+        }
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        if !self.handle.is_null() {
+            unsafe { CloseHandle(self.handle) };
+        }
+    }
+}
+
+impl MemoryReader for Process {
+    fn read_bytes(&self, address: usize, buf: &mut [u8]) -> Result<()> {
+        match unsafe {
+            ReadProcessMemory(
+                self.handle,
+                address as LPCVOID,
+                buf.as_mut_ptr() as LPVOID,
+                buf.len() as SIZE_T,
+                ptr::null_mut::<SIZE_T>(),
+            )
+        } {
+            FALSE => Err(ProcessError::ReadMemoryFail(address).into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn query_memory_info(&self, address: usize) -> Result<MemoryInfo> {
+        unsafe {
+            let mut information = mem::zeroed::<MEMORY_BASIC_INFORMATION>();
+            if VirtualQueryEx(
+                self.handle,
+                address as LPCVOID,
+                &mut information,
+                std::mem::size_of::<MEMORY_BASIC_INFORMATION>() as SIZE_T,
+            ) != 0
+            {
+                Ok(MemoryInfo {
+                    base: information.BaseAddress as usize,
+                    allocation_base: information.AllocationBase as usize,
+                    allocation_protect: information.AllocationProtect,
+                    size: information.RegionSize,
+                    state: information.State,
+                    protect: information.Protect,
+                    mem_type: information.Type,
+                })
+            } else {
+                Err(ProcessError::QueryMemoryFail(address).into())
+            }
+        }
+    }
+
+    fn get_module(&self, name: &str) -> Option<Module> {
+        self.modules().into_iter().find(|m| m.name == name)
+    }
+
+    fn modules(&self) -> Vec<Module> {
+        let mut modules = Vec::new();
+        let handle =
+            unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPMODULE | TH32CS_SNAPMODULE32, self.id) };
+
+        if handle == INVALID_HANDLE_VALUE {
+            return modules;
+        }
+        let mut me: MODULEENTRY32W = unsafe { mem::zeroed() };
+        me.dwSize = mem::size_of::<MODULEENTRY32W>() as u32;
+
+        if unsafe { Module32FirstW(handle, &mut me) } != FALSE {
+            loop {
+                modules.push(Module {
+                    name: String::from_utf16_lossy(&me.szModule)
+                        .trim_matches('\0')
+                        .to_string(),
+                    path: String::from_utf16_lossy(&me.szExePath)
+                        .trim_matches('\0')
+                        .to_string(),
+                    base: me.modBaseAddr as usize,
+                    size: me.modBaseSize as usize,
+                });
+
+                if unsafe { Module32NextW(handle, &mut me) } == FALSE {
+                    break;
+                }
+            }
+        }
+        unsafe { CloseHandle(handle) };
+        modules
+    }
+}
+
+impl MemoryWriter for Process {
+    fn write_bytes(&self, address: usize, buf: &[u8]) -> Result<()> {
+        let written = unsafe {
+            WriteProcessMemory(
+                self.handle,
+                address as LPVOID,
+                buf.as_ptr() as LPCVOID,
+                buf.len() as SIZE_T,
+                ptr::null_mut::<SIZE_T>(),
+            )
+        };
+        if written == FALSE {
+            return Err(ProcessError::WriteMemoryFail(address).into());
+        }
+        Ok(())
+    }
+
+    fn protect(&self, address: usize, size: usize, protection: u32) -> Result<u32> {
+        let mut old: DWORD = 0;
+        let changed = unsafe {
+            VirtualProtectEx(
+                self.handle,
+                address as LPVOID,
+                size,
+                protection,
+                &mut old as PDWORD,
+            )
+        };
+        if changed == FALSE {
+            return Err(ProcessError::ProtectMemoryFail(address).into());
+        }
+        Ok(old)
+    }
+}
+
+impl MemoryAllocator for Process {
+    fn alloc(&self, address: Option<usize>, size: usize, protection: u32) -> Result<usize> {
+        let buffer = unsafe {
+            VirtualAllocEx(
+                self.handle,
+                address.map_or(ptr::null_mut(), |address| address as LPVOID),
+                size,
+                MEM_RESERVE | MEM_COMMIT,
+                protection,
+            )
+        };
+        if buffer.is_null() {
+            return Err(ProcessError::AllocMemoryFail(size).into());
+        }
+        Ok(buffer as usize)
+    }
+
+    fn free(&self, address: usize) -> Result<()> {
+        let freed = unsafe { VirtualFreeEx(self.handle, address as LPVOID, 0, MEM_RELEASE) };
+        if freed == FALSE {
+            return Err(ProcessError::FreeMemoryFail(address).into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct ShareMemMq<'a> {
+    name: String,
+    meta: &'a mut ShareMemMqMeta,
+    data: &'a mut [u8],
+
+    // Handles
+    map: HANDLE,
+    buf: LPVOID,
+}
+
+#[repr(packed)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ShareMemMqMeta {
+    size: usize,
+    r_index: usize,
+    w_index: usize,
+    head_size: usize,
+}
+
+impl<'a> ShareMemMq<'a> {
+    pub fn open_or_new(name: &str, size: usize) -> Result<Self> {
+        let file_path = temp_dir().join(name);
+        let is_new = !file_path.exists();
+        let persistent_file = match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .share_mode(FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE)
+            .create_new(is_new)
+            .attributes(FILE_ATTRIBUTE_TEMPORARY)
+            .open(&file_path)
+        {
+            Ok(f) => f,
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => return Err(ShMemQError::FileExists(file_path).into()) ,
+            Err(e) => return Err(ShMemQError::CreateFile(file_path,e.raw_os_error().unwrap() as _).into()),
+        };
+
+        let buf_size = std::mem::size_of::<ShareMemMqMeta>() + size;
+        let map_file = unsafe {
+            CreateFileMappingW(
+                persistent_file.as_raw_handle() as _,
+                std::ptr::null_mut(),
+                PAGE_READWRITE,
+                0,
+                buf_size as DWORD,
+                name.as_ptr() as *mut u16,
+            )
+        };
+        if map_file.is_null() {
+            return Err(ShMemQError::CreateFileMapping(file_path).into());
+        }
+
+        let buf = unsafe { MapViewOfFile(map_file, FILE_MAP_ALL_ACCESS, 0, 0, buf_size as SIZE_T) };
+
+        if buf.is_null() {
+            unsafe { CloseHandle(map_file) };
+            return Err(ShMemQError::CreateFileMapping(file_path.clone()).into());
+        }
+
+        let meta = unsafe { &mut *buf.cast::<ShareMemMqMeta>() };
+        if is_new {
+            meta.size = buf_size;
+            meta.head_size = std::mem::size_of::<ShareMemMqMeta>();
+            meta.r_index = 0;
+            meta.w_index = 0;
+        }
+
+        let data_ptr = unsafe { buf.cast::<u8>().add(std::mem::size_of::<ShareMemMqMeta>()) };
+        let data = unsafe {
+            &mut *std::ptr::slice_from_raw_parts_mut(&mut *data_ptr, meta.size - meta.head_size)
+        };
+        Ok(Self {
+            name: name.to_string(),
+            meta,
+            map: map_file,
+            buf,
+            data,
+        })
+    }
+
+    pub fn peek(&self) -> Option<&[u8]> {
+        let data_size = self.meta.size - self.meta.head_size;
+        let remain_read_bytes = self.meta.w_index - self.meta.r_index;
+        if self.meta.r_index == self.meta.w_index || remain_read_bytes <= std::mem::size_of::<u16>()
+        {
+            None
+        } else {
+            unsafe {
+                let ptr_to_el = self.data.as_ptr().add(self.meta.r_index % data_size) as *const u16;
+                let el_size = ptr_to_el.read();
+                if (el_size as usize) > remain_read_bytes {
+                    None
+                } else {
+                    let ptr = self
+                        .data
+                        .as_ptr()
+                        .add((self.meta.r_index % data_size) + std::mem::size_of::<u16>())
+                        as *mut u8;
+                    Some(&mut *std::ptr::slice_from_raw_parts_mut(
+                        ptr,
+                        el_size as usize - std::mem::size_of::<u16>(),
+                    ))
+                }
+            }
+        }
+    }
+
+    pub fn dequeue(&mut self) -> Result<Vec<Vec<u8>>> {
+        let data_size = self.meta.size - self.meta.head_size;
+        let mut remain_read_bytes = self.meta.w_index - self.meta.r_index;
+        let mut result = Vec::new();
+        while self.meta.r_index < self.meta.w_index
+            && remain_read_bytes > std::mem::size_of::<u16>()
+        {
+            unsafe {
+                let ptr_to_el = self.data.as_ptr().add(self.meta.r_index % data_size) as *const u16;
+                let el_size = ptr_to_el.read();
+                if (el_size as usize) > remain_read_bytes
+                    || (el_size as usize <= std::mem::size_of::<u16>())
+                {
+                    return Err(ShMemQError::InvalidMq.into());
+                } else {
+                    let ptr = self
+                        .data
+                        .as_ptr()
+                        .add((self.meta.r_index % data_size) + std::mem::size_of::<u16>())
+                        as *mut u8;
+                    let el = &*std::ptr::slice_from_raw_parts_mut(
+                        ptr,
+                        el_size as usize - std::mem::size_of::<u16>(),
+                    );
+                    self.meta.r_index = self.meta.r_index + el_size as usize;
+                    result.push(el.to_vec());
+                }
+            }
+            remain_read_bytes = self.meta.w_index - self.meta.r_index;
+        }
+        Ok(result)
+    }
+
+    pub fn enqueue(&mut self, elements: &[Vec<u8>]) -> Result<()> {
+        let data_size = self.meta.size - self.meta.head_size;
+        let remain_bytes = self.meta.size - (self.meta.w_index - self.meta.r_index);
+        let mut size_of = 0;
+        for el in elements {
+            size_of += el.len() + std::mem::size_of::<u16>();
+        }
+        if size_of > remain_bytes {
+            return Err(ShMemQError::HasFull.into());
+        }
+        for el in elements {
+            let idx = self.meta.w_index % data_size;
+            unsafe {
+                let ptr_to_size = self.data.as_ptr().add(idx) as *mut u16;
+                ptr_to_size.write((el.len() + std::mem::size_of::<u16>()) as u16);
+                let ptr_to_el = self.data.as_ptr().add(idx + std::mem::size_of::<u16>()) as *mut u8;
+                let new_el = &mut *std::ptr::slice_from_raw_parts_mut(ptr_to_el, el.len() as usize);
+                new_el.copy_from_slice(el.as_slice());
+                self.meta.w_index = self.meta.w_index + el.len() + std::mem::size_of::<u16>();
+            }
+        }
+        Ok(())
+    }
+
+    fn temp_file_path<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        std::env::temp_dir().join(path)
+    }
+}
+
+impl<'a> Drop for ShareMemMq<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            UnmapViewOfFile(self.buf as LPCVOID);
+            CloseHandle(self.map);
+        };
+    }
+}
+
+mod test {
+    use crate::process::win32::{ShareMemMq, ShareMemMqMeta};
+
+    #[test]
+    pub fn test_share_memory_queue() {
+        let mut mq = ShareMemMq::open_or_new("test_memory_queue", 4890).unwrap();
+        assert_eq!(4890 + std::mem::size_of::<ShareMemMqMeta>(), mq.meta.size);
+        assert_eq!(0, mq.meta.r_index);
+        assert_eq!(0, mq.meta.w_index);
+        assert_eq!(None, mq.peek());
+
+        let mut data_list = Vec::new();
+        let mut el_sizes = 0;
+        for i in 0..1000 {
+            let bytes = i.to_string().into_bytes();
+            el_sizes += bytes.len() + std::mem::size_of::<u16>();
+            data_list.push(bytes);
+        }
+        mq.enqueue(data_list.as_slice()).unwrap();
+
+        assert_eq!(0, mq.meta.r_index);
+        assert_eq!(el_sizes, mq.meta.w_index);
+        assert_eq!(el_sizes, mq.meta.w_index - mq.meta.r_index);
+
+        let dequeue_data_list = mq.dequeue().unwrap();
+        assert_eq!(data_list, dequeue_data_list);
+        assert_eq!(mq.meta.r_index, el_sizes);
+        assert_eq!(mq.meta.w_index, el_sizes);
+        assert_eq!(None, mq.peek());
+    }
+
+    #[test]
+    pub fn test_share_memory_queue2() {
+        let mut mq = ShareMemMq::open_or_new("test_memory_queue", 4890).unwrap();
+        let mut data_list = Vec::new();
+        let mut el_sizes = 0;
+        for i in 0..500 {
+            let bytes = i.to_string().into_bytes();
+            el_sizes += bytes.len() + std::mem::size_of::<u16>();
+            data_list.push(bytes);
+        }
+        mq.enqueue(data_list.as_slice()).unwrap();
+        assert_eq!(mq.dequeue().unwrap(), data_list);
+
+        let mut data_list2 = Vec::new();
+        let mut el_sizes2 = 0;
+        for i in 0..1000 {
+            let bytes = i.to_string().into_bytes();
+            el_sizes2 += bytes.len() + std::mem::size_of::<u16>();
+            data_list2.push(bytes);
+        }
+        mq.enqueue(data_list2.as_slice()).unwrap();
+        assert_eq!(mq.dequeue().unwrap(), data_list2);
+    }
+}
+
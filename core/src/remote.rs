@@ -0,0 +1,204 @@
+use crate::error::RemoteError;
+use crate::memory::MemoryReader;
+pub use anyhow::Result;
+pub use core_derive::RemoteStruct;
+use std::fmt;
+use std::marker::PhantomData;
+
+// Structs read field by field from a remote address instead of mirroring the
+// whole layout with padding. Derive it and put the offset on every field:
+//
+//     #[derive(RemoteStruct)]
+//     pub struct ChrStats {
+//         #[offset(0x0)]
+//         pub hp: u32,
+//         #[offset(0x8)]
+//         #[deref(0x18)]           // [[address+8]+18]
+//         pub flags: u32,
+//         #[offset(0x44)]
+//         #[utf16(16)]             // [u16; 16] up to the first NUL
+//         pub name: String,
+//         #[offset(0x2C)]
+//         #[nested]                // another RemoteStruct at address+2C
+//         pub attributes: ChrAttributes,
+//         #[skip]                  // Default::default()
+//         pub cached: usize,
+//     }
+//
+// Arrays and any other Copy type are read as is. The derive also implements
+// Display, and serde::Serialize with `#[remote(serde)]`. Inside this crate use
+// `#[remote(crate = "crate")]`.
+pub trait RemoteStruct: Sized {
+    fn read_remote<R: MemoryReader>(reader: &R, address: usize) -> Result<Self>;
+}
+
+// A pointer into the target, `T` is the type it points at. Same layout as a
+// 64 bit pointer so it can be read as part of other values and struct fields.
+#[repr(transparent)]
+pub struct RemotePtr<T> {
+    address: usize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> RemotePtr<T> {
+    pub fn new(address: usize) -> RemotePtr<T> {
+        RemotePtr {
+            address,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn null() -> RemotePtr<T> {
+        RemotePtr::new(0)
+    }
+
+    pub fn address(&self) -> usize {
+        self.address
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.address == 0
+    }
+
+    // Fails on null instead of reading page zero
+    pub fn checked(&self) -> Result<usize> {
+        if self.is_null() {
+            return Err(RemoteError::NullPointer(std::any::type_name::<T>()).into());
+        }
+        Ok(self.address)
+    }
+
+    // Pointer to the `index`th element of an array starting here
+    pub fn add(&self, index: usize) -> RemotePtr<T> {
+        RemotePtr::new(self.address + index * std::mem::size_of::<T>())
+    }
+
+    pub fn cast<U>(&self) -> RemotePtr<U> {
+        RemotePtr::new(self.address)
+    }
+}
+
+impl<T: Copy> RemotePtr<T> {
+    pub fn read<R: MemoryReader>(&self, reader: &R) -> Result<T> {
+        reader.read::<T>(self.checked()?)
+    }
+
+    pub fn read_array<R: MemoryReader>(&self, reader: &R, count: usize) -> Result<Vec<T>> {
+        let address = if count == 0 {
+            self.address
+        } else {
+            self.checked()?
+        };
+        let mut values = vec![unsafe { std::mem::zeroed::<T>() }; count];
+        reader.read_ptr(values.as_mut_ptr(), address, count)?;
+        Ok(values)
+    }
+}
+
+impl<T: RemoteStruct> RemotePtr<T> {
+    pub fn read_remote<R: MemoryReader>(&self, reader: &R) -> Result<T> {
+        T::read_remote(reader, self.checked()?)
+    }
+}
+
+// Pointer to a pointer
+impl<T> RemotePtr<RemotePtr<T>> {
+    pub fn deref<R: MemoryReader>(&self, reader: &R) -> Result<RemotePtr<T>> {
+        let pointer = reader.read::<RemotePtr<T>>(self.checked()?)?;
+        pointer.checked()?;
+        Ok(pointer)
+    }
+}
+
+// Derives would require `T: Clone` and friends
+impl<T> Clone for RemotePtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for RemotePtr<T> {}
+
+impl<T> Default for RemotePtr<T> {
+    fn default() -> Self {
+        RemotePtr::null()
+    }
+}
+
+impl<T> PartialEq for RemotePtr<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.address == other.address
+    }
+}
+
+impl<T> Eq for RemotePtr<T> {}
+
+impl<T> fmt::Debug for RemotePtr<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RemotePtr({:#x})", self.address)
+    }
+}
+
+impl<T> fmt::Display for RemotePtr<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:X}", self.address)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::memory::MemoryBuffer;
+    use crate::remote::RemoteStruct;
+
+    #[derive(RemoteStruct, Debug, Default)]
+    #[remote(crate = "crate")]
+    struct Inner {
+        #[offset(0x4)]
+        value: u16,
+    }
+
+    #[derive(RemoteStruct, Debug)]
+    #[remote(crate = "crate")]
+    struct Outer {
+        #[offset(0x0)]
+        hp: u32,
+        #[offset(0x4)]
+        array: [u8; 4],
+        #[offset(0x8)]
+        #[deref(0x10)]
+        pointed: u32,
+        #[offset(0x20)]
+        #[utf16(8)]
+        name: String,
+        #[offset(0x30)]
+        #[nested]
+        inner: Inner,
+        #[skip]
+        skipped: usize,
+    }
+
+    #[test]
+    pub fn test_remote_struct() {
+        let base = 0x10000usize;
+        let mut data = vec![0u8; 0x100];
+        data[0x00..0x04].copy_from_slice(&1337u32.to_le_bytes());
+        data[0x04..0x08].copy_from_slice(&[1, 2, 3, 4]);
+        data[0x08..0x10].copy_from_slice(&(base + 0x80).to_le_bytes());
+        data[0x90..0x94].copy_from_slice(&42u32.to_le_bytes());
+        for (i, c) in "Ashen".encode_utf16().enumerate() {
+            data[0x20 + i * 2..0x22 + i * 2].copy_from_slice(&c.to_le_bytes());
+        }
+        data[0x34..0x36].copy_from_slice(&7u16.to_le_bytes());
+        let buffer = MemoryBuffer::new(base, data);
+
+        let outer = Outer::read_remote(&buffer, base).unwrap();
+        assert_eq!(1337, outer.hp);
+        assert_eq!([1, 2, 3, 4], outer.array);
+        assert_eq!(42, outer.pointed);
+        assert_eq!("Ashen", outer.name);
+        assert_eq!(7, outer.inner.value);
+        assert_eq!(0, outer.skipped);
+        assert_eq!("value: 7\n", outer.inner.to_string());
+        assert!(Outer::read_remote(&buffer, base + 0xF0).is_err());
+    }
+}
@@ -0,0 +1,373 @@
+use crate::error::ScanError;
+use crate::memory::MemoryReader;
+use crate::pattern::Pattern;
+use crate::pattern_scan::{readable_ranges, Chunks};
+use crate::signature_set::{SignatureMatches, SignatureSet};
+use anyhow::Result;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+pub const DEFAULT_JOB_SIZE: usize = 0x100000;
+
+// Shared flag the workers check before each job
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct ScanProgress {
+    // Bytes for region scans, items for ScanExecutor::map
+    pub done: usize,
+    pub total: usize,
+    pub hits: usize,
+}
+
+impl ScanProgress {
+    pub fn fraction(&self) -> f32 {
+        match self.total {
+            0 => 0.0,
+            total => self.done as f32 / total as f32,
+        }
+    }
+}
+
+type ProgressCallback = Arc<dyn Fn(ScanProgress) + Send + Sync>;
+
+// Runs scans on a bounded number of threads. Memory is split into jobs of
+// `job_size` bytes, the progress callback is called from the workers after
+// every job and a cancelled scan returns ScanError::Cancelled.
+#[derive(Clone)]
+pub struct ScanExecutor {
+    threads: usize,
+    job_size: usize,
+    cancel: CancelToken,
+    progress: Option<ProgressCallback>,
+}
+
+impl Default for ScanExecutor {
+    fn default() -> Self {
+        ScanExecutor::new()
+    }
+}
+
+impl ScanExecutor {
+    pub fn new() -> ScanExecutor {
+        ScanExecutor {
+            threads: num_cpus::get(),
+            job_size: DEFAULT_JOB_SIZE,
+            cancel: CancelToken::new(),
+            progress: None,
+        }
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> ScanExecutor {
+        self.threads = threads.max(1);
+        self
+    }
+
+    pub fn with_job_size(mut self, job_size: usize) -> ScanExecutor {
+        self.job_size = job_size.max(1);
+        self
+    }
+
+    pub fn with_cancel(mut self, cancel: CancelToken) -> ScanExecutor {
+        self.cancel = cancel;
+        self
+    }
+
+    pub fn with_progress<F: Fn(ScanProgress) + Send + Sync + 'static>(
+        mut self,
+        progress: F,
+    ) -> ScanExecutor {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    // Every match of `pattern` in the readable memory of [start, start + size),
+    // overlapping ones included, in ascending order
+    pub fn pattern_scan<R: MemoryReader + Sync>(
+        &self,
+        reader: &R,
+        pattern: &Pattern,
+        start: usize,
+        size: usize,
+    ) -> Result<Vec<usize>> {
+        let ranges = readable_ranges(reader, start, start + size);
+        self.scan_ranges(reader, &ranges, pattern.len() - 1, |base, data, len| {
+            let mut found = Vec::new();
+            pattern.search_with(data, |offset| {
+                if offset < len {
+                    found.push(base + offset);
+                }
+                offset < len
+            });
+            found
+        })
+    }
+
+    pub fn signature_scan<R: MemoryReader + Sync>(
+        &self,
+        reader: &R,
+        set: &SignatureSet,
+        start: usize,
+        size: usize,
+    ) -> Result<SignatureMatches> {
+        let ranges = readable_ranges(reader, start, start + size);
+        let overlap = set.max_len().saturating_sub(1);
+        let found = self.scan_ranges(reader, &ranges, overlap, |base, data, len| {
+            let mut found = Vec::new();
+            set.search_with(data, 0, len, |index, offset| {
+                found.push((index, base + offset))
+            });
+            found
+        })?;
+        let mut matches = set.empty_matches();
+        for (index, address) in found {
+            matches.hits[index].push(address);
+        }
+        Ok(matches)
+    }
+
+    // Calls `scan` with the address, the data and the length of every job.
+    // The data runs `overlap` bytes past the job when its range goes on, so
+    // `scan` only returns what starts in the first `len` bytes. The results
+    // are in the order of the jobs.
+    pub fn scan_ranges<R, T, F>(
+        &self,
+        reader: &R,
+        ranges: &[(usize, usize)],
+        overlap: usize,
+        scan: F,
+    ) -> Result<Vec<T>>
+    where
+        R: MemoryReader + Sync,
+        T: Send,
+        F: Fn(usize, &[u8], usize) -> Vec<T> + Sync,
+    {
+        let mut jobs = Vec::new();
+        for (start, end) in ranges {
+            let mut job = *start;
+            while job < *end {
+                let job_end = (job + self.job_size).min(*end);
+                jobs.push((job, job_end, (job_end + overlap).min(*end)));
+                job = job_end;
+            }
+        }
+        self.execute(
+            &jobs,
+            |(start, end, _)| end - start,
+            |&(start, end, read_end)| {
+                // One read, split only around unreadable pages
+                let mut chunks = Chunks::new(reader, vec![(start, read_end)], 0);
+                chunks.set_chunk_size(read_end - start);
+                let mut found = Vec::new();
+                while let Some((base, _, data)) = chunks.next_chunk() {
+                    if base >= end {
+                        break;
+                    }
+                    found.extend(scan(base, data, end - base));
+                }
+                found
+            },
+        )
+    }
+
+    // Runs `work` on every item, the progress counts items
+    pub fn map<I, T, F>(&self, items: &[I], work: F) -> Result<Vec<T>>
+    where
+        I: Sync,
+        T: Send,
+        F: Fn(&I) -> Vec<T> + Sync,
+    {
+        self.execute(items, |_| 1, work)
+    }
+
+    fn execute<J, T, W, F>(&self, jobs: &[J], weight: W, work: F) -> Result<Vec<T>>
+    where
+        J: Sync,
+        T: Send,
+        W: Fn(&J) -> usize + Sync,
+        F: Fn(&J) -> Vec<T> + Sync,
+    {
+        let total = jobs.iter().map(&weight).sum();
+        let next = AtomicUsize::new(0);
+        let done = AtomicUsize::new(0);
+        let hits = AtomicUsize::new(0);
+        let threads = self.threads.min(jobs.len()).max(1);
+        let worker = || {
+            let mut results = Vec::new();
+            while !self.cancel.is_cancelled() {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let job = match jobs.get(index) {
+                    Some(job) => job,
+                    None => break,
+                };
+                let found = work(job);
+                let size = weight(job);
+                let progress = ScanProgress {
+                    done: done.fetch_add(size, Ordering::Relaxed) + size,
+                    total,
+                    hits: hits.fetch_add(found.len(), Ordering::Relaxed) + found.len(),
+                };
+                if let Some(callback) = &self.progress {
+                    callback(progress);
+                }
+                results.push((index, found));
+            }
+            results
+        };
+        let finished = thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads).map(|_| scope.spawn(worker)).collect();
+            workers
+                .into_iter()
+                .map(|worker| worker.join())
+                .collect::<std::result::Result<Vec<_>, _>>()
+        })
+        .map_err(|_| ScanError::WorkerPanicked)?;
+        if self.cancel.is_cancelled() {
+            return Err(ScanError::Cancelled.into());
+        }
+
+        let mut finished: Vec<(usize, Vec<T>)> = finished.into_iter().flatten().collect();
+        finished.sort_by_key(|(index, _)| *index);
+        Ok(finished.into_iter().flat_map(|(_, found)| found).collect())
+    }
+}
+
+// Scan running on its own thread, polled by a UI every frame
+pub struct ScanTask<T> {
+    cancel: CancelToken,
+    progress: Arc<Mutex<ScanProgress>>,
+    handle: JoinHandle<Result<T>>,
+}
+
+impl<T: Send + 'static> ScanTask<T> {
+    pub fn spawn<F>(executor: ScanExecutor, scan: F) -> ScanTask<T>
+    where
+        F: FnOnce(&ScanExecutor) -> Result<T> + Send + 'static,
+    {
+        let progress = Arc::new(Mutex::new(ScanProgress::default()));
+        let shared = progress.clone();
+        let callback = executor.progress.clone();
+        let executor = executor.with_progress(move |update| {
+            // Workers finish out of order, keep the furthest one
+            if let Ok(mut progress) = shared.lock() {
+                if update.done >= progress.done {
+                    *progress = update;
+                }
+            }
+            if let Some(callback) = &callback {
+                callback(update);
+            }
+        });
+        ScanTask {
+            cancel: executor.cancel_token(),
+            progress,
+            handle: thread::spawn(move || scan(&executor)),
+        }
+    }
+
+    pub fn progress(&self) -> ScanProgress {
+        self.progress
+            .lock()
+            .map(|progress| *progress)
+            .unwrap_or_default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    // Waits for the scan to end
+    pub fn join(self) -> Result<T> {
+        self.handle.join().map_err(|_| ScanError::WorkerPanicked)?
+    }
+}
+
+impl<T> fmt::Debug for ScanTask<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScanTask")
+            .field("cancel", &self.cancel)
+            .field("progress", &self.progress)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::memory::MemoryBuffer;
+    use crate::pattern::Pattern;
+    use crate::scan_executor::{ScanExecutor, ScanProgress, ScanTask};
+    use crate::signature_set::SignatureSet;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    pub fn test_scan_executor() {
+        let base = 0x10000;
+        let mut memory = vec![0u8; 0x10000];
+        let mut expected = Vec::new();
+        // Across every job boundary and inside the jobs
+        for offset in (0x3FE..0xFC00).step_by(0x400) {
+            memory[offset..offset + 4].copy_from_slice(&[0x48, 0x8B, 0x05, 0x41]);
+            expected.push(base + offset);
+        }
+        let buffer = MemoryBuffer::new(base, memory);
+        let pattern = Pattern::parse("48 8B ?? 41").unwrap();
+
+        let updates = Arc::new(Mutex::new(Vec::<ScanProgress>::new()));
+        let shared = updates.clone();
+        let executor = ScanExecutor::new()
+            .with_threads(4)
+            .with_job_size(0x800)
+            .with_progress(move |progress| shared.lock().unwrap().push(progress));
+        let found = executor.pattern_scan(&buffer, &pattern, base, 0x10000);
+        assert_eq!(expected, found.unwrap());
+        let updates = updates.lock().unwrap().clone();
+        assert_eq!(0x20, updates.len());
+        let last = updates.iter().max_by_key(|progress| progress.done).unwrap();
+        assert_eq!(
+            (0x10000, 0x10000, expected.len()),
+            (last.done, last.total, last.hits)
+        );
+
+        let set = SignatureSet::parse(&[("Load", "48 8B 05"), ("Zero", "00 00 48")]).unwrap();
+        let matches = executor
+            .signature_scan(&buffer, &set, base, 0x10000)
+            .unwrap();
+        assert_eq!(Some(expected.as_slice()), matches.get("Load"));
+        assert_eq!(expected.len(), matches.get("Zero").unwrap().len());
+
+        let squares = executor.map(&[1, 2, 3, 4, 5], |n| vec![n * n]).unwrap();
+        assert_eq!(vec![1, 4, 9, 16, 25], squares);
+
+        // Cancelled before the first job
+        let task = ScanTask::spawn(ScanExecutor::new(), move |executor| {
+            executor.cancel_token().cancel();
+            executor.pattern_scan(&buffer, &pattern, base, 0x10000)
+        });
+        assert!(task.join().is_err());
+    }
+}
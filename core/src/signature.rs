@@ -0,0 +1,248 @@
+use crate::disasm::MAX_INSTRUCTION_LEN;
+use crate::error::SignatureError;
+use crate::image::{read_module_image, ModuleHeaders};
+use crate::memory::{MemoryReader, PAGE_EXECUTABLE};
+use crate::process::Module;
+use crate::xref::function_start;
+use anyhow::Result;
+use iced_x86::{Decoder, DecoderOptions, Instruction, OpKind};
+
+const MAX_SIGNATURE_LEN: usize = 64;
+// Instructions before the target that are tried as the start of the signature
+const MAX_LEADING_INSTRUCTIONS: usize = 16;
+const MAX_FUNCTION_PREFIX: usize = 0x10000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    // Same syntax as pattern_search: "48 8B 05 ?? ?? ?? ??"
+    pub pattern: String,
+    // Where the pattern matches in the module
+    pub address: usize,
+    // From the match start to the target
+    pub offset: usize,
+}
+
+// Instruction bytes with every relocatable operand marked as a wildcard
+#[derive(Debug, Default)]
+struct MaskedCode {
+    bytes: Vec<u8>,
+    wild: Vec<bool>,
+}
+
+impl MaskedCode {
+    fn pattern(&self, len: usize) -> String {
+        let tokens: Vec<String> = (0..len)
+            .map(|i| match self.wild[i] {
+                true => "??".to_string(),
+                false => format!("{:02X}", self.bytes[i]),
+            })
+            .collect();
+        tokens.join(" ")
+    }
+}
+
+// Shortest pattern that matches once in `module` and covers the instruction
+// at `target`. The pattern may start a few instructions before the target
+// when that makes it shorter; the starts are found by decoding from the
+// function start in .pdata.
+pub fn generate_signature<R: MemoryReader>(
+    reader: &R,
+    module: &Module,
+    target: usize,
+) -> Result<Signature> {
+    let headers = module.headers(reader)?;
+    headers
+        .sections
+        .iter()
+        .find(|s| s.protect & PAGE_EXECUTABLE != 0 && s.base <= target && target < s.base + s.size)
+        .ok_or(SignatureError::NotCode(target))?;
+    let image = read_module_image(reader, module);
+    let target_len = masked_code(&image, module.base, target, 1).bytes.len();
+    if target_len == 0 {
+        return Err(SignatureError::NotCode(target).into());
+    }
+
+    let mut best: Option<(usize, Signature)> = None;
+    for start in leading_instructions(reader, &headers, &image, module.base, target) {
+        let offset = target - start;
+        let min_len = offset + target_len;
+        if best.as_ref().is_some_and(|(len, _)| min_len >= *len) {
+            break;
+        }
+        let code = masked_code(&image, module.base, start, MAX_SIGNATURE_LEN);
+        let len = match unique_len(&image, &code, min_len) {
+            Some(len) => len,
+            None => continue,
+        };
+        if best.as_ref().is_none_or(|(best_len, _)| len < *best_len) {
+            let signature = Signature {
+                pattern: code.pattern(len),
+                address: start,
+                offset,
+            };
+            best = Some((len, signature));
+        }
+    }
+    best.map(|(_, signature)| signature)
+        .ok_or_else(|| SignatureError::NotUnique(target).into())
+}
+
+// Decodes from `address` until `max_len` bytes are covered, wildcarding rel32
+// branches, RIP-relative and absolute displacements and 64 bit immediates
+fn masked_code(image: &[u8], base: usize, address: usize, max_len: usize) -> MaskedCode {
+    let start = address - base;
+    let end = (start + max_len + MAX_INSTRUCTION_LEN).min(image.len());
+    let mut decoder =
+        Decoder::with_ip(64, &image[start..end], address as u64, DecoderOptions::NONE);
+    let mut instruction = Instruction::default();
+    let mut code = MaskedCode::default();
+    while code.bytes.len() < max_len && decoder.can_decode() {
+        let position = decoder.position();
+        decoder.decode_out(&mut instruction);
+        if instruction.is_invalid() {
+            break;
+        }
+        let offsets = decoder.get_constant_offsets(&instruction);
+        let mut wild = vec![false; instruction.len()];
+        let mut mask = |offset: usize, size: usize| wild[offset..offset + size].fill(true);
+        if offsets.has_displacement()
+            && (instruction.is_ip_rel_memory_operand() || offsets.displacement_size() == 8)
+        {
+            mask(offsets.displacement_offset(), offsets.displacement_size());
+        }
+        if offsets.has_immediate() {
+            let branch = (0..instruction.op_count()).any(|i| {
+                matches!(
+                    instruction.op_kind(i),
+                    OpKind::NearBranch32 | OpKind::NearBranch64
+                )
+            });
+            let size = offsets.immediate_size();
+            if (branch && size == 4) || size == 8 {
+                mask(offsets.immediate_offset(), size);
+            }
+        }
+        let bytes = &image[start + position..start + position + instruction.len()];
+        code.bytes.extend_from_slice(bytes);
+        code.wild.extend(wild);
+    }
+    code
+}
+
+// Instruction starts before `target`, nearest first, the target itself
+// included. Only the target when the function start is unknown or decoding
+// from it doesn't land on the target.
+fn leading_instructions<R: MemoryReader>(
+    reader: &R,
+    headers: &ModuleHeaders,
+    image: &[u8],
+    base: usize,
+    target: usize,
+) -> Vec<usize> {
+    let function = match function_start(reader, headers, target) {
+        Some(function) if target - function <= MAX_FUNCTION_PREFIX => function,
+        _ => return vec![target],
+    };
+    let code = &image[function - base..target - base];
+    let mut decoder = Decoder::with_ip(64, code, function as u64, DecoderOptions::NONE);
+    let mut instruction = Instruction::default();
+    let mut starts = Vec::new();
+    while decoder.can_decode() {
+        starts.push(decoder.ip() as usize);
+        decoder.decode_out(&mut instruction);
+        if instruction.is_invalid() {
+            return vec![target];
+        }
+    }
+    if decoder.ip() as usize != target {
+        return vec![target];
+    }
+    starts.push(target);
+    starts.reverse();
+    starts.truncate(MAX_LEADING_INSTRUCTIONS + 1);
+    starts
+}
+
+// Length of the shortest prefix of `code`, at least `min_len` long, that
+// matches only once in `image`
+fn unique_len(image: &[u8], code: &MaskedCode, min_len: usize) -> Option<usize> {
+    if code.bytes.len() < min_len || code.wild.first() != Some(&false) {
+        return None;
+    }
+    let mut candidates: Vec<u32> = image
+        .iter()
+        .enumerate()
+        .filter(|(_, b)| **b == code.bytes[0])
+        .map(|(i, _)| i as u32)
+        .collect();
+    for len in 1..=code.bytes.len() {
+        if len >= min_len && candidates.len() == 1 {
+            return Some(len);
+        }
+        if len == code.bytes.len() {
+            break;
+        }
+        let (byte, wild) = (code.bytes[len], code.wild[len]);
+        candidates.retain(|c| {
+            image
+                .get(*c as usize + len)
+                .is_some_and(|b| wild || *b == byte)
+        });
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use crate::image::test::{build_pe, put, set_directory};
+    use crate::image::PeImage;
+    use crate::memory::MemoryReader;
+    use crate::pattern::pattern_search;
+    use crate::signature::generate_signature;
+    use pelite::image::IMAGE_DIRECTORY_ENTRY_EXCEPTION;
+
+    #[test]
+    pub fn test_generate_signature() {
+        let mut text = vec![0xCCu8; 0x40];
+        // mov rax, [rip+0x100] ; call 0x140001000 ; ret
+        put(&mut text, 0x00, &[0x48, 0x8B, 0x05, 0x00, 0x01, 0x00, 0x00]);
+        put(&mut text, 0x07, &[0xE8, 0xF4, 0xFF, 0xFF, 0xFF]);
+        put(&mut text, 0x0C, &[0xC3]);
+        // mov rax, [rip+0x200] ; call 0x140001000 ; test rax, rax ; ret
+        put(&mut text, 0x20, &[0x48, 0x8B, 0x05, 0x00, 0x02, 0x00, 0x00]);
+        put(&mut text, 0x27, &[0xE8, 0xD4, 0xFF, 0xFF, 0xFF]);
+        put(&mut text, 0x2C, &[0x48, 0x85, 0xC0, 0xC3]);
+
+        // One RUNTIME_FUNCTION for 0x1020..0x1030
+        let mut rdata = vec![0u8; 0x20];
+        for (i, value) in [0x1020u32, 0x1030, 0x2010].iter().enumerate() {
+            put(&mut rdata, i * 4, &value.to_le_bytes());
+        }
+        put(&mut rdata, 0x10, &[0x01, 0, 0, 0]);
+
+        let mut file = build_pe(&[(".text", &text, 0x60000020), (".rdata", &rdata, 0x40000040)]);
+        set_directory(&mut file, IMAGE_DIRECTORY_ENTRY_EXCEPTION, 0x2000, 12);
+        let image = PeImage::from_bytes("DarkSoulsIII.exe", file.as_slice()).unwrap();
+        let module = image.get_module("DarkSoulsIII.exe").unwrap();
+
+        let signature = generate_signature(&image, &module, 0x140001020).unwrap();
+        assert_eq!("48 8B 05 ?? ?? ?? ?? E8 ?? ?? ?? ?? 48", signature.pattern);
+        assert_eq!(0x140001020, signature.address);
+        assert_eq!(0, signature.offset);
+        let found = pattern_search(
+            signature.pattern,
+            image.as_slice(),
+            false,
+            Some(0x140000000),
+        );
+        assert_eq!(vec![0x140001020], found.unwrap());
+
+        // The ret alone is everywhere, starting at the test before it is shorter
+        let signature = generate_signature(&image, &module, 0x14000102F).unwrap();
+        assert_eq!("48 85 C0 C3", signature.pattern);
+        assert_eq!(0x14000102C, signature.address);
+        assert_eq!(3, signature.offset);
+
+        assert!(generate_signature(&image, &module, 0x140002000).is_err());
+    }
+}
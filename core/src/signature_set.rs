@@ -0,0 +1,247 @@
+use crate::error::SignatureError;
+use crate::memory::MemoryReader;
+use crate::pattern::Pattern;
+use crate::pattern_scan::{readable_ranges, Chunks};
+use crate::process::Module;
+use aho_corasick::AhoCorasick;
+use anyhow::Result;
+use std::collections::HashMap;
+
+const FULL: u8 = 0xFF;
+
+// Named patterns searched together in one pass over the memory. The longest
+// run of fully known bytes of every pattern goes into one Aho-Corasick
+// automaton and the whole pattern is compared where its run is found.
+#[derive(Debug, Clone)]
+pub struct SignatureSet {
+    names: Vec<String>,
+    patterns: Vec<Pattern>,
+    // Signature and offset of the run in it, by automaton pattern id
+    anchors: Vec<(usize, usize)>,
+    automaton: AhoCorasick,
+    // Signatures without a fully known byte, compared at every position
+    unanchored: Vec<usize>,
+    max_len: usize,
+}
+
+impl SignatureSet {
+    pub fn new(signatures: Vec<(String, Pattern)>) -> Result<SignatureSet> {
+        let mut names = Vec::new();
+        let mut patterns = Vec::new();
+        let mut anchors = Vec::new();
+        let mut literals = Vec::new();
+        let mut unanchored = Vec::new();
+        for (index, (name, pattern)) in signatures.into_iter().enumerate() {
+            if names.contains(&name) {
+                return Err(SignatureError::DuplicateName(name).into());
+            }
+            match longest_literal(&pattern) {
+                Some((offset, len)) => {
+                    anchors.push((index, offset));
+                    literals.push(pattern.bytes()[offset..offset + len].to_vec());
+                }
+                None => unanchored.push(index),
+            }
+            names.push(name);
+            patterns.push(pattern);
+        }
+        Ok(SignatureSet {
+            max_len: patterns.iter().map(Pattern::len).max().unwrap_or(0),
+            names,
+            patterns,
+            anchors,
+            automaton: AhoCorasick::new(literals)?,
+            unanchored,
+        })
+    }
+
+    // Name and pattern pairs in any syntax Pattern::parse accepts
+    pub fn parse<S: AsRef<str>>(signatures: &[(S, S)]) -> Result<SignatureSet> {
+        let signatures = signatures
+            .iter()
+            .map(|(name, pattern)| Ok((name.as_ref().to_string(), pattern.as_ref().parse()?)))
+            .collect::<Result<Vec<(String, Pattern)>>>()?;
+        SignatureSet::new(signatures)
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    // Matches in a local buffer that starts at `base`
+    pub fn search(&self, data: &[u8], base: usize) -> SignatureMatches {
+        let mut matches = self.empty_matches();
+        self.search_with(data, 0, data.len(), |index, start| {
+            matches.hits[index].push(base + start)
+        });
+        matches
+    }
+
+    // Reads the readable memory of [start, start + size) once for every
+    // signature
+    pub fn scan<R: MemoryReader>(&self, reader: &R, start: usize, size: usize) -> SignatureMatches {
+        let ranges = readable_ranges(reader, start, start + size);
+        let mut chunks = Chunks::new(reader, ranges, self.max_len.saturating_sub(1));
+        let mut matches = self.empty_matches();
+        while let Some((base, carried, data)) = chunks.next_chunk() {
+            self.search_with(data, carried, data.len(), |index, start| {
+                matches.hits[index].push(base + start)
+            });
+        }
+        matches
+    }
+
+    pub fn scan_module<R: MemoryReader>(&self, reader: &R, module: &Module) -> SignatureMatches {
+        self.scan(reader, module.base, module.size)
+    }
+
+    pub(crate) fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    pub(crate) fn empty_matches(&self) -> SignatureMatches {
+        SignatureMatches {
+            names: self.names.clone(),
+            hits: vec![Vec::new(); self.names.len()],
+        }
+    }
+
+    // Calls `on_match` with the signature index and offset of every match
+    // that starts before `limit`. Matches ending in the first `carried` bytes
+    // were found with the previous chunk and are skipped.
+    pub(crate) fn search_with<F: FnMut(usize, usize)>(
+        &self,
+        data: &[u8],
+        carried: usize,
+        limit: usize,
+        mut on_match: F,
+    ) {
+        for found in self.automaton.find_overlapping_iter(data) {
+            let (index, offset) = self.anchors[found.pattern().as_usize()];
+            let pattern = &self.patterns[index];
+            let start = match found.start().checked_sub(offset) {
+                Some(start) => start,
+                None => continue,
+            };
+            if start < limit && start + pattern.len() > carried && pattern.matches_at(data, start) {
+                on_match(index, start);
+            }
+        }
+        for index in &self.unanchored {
+            let pattern = &self.patterns[*index];
+            pattern.search_with(data, |start| {
+                if start + pattern.len() > carried && start < limit {
+                    on_match(*index, start);
+                }
+                start < limit
+            });
+        }
+    }
+}
+
+// Offset and length of the longest run of fully known bytes
+fn longest_literal(pattern: &Pattern) -> Option<(usize, usize)> {
+    let mut best: Option<(usize, usize)> = None;
+    let mut run_start = 0;
+    for (i, mask) in pattern.masks().iter().chain([&0]).enumerate() {
+        if *mask == FULL {
+            continue;
+        }
+        let len = i - run_start;
+        if len > 0 && best.is_none_or(|(_, best_len)| len > best_len) {
+            best = Some((run_start, len));
+        }
+        run_start = i + 1;
+    }
+    best
+}
+
+// Addresses found for every signature of a set, in ascending order
+#[derive(Debug, Clone, Default)]
+pub struct SignatureMatches {
+    names: Vec<String>,
+    pub(crate) hits: Vec<Vec<usize>>,
+}
+
+impl SignatureMatches {
+    pub fn get(&self, name: &str) -> Option<&[usize]> {
+        let index = self.names.iter().position(|n| n == name)?;
+        Some(&self.hits[index])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[usize])> {
+        self.names
+            .iter()
+            .map(String::as_str)
+            .zip(self.hits.iter().map(Vec::as_slice))
+    }
+
+    // The address of every signature, or an error naming each one that
+    // matched zero or several times
+    pub fn unique(&self) -> Result<HashMap<String, usize>> {
+        let mut addresses = HashMap::new();
+        let mut failed = Vec::new();
+        for (name, hits) in self.iter() {
+            match hits {
+                [address] => {
+                    addresses.insert(name.to_string(), *address);
+                }
+                _ => failed.push(format!("{}: {} Matches", name, hits.len())),
+            }
+        }
+        match failed.is_empty() {
+            true => Ok(addresses),
+            false => Err(SignatureError::NotMatchedOnce(failed.join(", ")).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::memory::MemoryBuffer;
+    use crate::signature_set::SignatureSet;
+
+    #[test]
+    pub fn test_signature_set() {
+        let base = 0x10000;
+        let mut memory = vec![0u8; 0x30000];
+        memory[0x100..0x107].copy_from_slice(&[0x48, 0x8B, 0x05, 0x11, 0x22, 0x33, 0x44]);
+        // Across the first two chunks
+        memory[0xFFFE..0x10003].copy_from_slice(&[0x48, 0x8D, 0x0D, 0x55, 0x66]);
+        memory[0x20000..0x20004].copy_from_slice(&[0xE8, 0x01, 0x02, 0x03]);
+        memory[0x20100..0x20104].copy_from_slice(&[0xE8, 0x01, 0x02, 0x03]);
+        memory[0x20200..0x20202].copy_from_slice(&[0x4C, 0x15]);
+        let buffer = MemoryBuffer::new(base, memory);
+
+        let set = SignatureSet::parse(&[
+            ("GameDataMan", "48 8B 05 ?? ?? ?? 44"),
+            ("WorldChrMan", "48 8D 0D ?? 66"),
+            ("Call", "E8 01 02 03"),
+            ("Missing", "48 8B 05 ?? ?? ?? 45"),
+            ("Nibbles", "4? ?5"),
+        ])
+        .unwrap();
+        assert_eq!(5, set.len());
+        let matches = set.scan(&buffer, base, 0x30000);
+        assert_eq!(Some(&[0x10100][..]), matches.get("GameDataMan"));
+        assert_eq!(Some(&[0x1FFFE][..]), matches.get("WorldChrMan"));
+        assert_eq!(Some(&[0x30000, 0x30100][..]), matches.get("Call"));
+        assert_eq!(Some(&[][..]), matches.get("Missing"));
+        assert_eq!(Some(&[0x30200][..]), matches.get("Nibbles"));
+        assert_eq!(None, matches.get("ChrDbgFlags"));
+
+        let error = matches.unique().unwrap_err().to_string();
+        assert!(error.contains("Call: 2 Matches, Missing: 0 Matches"));
+        let local = set.search(buffer.as_slice(), base);
+        assert_eq!(matches.get("Call"), local.get("Call"));
+
+        let set = SignatureSet::parse(&[("GameDataMan", "48 8B 05"), ("Nibbles", "4? ?5")]);
+        let unique = set.unwrap().scan(&buffer, base, 0x30000).unique().unwrap();
+        assert_eq!(0x10100, unique["GameDataMan"]);
+        assert!(SignatureSet::parse(&[("A", "48"), ("A", "8B")]).is_err());
+    }
+}
@@ -0,0 +1,414 @@
+use crate::error::{ProcessError, SnapshotError};
+use crate::memory::{MemoryInfo, MemoryReader, RegionFilter, MAX_USER_ADDRESS};
+use crate::process::Module;
+use anyhow::Result;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const SNAPSHOT_MAGIC: &[u8; 8] = b"DS3SNAP\0";
+const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone)]
+pub enum SnapshotScope {
+    // Every committed, readable region
+    All,
+    // Regions inside the named module
+    Module(String),
+    // `span` bytes at each root and at every pointer found in them, `depth` levels deep
+    Pointers {
+        roots: Vec<usize>,
+        depth: usize,
+        span: usize,
+    },
+}
+
+// Captured memory state: module list, region map and the bytes of the
+// captured blocks. Addresses outside the blocks fail to read.
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    modules: Vec<Module>,
+    regions: Vec<MemoryInfo>,
+    blocks: BTreeMap<usize, Vec<u8>>,
+}
+
+impl Snapshot {
+    pub fn capture<R: MemoryReader>(
+        reader: &R,
+        modules: &[&str],
+        scope: &SnapshotScope,
+    ) -> Result<Snapshot> {
+        let mut snapshot = Snapshot::default();
+        for name in modules {
+            snapshot.modules.push(
+                reader
+                    .get_module(name)
+                    .ok_or(ProcessError::ModuleNotFound)?,
+            );
+        }
+        snapshot.regions = reader
+            .regions(RegionFilter::default())
+            .map(|r| r.info)
+            .collect();
+
+        match scope {
+            SnapshotScope::All => {
+                for region in snapshot.regions.clone() {
+                    snapshot.capture_region(reader, &region);
+                }
+            }
+            SnapshotScope::Module(name) => {
+                let module = reader
+                    .get_module(name)
+                    .ok_or(ProcessError::ModuleNotFound)?;
+                for region in snapshot.regions.clone() {
+                    if region.base >= module.base && region.base < module.base + module.size {
+                        snapshot.capture_region(reader, &region);
+                    }
+                }
+                if !snapshot.modules.iter().any(|m| m.name == module.name) {
+                    snapshot.modules.push(module);
+                }
+            }
+            SnapshotScope::Pointers { roots, depth, span } => {
+                snapshot.capture_pointers(reader, roots, *depth, *span);
+            }
+        }
+        Ok(snapshot)
+    }
+
+    fn capture_region<R: MemoryReader>(&mut self, reader: &R, region: &MemoryInfo) {
+        if !region.is_readable() {
+            return;
+        }
+        let mut data = vec![0u8; region.size];
+        if reader.read_bytes(region.base, data.as_mut_slice()).is_ok() {
+            self.blocks.insert(region.base, data);
+        }
+    }
+
+    fn capture_pointers<R: MemoryReader>(
+        &mut self,
+        reader: &R,
+        roots: &[usize],
+        depth: usize,
+        span: usize,
+    ) {
+        let mut queue: VecDeque<(usize, usize)> = roots.iter().map(|r| (*r, 0)).collect();
+        while let Some((target, level)) = queue.pop_front() {
+            let region = match self.region_at(target) {
+                Some(region) if region.is_readable() => *region,
+                _ => continue,
+            };
+            // A target inside earlier blocks only needs what runs past them
+            let mut address = target;
+            while let Some((base, data)) = self.block_at(address) {
+                address = base + data.len();
+            }
+            let mut end = (target + span).min(region.end());
+            if let Some((next, _)) = self.blocks.range(address..).next() {
+                end = end.min(*next);
+            }
+            if address >= end {
+                continue;
+            }
+            let mut data = vec![0u8; end - address];
+            if reader.read_bytes(address, data.as_mut_slice()).is_err() {
+                continue;
+            }
+            if level < depth {
+                let first = (8 - address % 8) % 8;
+                for chunk in data.get(first..).unwrap_or(&[]).chunks_exact(8) {
+                    let mut value = [0u8; 8];
+                    value.copy_from_slice(chunk);
+                    let pointer = usize::from_le_bytes(value);
+                    if matches!(self.region_at(pointer), Some(r) if r.is_readable()) {
+                        queue.push_back((pointer, level + 1));
+                    }
+                }
+            }
+            self.blocks.insert(address, data);
+        }
+    }
+
+    fn region_at(&self, address: usize) -> Option<&MemoryInfo> {
+        let index = self.regions.partition_point(|r| r.base <= address);
+        self.regions[..index].last().filter(|r| address < r.end())
+    }
+
+    fn block_at(&self, address: usize) -> Option<(&usize, &Vec<u8>)> {
+        self.blocks
+            .range(..=address)
+            .next_back()
+            .filter(|(base, data)| address < **base + data.len())
+    }
+
+    pub fn captured_size(&self) -> usize {
+        self.blocks.values().map(|b| b.len()).sum()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        self.write_to(&mut file)?;
+        file.flush()?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Snapshot> {
+        Snapshot::read_from(BufReader::new(File::open(path)?))
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        writer.write_all(SNAPSHOT_MAGIC)?;
+        writer.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+        let mut w = ZlibEncoder::new(writer, Compression::default());
+
+        w.write_all(&(self.modules.len() as u32).to_le_bytes())?;
+        for module in &self.modules {
+            w.write_all(&(module.name.len() as u32).to_le_bytes())?;
+            w.write_all(module.name.as_bytes())?;
+            w.write_all(&(module.base as u64).to_le_bytes())?;
+            w.write_all(&(module.size as u64).to_le_bytes())?;
+            w.write_all(&(module.path.len() as u32).to_le_bytes())?;
+            w.write_all(module.path.as_bytes())?;
+        }
+
+        w.write_all(&(self.regions.len() as u32).to_le_bytes())?;
+        for region in &self.regions {
+            w.write_all(&(region.base as u64).to_le_bytes())?;
+            w.write_all(&(region.allocation_base as u64).to_le_bytes())?;
+            w.write_all(&(region.size as u64).to_le_bytes())?;
+            w.write_all(&region.allocation_protect.to_le_bytes())?;
+            w.write_all(&region.state.to_le_bytes())?;
+            w.write_all(&region.protect.to_le_bytes())?;
+            w.write_all(&region.mem_type.to_le_bytes())?;
+        }
+
+        w.write_all(&(self.blocks.len() as u32).to_le_bytes())?;
+        for (base, data) in &self.blocks {
+            w.write_all(&(*base as u64).to_le_bytes())?;
+            w.write_all(&(data.len() as u64).to_le_bytes())?;
+            w.write_all(data.as_slice())?;
+        }
+        w.finish()?;
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Snapshot> {
+        let mut magic = [0u8; 8];
+        reader
+            .read_exact(&mut magic)
+            .map_err(|_| SnapshotError::InvalidMagic)?;
+        if &magic != SNAPSHOT_MAGIC {
+            return Err(SnapshotError::InvalidMagic.into());
+        }
+        let version = read_u32(&mut reader)?;
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version).into());
+        }
+        let mut r = ZlibDecoder::new(reader);
+        let mut snapshot = Snapshot::default();
+
+        for _ in 0..read_u32(&mut r)? {
+            let name = read_string(&mut r)?;
+            let base = read_u64(&mut r)? as usize;
+            let size = read_u64(&mut r)? as usize;
+            let path = read_string(&mut r)?;
+            snapshot.modules.push(Module {
+                name,
+                path,
+                base,
+                size,
+            });
+        }
+
+        for _ in 0..read_u32(&mut r)? {
+            snapshot.regions.push(MemoryInfo {
+                base: read_u64(&mut r)? as usize,
+                allocation_base: read_u64(&mut r)? as usize,
+                size: read_u64(&mut r)? as usize,
+                allocation_protect: read_u32(&mut r)?,
+                state: read_u32(&mut r)?,
+                protect: read_u32(&mut r)?,
+                mem_type: read_u32(&mut r)?,
+            });
+        }
+
+        for _ in 0..read_u32(&mut r)? {
+            let base = read_u64(&mut r)? as usize;
+            let len = read_u64(&mut r)? as usize;
+            snapshot.blocks.insert(base, read_vec(&mut r, len)?);
+        }
+        Ok(snapshot)
+    }
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32> {
+    let mut buf = [0u8; 4];
+    reader
+        .read_exact(&mut buf)
+        .map_err(|e| SnapshotError::Corrupted(e.to_string()))?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64> {
+    let mut buf = [0u8; 8];
+    reader
+        .read_exact(&mut buf)
+        .map_err(|e| SnapshotError::Corrupted(e.to_string()))?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_vec<R: Read>(reader: &mut R, len: usize) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    reader
+        .take(len as u64)
+        .read_to_end(&mut buf)
+        .map_err(|e| SnapshotError::Corrupted(e.to_string()))?;
+    if buf.len() != len {
+        return Err(SnapshotError::Corrupted("truncated block".to_string()).into());
+    }
+    Ok(buf)
+}
+
+fn read_string<R: Read>(reader: &mut R) -> Result<String> {
+    let len = read_u32(reader)? as usize;
+    Ok(String::from_utf8(read_vec(reader, len)?)
+        .map_err(|_| SnapshotError::Corrupted("module name".to_string()))?)
+}
+
+impl MemoryReader for Snapshot {
+    fn read_bytes(&self, address: usize, buf: &mut [u8]) -> Result<()> {
+        let mut copied = 0;
+        while copied < buf.len() {
+            let current = address + copied;
+            let (base, data) = self
+                .block_at(current)
+                .ok_or(ProcessError::ReadMemoryFail(address))?;
+            let offset = current - *base;
+            let len = (data.len() - offset).min(buf.len() - copied);
+            buf[copied..copied + len].copy_from_slice(&data[offset..offset + len]);
+            copied += len;
+        }
+        Ok(())
+    }
+
+    fn query_memory_info(&self, address: usize) -> Result<MemoryInfo> {
+        if address > MAX_USER_ADDRESS {
+            return Err(ProcessError::QueryMemoryFail(address).into());
+        }
+        if let Some(region) = self.region_at(address) {
+            return Ok(*region);
+        }
+        let base = self
+            .regions
+            .iter()
+            .map(|r| r.end())
+            .filter(|end| *end <= address)
+            .max()
+            .unwrap_or(0);
+        let end = self
+            .regions
+            .iter()
+            .map(|r| r.base)
+            .find(|start| *start > address)
+            .unwrap_or(MAX_USER_ADDRESS + 1);
+        Ok(MemoryInfo::free(base, end))
+    }
+
+    fn get_module(&self, name: &str) -> Option<Module> {
+        self.modules.iter().find(|m| m.name == name).cloned()
+    }
+
+    fn modules(&self) -> Vec<Module> {
+        self.modules.clone()
+    }
+
+    fn memory_regions(&self) -> Vec<MemoryInfo> {
+        self.regions.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::memory::{MemoryBuffer, MemoryReader};
+    use crate::snapshot::{Snapshot, SnapshotScope};
+
+    #[test]
+    pub fn test_snapshot_pointer_capture_round_trip() {
+        let base = 0x10000usize;
+        let mut data = vec![0u8; 0x3000];
+        // root -> 0x11000 -> 0x12000
+        data[0x08..0x10].copy_from_slice(&(base + 0x1000).to_le_bytes());
+        data[0x1010..0x1018].copy_from_slice(&(base + 0x2000).to_le_bytes());
+        data[0x2000..0x2004].copy_from_slice(&1337u32.to_le_bytes());
+        data[0x30..0x38].copy_from_slice(&(base + 0x2800).to_le_bytes());
+        data[0x2800..0x2804].copy_from_slice(&42u32.to_le_bytes());
+        let buffer = MemoryBuffer::new(base, data).with_name("DarkSoulsIII.exe");
+
+        let scope = SnapshotScope::Pointers {
+            roots: vec![base],
+            depth: 2,
+            span: 0x20,
+        };
+        let snapshot = Snapshot::capture(&buffer, &["DarkSoulsIII.exe"], &scope).unwrap();
+        assert_eq!(0x60, snapshot.captured_size());
+
+        let mut file = Vec::new();
+        snapshot.write_to(&mut file).unwrap();
+        let loaded = Snapshot::read_from(file.as_slice()).unwrap();
+
+        let first = loaded.read::<usize>(base + 0x8).unwrap();
+        let second = loaded.read::<usize>(first + 0x10).unwrap();
+        assert_eq!(1337, loaded.read::<u32>(second).unwrap());
+        assert!(loaded.read::<u32>(base + 0x100).is_err());
+        assert_eq!(base, loaded.get_module("DarkSoulsIII.exe").unwrap().base);
+        assert!(loaded
+            .query_memory_info(base + 0x100)
+            .unwrap()
+            .is_readable());
+
+        // The second root is inside the first block but its span runs past it
+        let scope = SnapshotScope::Pointers {
+            roots: vec![base, base + 0x18],
+            depth: 1,
+            span: 0x20,
+        };
+        let snapshot = Snapshot::capture(&buffer, &[], &scope).unwrap();
+        let target = snapshot.read::<usize>(base + 0x30).unwrap();
+        assert_eq!(42, snapshot.read::<u32>(target).unwrap());
+        assert!(snapshot.read::<u8>(base + 0x38).is_err());
+    }
+
+    #[test]
+    pub fn test_snapshot_region_capture_round_trip() {
+        let base = 0x10000usize;
+        let data: Vec<u8> = (0..0x3000).map(|i| i as u8).collect();
+        let buffer = MemoryBuffer::new(base, data).with_name("DarkSoulsIII.exe");
+        let round_trip = |snapshot: Snapshot| {
+            let mut file = Vec::new();
+            snapshot.write_to(&mut file).unwrap();
+            Snapshot::read_from(file.as_slice()).unwrap()
+        };
+
+        let all = Snapshot::capture(&buffer, &[], &SnapshotScope::All).unwrap();
+        let loaded = round_trip(all);
+        assert_eq!(0x3000, loaded.captured_size());
+        assert_eq!(0xFF, loaded.read::<u8>(base + 0x2FFF).unwrap());
+        assert!(loaded.get_module("DarkSoulsIII.exe").is_none());
+
+        let scope = SnapshotScope::Module("DarkSoulsIII.exe".to_string());
+        let loaded = round_trip(Snapshot::capture(&buffer, &[], &scope).unwrap());
+        assert_eq!(0x3000, loaded.captured_size());
+        assert_eq!(0x10, loaded.read::<u8>(base + 0x1010).unwrap());
+        let module = loaded.get_module("DarkSoulsIII.exe").unwrap();
+        assert_eq!((base, 0x3000), (module.base, module.size));
+
+        let scope = SnapshotScope::Module("notepad.exe".to_string());
+        assert!(Snapshot::capture(&buffer, &[], &scope).is_err());
+    }
+}
@@ -0,0 +1,803 @@
+use crate::error::ScanError;
+use crate::memory::{MemoryReader, RegionFilter};
+use crate::pattern_scan::Chunks;
+use anyhow::Result;
+use std::cmp::Ordering;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+// Regions are read in chunks of this size
+const CHUNK_SIZE: usize = 0x100000;
+// Next scans read results closer than this with one read
+const BATCH_SPAN: usize = 0x10000;
+
+static SNAPSHOT_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ValueType {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    U64,
+    I64,
+    F32,
+    F64,
+    Utf8,
+    Utf16,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i128),
+    Float(f64),
+    String(String),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(v) => write!(f, "{}", v),
+            Value::Float(v) => write!(f, "{}", v),
+            Value::String(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FirstScan {
+    Exact(Value),
+    // Both ends included
+    Range(Value, Value),
+    // Keeps every address, the bytes go to a temporary file instead of memory
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NextScan {
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+    IncreasedBy(Value),
+    DecreasedBy(Value),
+    Equal(Value),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Number {
+    Int(i128),
+    Float(f64),
+}
+
+impl ValueType {
+    // None for strings, their size comes from the scanned value
+    pub fn size(&self) -> Option<usize> {
+        match self {
+            ValueType::U8 | ValueType::I8 => Some(1),
+            ValueType::U16 | ValueType::I16 => Some(2),
+            ValueType::U32 | ValueType::I32 | ValueType::F32 => Some(4),
+            ValueType::U64 | ValueType::I64 | ValueType::F64 => Some(8),
+            ValueType::Utf8 | ValueType::Utf16 => None,
+        }
+    }
+
+    pub fn is_string(&self) -> bool {
+        self.size().is_none()
+    }
+
+    fn is_float(&self) -> bool {
+        matches!(self, ValueType::F32 | ValueType::F64)
+    }
+
+    fn int_range(&self) -> (i128, i128) {
+        match self {
+            ValueType::U8 => (0, u8::MAX as i128),
+            ValueType::I8 => (i8::MIN as i128, i8::MAX as i128),
+            ValueType::U16 => (0, u16::MAX as i128),
+            ValueType::I16 => (i16::MIN as i128, i16::MAX as i128),
+            ValueType::U32 => (0, u32::MAX as i128),
+            ValueType::I32 => (i32::MIN as i128, i32::MAX as i128),
+            ValueType::U64 => (0, u64::MAX as i128),
+            _ => (i64::MIN as i128, i64::MAX as i128),
+        }
+    }
+
+    // Text typed by the user, integers can be hex with 0x
+    pub fn parse(&self, text: &str) -> Result<Value> {
+        let text = text.trim();
+        let invalid = || ScanError::InvalidValue(text.to_string());
+        if self.is_string() {
+            return Ok(Value::String(text.to_string()));
+        }
+        if self.is_float() {
+            return Ok(Value::Float(text.parse::<f64>().map_err(|_| invalid())?));
+        }
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text),
+        };
+        let value = match digits
+            .strip_prefix("0x")
+            .or_else(|| digits.strip_prefix("0X"))
+        {
+            Some(hex) => i128::from_str_radix(hex, 16),
+            None => digits.parse::<i128>(),
+        }
+        .map_err(|_| invalid())?;
+        Ok(Value::Int(if negative { -value } else { value }))
+    }
+
+    fn number(&self, value: &Value) -> Result<Number> {
+        let invalid = || ScanError::InvalidValue(value.to_string());
+        match (value, self.is_float()) {
+            (Value::String(_), _) => Err(invalid().into()),
+            (Value::Int(v), true) => Ok(Number::Float(*v as f64)),
+            (Value::Float(v), true) => Ok(Number::Float(*v)),
+            (Value::Float(v), false) if v.fract() == 0.0 => Ok(Number::Int(*v as i128)),
+            (Value::Float(_), false) => Err(invalid().into()),
+            (Value::Int(v), false) => Ok(Number::Int(*v)),
+        }
+    }
+
+    // Bytes of `value` as stored in the target
+    fn encode(&self, value: &Value) -> Result<Vec<u8>> {
+        let invalid = || ScanError::InvalidValue(value.to_string());
+        match (self, value) {
+            (ValueType::Utf8, Value::String(s)) => Ok(s.as_bytes().to_vec()),
+            (ValueType::Utf16, Value::String(s)) => {
+                Ok(s.encode_utf16().flat_map(|c| c.to_le_bytes()).collect())
+            }
+            (ValueType::F32, _) => match self.number(value)? {
+                Number::Float(v) => Ok((v as f32).to_le_bytes().to_vec()),
+                Number::Int(_) => Err(invalid().into()),
+            },
+            (ValueType::F64, _) => match self.number(value)? {
+                Number::Float(v) => Ok(v.to_le_bytes().to_vec()),
+                Number::Int(_) => Err(invalid().into()),
+            },
+            (_, _) if self.is_string() => Err(invalid().into()),
+            (_, _) => {
+                let v = match self.number(value)? {
+                    Number::Int(v) => v,
+                    Number::Float(_) => return Err(invalid().into()),
+                };
+                let (min, max) = self.int_range();
+                if v < min || v > max {
+                    return Err(invalid().into());
+                }
+                Ok(v.to_le_bytes()[..self.size().unwrap()].to_vec())
+            }
+        }
+    }
+
+    fn decode_number(&self, bytes: &[u8]) -> Number {
+        let mut buf = [0u8; 8];
+        buf[..bytes.len()].copy_from_slice(bytes);
+        let [b0, b1, b2, b3, ..] = buf;
+        match self {
+            ValueType::U8 => Number::Int(b0 as i128),
+            ValueType::I8 => Number::Int(b0 as i8 as i128),
+            ValueType::U16 => Number::Int(u16::from_le_bytes([b0, b1]) as i128),
+            ValueType::I16 => Number::Int(i16::from_le_bytes([b0, b1]) as i128),
+            ValueType::U32 => Number::Int(u32::from_le_bytes([b0, b1, b2, b3]) as i128),
+            ValueType::I32 => Number::Int(i32::from_le_bytes([b0, b1, b2, b3]) as i128),
+            ValueType::U64 => Number::Int(u64::from_le_bytes(buf) as i128),
+            ValueType::I64 => Number::Int(i64::from_le_bytes(buf) as i128),
+            ValueType::F32 => Number::Float(f32::from_le_bytes([b0, b1, b2, b3]) as f64),
+            _ => Number::Float(f64::from_le_bytes(buf)),
+        }
+    }
+
+    pub fn decode(&self, bytes: &[u8]) -> Value {
+        match self {
+            ValueType::Utf8 => Value::String(String::from_utf8_lossy(bytes).into_owned()),
+            ValueType::Utf16 => {
+                let units: Vec<u16> = bytes
+                    .chunks_exact(2)
+                    .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                    .collect();
+                Value::String(String::from_utf16_lossy(&units))
+            }
+            _ => match self.decode_number(bytes) {
+                Number::Int(v) => Value::Int(v),
+                Number::Float(v) => Value::Float(v),
+            },
+        }
+    }
+}
+
+// A compiled scan condition, `old` is empty for first scans
+enum Condition {
+    Bytes(Vec<u8>),
+    Equal(Number),
+    Range(Number, Number),
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+    IncreasedBy(Number),
+    DecreasedBy(Number),
+}
+
+struct Matcher {
+    value_type: ValueType,
+    tolerance: f64,
+    condition: Condition,
+}
+
+impl Matcher {
+    fn compare(&self, a: Number, b: Number) -> Option<Ordering> {
+        match (a, b) {
+            (Number::Int(a), Number::Int(b)) => Some(a.cmp(&b)),
+            (Number::Float(a), Number::Float(b)) if (a - b).abs() <= self.tolerance => {
+                Some(Ordering::Equal)
+            }
+            (Number::Float(a), Number::Float(b)) => a.partial_cmp(&b),
+            _ => None,
+        }
+    }
+
+    fn difference(&self, old: Number, new: Number, expected: Number) -> bool {
+        match (old, new, expected) {
+            (Number::Int(old), Number::Int(new), Number::Int(expected)) => new - old == expected,
+            (Number::Float(old), Number::Float(new), Number::Float(expected)) => {
+                (new - old - expected).abs() <= self.tolerance
+            }
+            _ => false,
+        }
+    }
+
+    fn changed(&self, old: &[u8], new: &[u8]) -> bool {
+        if self.value_type.is_string() {
+            return old != new;
+        }
+        let (old_number, new_number) = (
+            self.value_type.decode_number(old),
+            self.value_type.decode_number(new),
+        );
+        match self.compare(new_number, old_number) {
+            Some(ordering) => ordering != Ordering::Equal,
+            None => old != new,
+        }
+    }
+
+    fn matches(&self, old: &[u8], new: &[u8]) -> bool {
+        let number = || self.value_type.decode_number(new);
+        let old_number = || self.value_type.decode_number(old);
+        match &self.condition {
+            Condition::Bytes(bytes) => new == bytes.as_slice(),
+            Condition::Equal(value) => self.compare(number(), *value) == Some(Ordering::Equal),
+            Condition::Range(low, high) => {
+                let value = number();
+                matches!(
+                    self.compare(value, *low),
+                    Some(Ordering::Equal | Ordering::Greater)
+                ) && matches!(
+                    self.compare(value, *high),
+                    Some(Ordering::Equal | Ordering::Less)
+                )
+            }
+            Condition::Changed => self.changed(old, new),
+            Condition::Unchanged => !self.changed(old, new),
+            Condition::Increased => self.compare(number(), old_number()) == Some(Ordering::Greater),
+            Condition::Decreased => self.compare(number(), old_number()) == Some(Ordering::Less),
+            Condition::IncreasedBy(by) => self.difference(old_number(), number(), *by),
+            Condition::DecreasedBy(by) => self.difference(number(), old_number(), *by),
+        }
+    }
+}
+
+// Bytes of the regions seen by an unknown value first scan
+#[derive(Debug)]
+struct SnapshotFile {
+    path: PathBuf,
+    file: File,
+    // (address, size, file offset)
+    ranges: Vec<(usize, usize, u64)>,
+}
+
+impl SnapshotFile {
+    fn create() -> Result<SnapshotFile> {
+        let path = std::env::temp_dir().join(format!(
+            "value-scan-{}-{}.bin",
+            std::process::id(),
+            SNAPSHOT_COUNTER.fetch_add(1, AtomicOrdering::Relaxed)
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)?;
+        Ok(SnapshotFile {
+            path,
+            file,
+            ranges: Vec::new(),
+        })
+    }
+
+    fn append(&mut self, address: usize, data: &[u8]) -> Result<()> {
+        let offset = self.file.seek(SeekFrom::End(0))?;
+        self.file.write_all(data)?;
+        match self.ranges.last_mut() {
+            Some((base, size, start))
+                if *base + *size == address && *start + *size as u64 == offset =>
+            {
+                *size += data.len()
+            }
+            _ => self.ranges.push((address, data.len(), offset)),
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, offset: u64, buf: &mut [u8]) -> Result<()> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(buf)?;
+        Ok(())
+    }
+}
+
+impl Drop for SnapshotFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[derive(Debug)]
+enum ScanResults {
+    Empty,
+    Snapshot(SnapshotFile),
+    // Sorted addresses and their last values, `width` bytes each
+    Matches {
+        addresses: Vec<usize>,
+        values: Vec<u8>,
+    },
+}
+
+// Cheat Engine style value scanner: a first scan over every region matching
+// the filter (writable by default), then next scans that narrow the results
+// down by comparing against the values seen last time.
+#[derive(Debug)]
+pub struct ValueScanner {
+    value_type: ValueType,
+    alignment: Option<usize>,
+    tolerance: f64,
+    filter: RegionFilter,
+    width: usize,
+    results: ScanResults,
+}
+
+impl ValueScanner {
+    pub fn new(value_type: ValueType) -> ValueScanner {
+        ValueScanner {
+            value_type,
+            alignment: None,
+            tolerance: 0.0,
+            filter: RegionFilter {
+                writable: true,
+                ..Default::default()
+            },
+            width: value_type.size().unwrap_or(0),
+            results: ScanResults::Empty,
+        }
+    }
+
+    // Defaults to the value size, 1 for strings
+    pub fn with_alignment(mut self, alignment: usize) -> ValueScanner {
+        self.alignment = Some(alignment.max(1));
+        self
+    }
+
+    // Floats closer than `tolerance` count as equal
+    pub fn with_tolerance(mut self, tolerance: f64) -> ValueScanner {
+        self.tolerance = tolerance.abs();
+        self
+    }
+
+    pub fn with_filter(mut self, filter: RegionFilter) -> ValueScanner {
+        self.filter = filter;
+        self
+    }
+
+    pub fn value_type(&self) -> ValueType {
+        self.value_type
+    }
+
+    fn alignment(&self) -> usize {
+        match (self.alignment, self.value_type.is_string()) {
+            (Some(alignment), _) => alignment,
+            (None, true) => 1,
+            (None, false) => self.width,
+        }
+    }
+
+    // Number of addresses still in the results
+    pub fn len(&self) -> usize {
+        match &self.results {
+            ScanResults::Empty => 0,
+            ScanResults::Snapshot(snapshot) => snapshot
+                .ranges
+                .iter()
+                .map(|(address, size, _)| self.positions(*address, *size, *size))
+                .sum(),
+            ScanResults::Matches { addresses, .. } => addresses.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn reset(&mut self) {
+        self.results = ScanResults::Empty;
+        self.width = self.value_type.size().unwrap_or(0);
+    }
+
+    // Offsets into a block at `address` of `size` bytes where a value starts,
+    // only the first `limit` bytes may hold a start
+    fn offsets(&self, address: usize, size: usize, limit: usize) -> impl Iterator<Item = usize> {
+        let alignment = self.alignment();
+        let first = address.next_multiple_of(alignment) - address;
+        let end = (size + 1).saturating_sub(self.width).min(limit);
+        (first..end.max(first)).step_by(alignment)
+    }
+
+    fn positions(&self, address: usize, size: usize, limit: usize) -> usize {
+        let alignment = self.alignment();
+        let first = address.next_multiple_of(alignment) - address;
+        let end = (size + 1).saturating_sub(self.width).min(limit);
+        end.saturating_sub(first).div_ceil(alignment)
+    }
+
+    pub fn first_scan<R: MemoryReader>(&mut self, reader: &R, scan: &FirstScan) -> Result<()> {
+        self.reset();
+        let value_type = self.value_type;
+        let condition = match scan {
+            FirstScan::Exact(value) if value_type.is_float() => {
+                Condition::Equal(value_type.number(value)?)
+            }
+            FirstScan::Exact(value) => Condition::Bytes(value_type.encode(value)?),
+            FirstScan::Range(low, high) if !value_type.is_string() => {
+                Condition::Range(value_type.number(low)?, value_type.number(high)?)
+            }
+            FirstScan::Unknown if !value_type.is_string() => return self.snapshot(reader),
+            _ => return Err(ScanError::Unsupported(format!("{:?}", scan)).into()),
+        };
+        if let Condition::Bytes(bytes) = &condition {
+            self.width = bytes.len();
+        }
+        if self.width == 0 {
+            return Err(ScanError::InvalidValue(String::new()).into());
+        }
+        let matcher = Matcher {
+            value_type,
+            tolerance: self.tolerance,
+            condition,
+        };
+
+        let mut addresses = Vec::new();
+        let mut values = Vec::new();
+        // A value that didn't fit the previous chunk starts in the carried bytes
+        let mut chunks = Chunks::new(reader, self.ranges(reader), self.width - 1);
+        chunks.set_chunk_size(CHUNK_SIZE);
+        while let Some((base, _, data)) = chunks.next_chunk() {
+            for offset in self.offsets(base, data.len(), data.len()) {
+                let value = &data[offset..offset + self.width];
+                if matcher.matches(&[], value) {
+                    addresses.push(base + offset);
+                    values.extend_from_slice(value);
+                }
+            }
+        }
+        self.results = ScanResults::Matches { addresses, values };
+        Ok(())
+    }
+
+    fn snapshot<R: MemoryReader>(&mut self, reader: &R) -> Result<()> {
+        let mut snapshot = SnapshotFile::create()?;
+        let mut chunks = Chunks::new(reader, self.ranges(reader), 0);
+        chunks.set_chunk_size(CHUNK_SIZE);
+        while let Some((base, _, data)) = chunks.next_chunk() {
+            snapshot.append(base, data)?;
+        }
+        self.results = ScanResults::Snapshot(snapshot);
+        Ok(())
+    }
+
+    // Regions passing the filter, an unreadable page only drops itself
+    fn ranges<R: MemoryReader>(&self, reader: &R) -> Vec<(usize, usize)> {
+        reader
+            .regions(self.filter)
+            .map(|region| (region.info.base, region.info.end()))
+            .collect()
+    }
+
+    pub fn next_scan<R: MemoryReader>(&mut self, reader: &R, scan: &NextScan) -> Result<()> {
+        let value_type = self.value_type;
+        let number = |value: &Value| value_type.number(value);
+        let condition = match scan {
+            NextScan::Changed => Condition::Changed,
+            NextScan::Unchanged => Condition::Unchanged,
+            NextScan::Equal(value) if value_type.is_float() => Condition::Equal(number(value)?),
+            NextScan::Equal(value) => Condition::Bytes(value_type.encode(value)?),
+            _ if value_type.is_string() => {
+                return Err(ScanError::Unsupported(format!("{:?}", scan)).into())
+            }
+            NextScan::Increased => Condition::Increased,
+            NextScan::Decreased => Condition::Decreased,
+            NextScan::IncreasedBy(value) => Condition::IncreasedBy(number(value)?),
+            NextScan::DecreasedBy(value) => Condition::DecreasedBy(number(value)?),
+        };
+        if let Condition::Bytes(bytes) = &condition {
+            if bytes.len() != self.width {
+                return Err(ScanError::InvalidValue(format!("{:?}", scan)).into());
+            }
+        }
+        let matcher = Matcher {
+            value_type,
+            tolerance: self.tolerance,
+            condition,
+        };
+
+        let results = std::mem::replace(&mut self.results, ScanResults::Empty);
+        self.results = match results {
+            ScanResults::Empty => ScanResults::Empty,
+            ScanResults::Snapshot(mut snapshot) => {
+                self.next_snapshot(reader, &matcher, &mut snapshot)?
+            }
+            ScanResults::Matches { addresses, values } => {
+                self.next_matches(reader, &matcher, &addresses, &values)
+            }
+        };
+        Ok(())
+    }
+
+    fn next_snapshot<R: MemoryReader>(
+        &self,
+        reader: &R,
+        matcher: &Matcher,
+        snapshot: &mut SnapshotFile,
+    ) -> Result<ScanResults> {
+        let mut addresses = Vec::new();
+        let mut values = Vec::new();
+        let mut old = Vec::new();
+        let mut new = Vec::new();
+        for (base, size, file_offset) in snapshot.ranges.clone() {
+            let mut start = 0;
+            while start < size {
+                let len = (CHUNK_SIZE + self.width - 1).min(size - start);
+                old.resize(len, 0);
+                new.resize(len, 0);
+                snapshot.read(file_offset + start as u64, &mut old)?;
+                if reader.read_bytes(base + start, &mut new).is_ok() {
+                    for offset in self.offsets(base + start, len, CHUNK_SIZE) {
+                        let range = offset..offset + self.width;
+                        if matcher.matches(&old[range.clone()], &new[range.clone()]) {
+                            addresses.push(base + start + offset);
+                            values.extend_from_slice(&new[range]);
+                        }
+                    }
+                }
+                start += CHUNK_SIZE;
+            }
+        }
+        Ok(ScanResults::Matches { addresses, values })
+    }
+
+    fn next_matches<R: MemoryReader>(
+        &self,
+        reader: &R,
+        matcher: &Matcher,
+        addresses: &[usize],
+        values: &[u8],
+    ) -> ScanResults {
+        let width = self.width;
+        let mut new_addresses = Vec::new();
+        let mut new_values = Vec::new();
+        let mut buf = Vec::new();
+        let mut value = vec![0u8; width];
+        let mut i = 0;
+        while i < addresses.len() {
+            let start = addresses[i];
+            let mut end = i + 1;
+            while end < addresses.len() && addresses[end] + width - start <= BATCH_SPAN {
+                end += 1;
+            }
+            buf.resize(addresses[end - 1] + width - start, 0);
+            let batch = reader.read_bytes(start, &mut buf).is_ok();
+            for j in i..end {
+                let address = addresses[j];
+                let current = if batch {
+                    &buf[address - start..address - start + width]
+                } else if reader.read_bytes(address, &mut value).is_ok() {
+                    value.as_slice()
+                } else {
+                    continue;
+                };
+                if matcher.matches(&values[j * width..(j + 1) * width], current) {
+                    new_addresses.push(address);
+                    new_values.extend_from_slice(current);
+                }
+            }
+            i = end;
+        }
+        ScanResults::Matches {
+            addresses: new_addresses,
+            values: new_values,
+        }
+    }
+
+    // Up to `count` results starting at the `skip`th, with the value seen by
+    // the last scan
+    pub fn results(&mut self, skip: usize, count: usize) -> Result<Vec<(usize, Value)>> {
+        let width = self.width;
+        let value_type = self.value_type;
+        match &self.results {
+            ScanResults::Empty => Ok(Vec::new()),
+            ScanResults::Matches { addresses, values } => Ok(addresses
+                .iter()
+                .enumerate()
+                .skip(skip)
+                .take(count)
+                .map(|(i, address)| {
+                    (
+                        *address,
+                        value_type.decode(&values[i * width..(i + 1) * width]),
+                    )
+                })
+                .collect()),
+            ScanResults::Snapshot(_) => self.snapshot_results(skip, count),
+        }
+    }
+
+    fn snapshot_results(&mut self, mut skip: usize, count: usize) -> Result<Vec<(usize, Value)>> {
+        let width = self.width;
+        let mut results = Vec::new();
+        let ranges = match &self.results {
+            ScanResults::Snapshot(snapshot) => snapshot.ranges.clone(),
+            _ => return Ok(results),
+        };
+        let mut value = vec![0u8; width];
+        for (address, size, file_offset) in ranges {
+            let offsets: Vec<usize> = self.offsets(address, size, size).collect();
+            if skip >= offsets.len() {
+                skip -= offsets.len();
+                continue;
+            }
+            for offset in offsets.into_iter().skip(skip) {
+                if results.len() == count {
+                    return Ok(results);
+                }
+                if let ScanResults::Snapshot(snapshot) = &mut self.results {
+                    snapshot.read(file_offset + offset as u64, &mut value)?;
+                }
+                results.push((address + offset, self.value_type.decode(&value)));
+            }
+            skip = 0;
+        }
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::memory::{MemoryBuffer, RegionFilter};
+    use crate::pattern_scan::test::HoleReader;
+    use crate::value_scan::{FirstScan, NextScan, Value, ValueScanner, ValueType};
+    use std::cell::Cell;
+
+    fn buffer(values: &[(usize, &[u8])]) -> MemoryBuffer {
+        let mut data = vec![0u8; 0x3000];
+        for (offset, bytes) in values {
+            data[*offset..offset + bytes.len()].copy_from_slice(bytes);
+        }
+        MemoryBuffer::new(0x10000, data)
+    }
+
+    #[test]
+    pub fn test_value_scanner() {
+        // MemoryBuffer regions are read-only
+        let filter = RegionFilter::default();
+        let before = buffer(&[
+            (0x100, &1337u32.to_le_bytes()),
+            (0x2FFC, &1337u32.to_le_bytes()),
+            (0x200, &50u32.to_le_bytes()),
+        ]);
+        let after = buffer(&[
+            (0x100, &1347u32.to_le_bytes()),
+            (0x2FFC, &1337u32.to_le_bytes()),
+            (0x200, &40u32.to_le_bytes()),
+        ]);
+
+        let mut scanner = ValueScanner::new(ValueType::U32).with_filter(filter);
+        scanner
+            .first_scan(&before, &FirstScan::Exact(Value::Int(1337)))
+            .unwrap();
+        assert_eq!(2, scanner.len());
+        scanner
+            .next_scan(&after, &NextScan::IncreasedBy(Value::Int(10)))
+            .unwrap();
+        assert_eq!(
+            vec![(0x10100, Value::Int(1347))],
+            scanner.results(0, 10).unwrap()
+        );
+
+        // Unknown initial value keeps every aligned u32
+        scanner.first_scan(&before, &FirstScan::Unknown).unwrap();
+        assert_eq!(0x3000 / 4, scanner.len());
+        assert_eq!(
+            vec![(0x10100, Value::Int(1337))],
+            scanner.results(0x40, 1).unwrap()
+        );
+        scanner.next_scan(&after, &NextScan::Changed).unwrap();
+        assert_eq!(2, scanner.len());
+        scanner.next_scan(&after, &NextScan::Decreased).unwrap();
+        assert!(scanner.is_empty());
+
+        scanner
+            .first_scan(&before, &FirstScan::Range(Value::Int(40), Value::Int(60)))
+            .unwrap();
+        scanner.next_scan(&after, &NextScan::Decreased).unwrap();
+        assert_eq!(
+            vec![(0x10200, Value::Int(40))],
+            scanner.results(0, 10).unwrap()
+        );
+
+        // An unreadable page only hides itself, not the rest of the chunk
+        let holed = HoleReader {
+            buffer: before,
+            hole: 0x11000,
+            reads: Cell::new(0),
+        };
+        scanner
+            .first_scan(&holed, &FirstScan::Exact(Value::Int(1337)))
+            .unwrap();
+        assert_eq!(2, scanner.len());
+        scanner.first_scan(&holed, &FirstScan::Unknown).unwrap();
+        assert_eq!(0x2000 / 4, scanner.len());
+    }
+
+    #[test]
+    pub fn test_value_scanner_float_and_string() {
+        let mut name = Vec::new();
+        for c in "Ashen One".encode_utf16() {
+            name.extend_from_slice(&c.to_le_bytes());
+        }
+        let memory = buffer(&[(0x300, &1.5f32.to_le_bytes()), (0x401, &name)]);
+        let filter = RegionFilter::default();
+
+        let mut scanner = ValueScanner::new(ValueType::F32)
+            .with_filter(filter)
+            .with_tolerance(0.01);
+        scanner
+            .first_scan(&memory, &FirstScan::Exact(Value::Float(1.505)))
+            .unwrap();
+        assert_eq!(1, scanner.len());
+        scanner.next_scan(&memory, &NextScan::Unchanged).unwrap();
+        assert_eq!(1, scanner.len());
+        assert!(scanner
+            .first_scan(&memory, &FirstScan::Exact(Value::String("x".into())))
+            .is_err());
+
+        let mut scanner = ValueScanner::new(ValueType::Utf16).with_filter(filter);
+        scanner
+            .first_scan(
+                &memory,
+                &FirstScan::Exact(ValueType::Utf16.parse("Ashen One").unwrap()),
+            )
+            .unwrap();
+        assert_eq!(
+            vec![(0x10401, Value::String("Ashen One".into()))],
+            scanner.results(0, 10).unwrap()
+        );
+        assert!(scanner.next_scan(&memory, &NextScan::Increased).is_err());
+        assert_eq!(Value::Int(-0x10), ValueType::I8.parse("-0x10").unwrap());
+        assert!(ValueType::U8.parse("abc").is_err());
+    }
+}
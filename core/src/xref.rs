@@ -0,0 +1,260 @@
+use crate::disasm::MAX_INSTRUCTION_LEN;
+use crate::image::{ModuleHeaders, RuntimeFunction};
+use crate::memory::{MemoryReader, PAGE_EXECUTABLE};
+use crate::process::Module;
+use anyhow::Result;
+use iced_x86::{Decoder, DecoderOptions, FlowControl, Instruction, OpKind};
+
+// Immediate sizes that can follow a rel32 displacement
+const TRAILING_IMMEDIATES: [usize; 4] = [0, 1, 2, 4];
+const UNW_FLAG_CHAININFO: u8 = 0x4;
+const MAX_CHAIN_DEPTH: usize = 32;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum XrefKind {
+    // RIP-relative memory operand: loads, stores and lea
+    RipRelative,
+    Call,
+    Jump,
+    // mov r64, imm64
+    Immediate,
+    // Pointer stored in a data section
+    Data,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Xref {
+    pub address: usize,
+    pub kind: XrefKind,
+    // Start of the function holding the instruction, from .pdata
+    pub function: Option<usize>,
+}
+
+// Every instruction in the executable sections of `module` that refers to
+// `target`, and every aligned pointer to it in the other sections
+pub fn find_xrefs<R: MemoryReader>(
+    reader: &R,
+    module: &Module,
+    target: usize,
+) -> Result<Vec<Xref>> {
+    let headers = module.headers(reader)?;
+    let mut xrefs = Vec::new();
+    for section in &headers.sections {
+        let mut data = vec![0u8; section.size];
+        reader.read_bytes(section.base, &mut data)?;
+        if section.protect & PAGE_EXECUTABLE != 0 {
+            for (address, kind) in code_refs(&data, section.base, target) {
+                xrefs.push(Xref {
+                    address,
+                    kind,
+                    function: function_start(reader, &headers, address),
+                });
+            }
+        } else {
+            for (i, bytes) in data.chunks_exact(8).enumerate() {
+                if usize::from_le_bytes(bytes.try_into().unwrap()) == target {
+                    xrefs.push(Xref {
+                        address: section.base + i * 8,
+                        kind: XrefKind::Data,
+                        function: None,
+                    });
+                }
+            }
+        }
+    }
+    Ok(xrefs)
+}
+
+// Finds the rel32 displacements and imm64 values that could encode `target`
+// first, then decodes backwards from each to find the instruction using it
+fn code_refs(code: &[u8], base: usize, target: usize) -> Vec<(usize, XrefKind)> {
+    let mut refs = Vec::new();
+    let mut last_end = 0;
+    for offset in 0..code.len().saturating_sub(3) {
+        // An instruction was already found over these bytes
+        if offset < last_end {
+            continue;
+        }
+        let value = i32::from_le_bytes(code[offset..offset + 4].try_into().unwrap()) as isize;
+        for immediate in TRAILING_IMMEDIATES {
+            let end = offset + 4 + immediate;
+            if (base + end).wrapping_add_signed(value) != target {
+                continue;
+            }
+            if let Some((start, kind)) = decode_ending_at(code, base, offset, end, target) {
+                refs.push((base + start, kind));
+                last_end = end;
+                break;
+            }
+        }
+        if offset < last_end || offset + 8 > code.len() {
+            continue;
+        }
+        if usize::from_le_bytes(code[offset..offset + 8].try_into().unwrap()) == target {
+            if let Some((start, kind)) = decode_ending_at(code, base, offset, offset + 8, target) {
+                refs.push((base + start, kind));
+                last_end = offset + 8;
+            }
+        }
+    }
+    refs
+}
+
+// Tries every start before `field` for an instruction that ends at `end` and
+// refers to `target`. A REX or legacy prefix also decodes without it, so the
+// match is extended back as long as the longer instruction still matches.
+fn decode_ending_at(
+    code: &[u8],
+    base: usize,
+    field: usize,
+    end: usize,
+    target: usize,
+) -> Option<(usize, XrefKind)> {
+    let first = end.saturating_sub(MAX_INSTRUCTION_LEN);
+    let mut instruction = Instruction::default();
+    let mut found = None;
+    for start in (first..field).rev() {
+        let ip = (base + start) as u64;
+        let mut decoder = Decoder::with_ip(64, &code[start..end], ip, DecoderOptions::NONE);
+        decoder.decode_out(&mut instruction);
+        let kind = if instruction.is_invalid() || start + instruction.len() != end {
+            None
+        } else {
+            reference_kind(&instruction, target)
+        };
+        match kind {
+            Some(kind) => found = Some((start, kind)),
+            None if found.is_some() => break,
+            None => {}
+        }
+    }
+    found
+}
+
+fn reference_kind(instruction: &Instruction, target: usize) -> Option<XrefKind> {
+    let target = target as u64;
+    match instruction.op0_kind() {
+        OpKind::NearBranch32 | OpKind::NearBranch64
+            if instruction.near_branch_target() == target =>
+        {
+            return match instruction.flow_control() {
+                FlowControl::Call => Some(XrefKind::Call),
+                _ => Some(XrefKind::Jump),
+            };
+        }
+        _ => {}
+    }
+    if instruction.is_ip_rel_memory_operand() && instruction.ip_rel_memory_address() == target {
+        return Some(XrefKind::RipRelative);
+    }
+    let immediate = (0..instruction.op_count()).any(|i| {
+        instruction.op_kind(i) == OpKind::Immediate64 && instruction.immediate(i) == target
+    });
+    if immediate {
+        return Some(XrefKind::Immediate);
+    }
+    None
+}
+
+// Function start from the exception directory. Chained unwind info marks a
+// fragment of another function, the chain is followed to the primary entry.
+pub fn function_start<R: MemoryReader>(
+    reader: &R,
+    headers: &ModuleHeaders,
+    address: usize,
+) -> Option<usize> {
+    let mut function = runtime_function(&headers.exception, address)?;
+    for _ in 0..MAX_CHAIN_DEPTH {
+        let header = reader.read::<[u8; 4]>(function.unwind_info).ok()?;
+        if header[0] >> 3 & UNW_FLAG_CHAININFO == 0 {
+            break;
+        }
+        // The parent RUNTIME_FUNCTION follows the unwind codes, aligned to 4 bytes
+        let codes = (header[2] as usize + 1) & !1;
+        let chained = function.unwind_info + 4 + codes * 2;
+        let parent = reader.read::<[u32; 3]>(chained).ok()?;
+        function = RuntimeFunction {
+            begin: headers.base + parent[0] as usize,
+            end: headers.base + parent[1] as usize,
+            unwind_info: headers.base + parent[2] as usize,
+        };
+    }
+    Some(function.begin)
+}
+
+// .pdata is sorted by start address
+fn runtime_function(functions: &[RuntimeFunction], address: usize) -> Option<RuntimeFunction> {
+    let index = functions.partition_point(|f| f.begin <= address);
+    let function = functions.get(index.checked_sub(1)?)?;
+    (address < function.end).then_some(*function)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::image::test::{build_pe, put, set_directory};
+    use crate::image::PeImage;
+    use crate::memory::MemoryReader;
+    use crate::xref::{find_xrefs, Xref, XrefKind};
+    use pelite::image::IMAGE_DIRECTORY_ENTRY_EXCEPTION;
+
+    #[test]
+    pub fn test_find_xrefs() {
+        let mut text = vec![0xCCu8; 0x30];
+        // lea rcx, [rip+0x1009] ; mov rax, 0x140002010 ; ret
+        put(&mut text, 0x00, &[0x48, 0x8D, 0x0D, 0x09, 0x10, 0x00, 0x00]);
+        put(&mut text, 0x07, &[0x48, 0xB8]);
+        put(&mut text, 0x09, &0x140002010u64.to_le_bytes());
+        put(&mut text, 0x11, &[0xC3]);
+        // call 0x140001000 ; mov dword [rip+0xFE1], 1 ; ret
+        put(&mut text, 0x20, &[0xE8, 0xDB, 0xFF, 0xFF, 0xFF]);
+        put(&mut text, 0x25, &[0xC7, 0x05, 0xE1, 0x0F, 0x00, 0x00]);
+        put(&mut text, 0x2B, &1u32.to_le_bytes());
+        put(&mut text, 0x2F, &[0xC3]);
+
+        let mut data = vec![0u8; 0x20];
+        put(&mut data, 0x00, &0x140002010u64.to_le_bytes());
+
+        // Three RUNTIME_FUNCTIONs, the last one is a fragment chained to the
+        // second through the unwind info at 0x3038
+        let mut rdata = vec![0u8; 0x50];
+        let functions = [
+            0x1000u32, 0x1012, 0x3030, 0x1020, 0x1025, 0x3030, 0x1025, 0x1030, 0x3038,
+        ];
+        for (i, value) in functions.iter().enumerate() {
+            put(&mut rdata, i * 4, &value.to_le_bytes());
+        }
+        put(&mut rdata, 0x30, &[0x01, 0, 0, 0]);
+        put(&mut rdata, 0x38, &[0x21, 0, 0, 0]);
+        for (i, value) in [0x1020u32, 0x1025, 0x3030].iter().enumerate() {
+            put(&mut rdata, 0x3C + i * 4, &value.to_le_bytes());
+        }
+
+        let mut file = build_pe(&[
+            (".text", &text, 0x60000020),
+            (".data", &data, 0xC0000040),
+            (".rdata", &rdata, 0x40000040),
+        ]);
+        set_directory(&mut file, IMAGE_DIRECTORY_ENTRY_EXCEPTION, 0x3000, 36);
+        let image = PeImage::from_bytes("DarkSoulsIII.exe", file.as_slice()).unwrap();
+        let module = image.get_module("DarkSoulsIII.exe").unwrap();
+
+        let xref = |address, kind, function| Xref {
+            address,
+            kind,
+            function,
+        };
+        assert_eq!(
+            vec![
+                xref(0x140001000, XrefKind::RipRelative, Some(0x140001000)),
+                xref(0x140001007, XrefKind::Immediate, Some(0x140001000)),
+                xref(0x140001025, XrefKind::RipRelative, Some(0x140001020)),
+                xref(0x140002000, XrefKind::Data, None),
+            ],
+            find_xrefs(&image, &module, 0x140002010).unwrap()
+        );
+        assert_eq!(
+            vec![xref(0x140001020, XrefKind::Call, Some(0x140001020))],
+            find_xrefs(&image, &module, 0x140001000).unwrap()
+        );
+    }
+}
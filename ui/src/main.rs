@@ -1,6 +1,11 @@
 use crate::glutin::dpi::LogicalPosition;
 use crate::MemoryItemType::*;
-use core::process::Process;
+use core::error::ProcessError;
+use core::memory::MemoryReader;
+use core::minidump::Minidump;
+use core::pointer::PointerChain;
+use core::process::{Process, ProcessInfo, ProcessMatch, RTTIInfo};
+use core::scan_executor::{ScanExecutor, ScanTask};
 use glium::glutin;
 use glium::glutin::dpi::Position;
 use glium::glutin::event::{Event, WindowEvent};
@@ -11,7 +16,7 @@ use glium::{Display, Surface};
 use imgui::StyleColor::Button;
 use imgui::{
     Condition, Context, FontConfig, FontGlyphRanges, FontSource, InputTextFlags, ItemHoveredFlags,
-    MenuItem, MouseButton, PopupModal, Selectable, TreeNode, Ui, Window,
+    MenuItem, MouseButton, PopupModal, ProgressBar, Selectable, TreeNode, Ui, Window,
 };
 use imgui_glium_renderer::Renderer;
 use imgui_winit_support::{HiDpiMode, WinitPlatform};
@@ -101,6 +106,10 @@ fn main() {
     state.d3d_test_window_state = D3DTestWindowState {
         process_name: "DarkSoulsIII.exe".to_string(),
         process: Process::from_name("DarkSoulsIII.exe"),
+        instances: Vec::new(),
+        dump_path: String::new(),
+        dump: None,
+        root_address: "7FF4AD045A58".to_string(),
         memory_tree: Some(MemoryTreeState {
             address: 0x7FF4AD045A58,
             item_type: MemoryItemType::Class,
@@ -167,8 +176,12 @@ fn run_ui(state: &mut UIState, ui: &mut Ui) {
         ui,
     );
     state.rtti_window_state.process_name = state.d3d_test_window_state.process_name.clone();
-    state.rtti_window_state.process =
-        Process::from_name(state.rtti_window_state.process_name.as_str());
+    // Follow the instance picked in the D3D window until another one is picked here
+    let pid = state.d3d_test_window_state.process.as_ref().map(|ps| ps.id);
+    if state.rtti_window_state.synced_pid != pid {
+        state.rtti_window_state.synced_pid = pid;
+        state.rtti_window_state.process = pid.and_then(Process::from_pid);
+    }
     rtti_search_window(
         state.bar_state.rtti_viewer,
         &mut state.rtti_window_state,
@@ -281,6 +294,10 @@ pub struct Vector4x4 {
 struct D3DTestWindowState {
     pub process_name: String,
     pub process: Option<Process>,
+    pub instances: Vec<ProcessInfo>,
+    pub dump_path: String,
+    pub dump: Option<Minidump>,
+    pub root_address: String,
     pub memory_tree: Option<MemoryTreeState>,
 }
 
@@ -334,8 +351,8 @@ impl MemoryTreeState {
     }
 }
 
-fn memory_tree_node<'a>(
-    ps: &mut Process,
+fn memory_tree_node<'a, R: MemoryReader>(
+    ps: &R,
     offset: Option<usize>,
     state: &'a mut MemoryTreeState,
     ui: &Ui,
@@ -677,7 +694,7 @@ fn memory_tree_node<'a>(
     value_size
 }
 
-fn memory_tree_item_menu(ps: &Process, state: &mut MemoryTreeState, ui: &Ui) {
+fn memory_tree_item_menu<R: MemoryReader>(ps: &R, state: &mut MemoryTreeState, ui: &Ui) {
     ui.popup(state.popup_menu_id(), || {
         if let Some(menu) = ui.begin_menu("Change Type") {
             for el in [
@@ -753,30 +770,99 @@ fn memory_tree_item_menu(ps: &Process, state: &mut MemoryTreeState, ui: &Ui) {
     });
 }
 
+// Name input, lists every running instance when more than one matches
+fn process_picker(
+    ui: &Ui,
+    process_name: &mut String,
+    instances: &mut Vec<ProcessInfo>,
+    process: &mut Option<Process>,
+) -> bool {
+    let label = match process {
+        Some(ps) => format!("Process: {}", ps.id),
+        None => "Process: Not Select".to_owned(),
+    };
+    let mut changed = false;
+    if ui
+        .input_text(label, process_name)
+        .enter_returns_true(true)
+        .build()
+        && !process_name.is_empty()
+    {
+        *instances = Process::find_all(&ProcessMatch::Exact(process_name.as_str()));
+        *process = match instances.as_slice() {
+            [info] => Process::from_pid(info.pid),
+            _ => None,
+        };
+        changed = true;
+    }
+    if instances.len() > 1 {
+        for info in instances.iter() {
+            let selected = process.as_ref().map(|ps| ps.id) == Some(info.pid);
+            let label = format!(
+                "{} {} {}",
+                info.pid,
+                info.name,
+                info.window_title.as_deref().unwrap_or("")
+            );
+            if Selectable::new(label).selected(selected).build(ui) {
+                *process = Process::from_pid(info.pid);
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
 fn d3d_test_window(show: bool, state: &mut D3DTestWindowState, ui: &mut Ui) {
     if show {
-        let mut process_name_change = false;
         Window::new("D3D Test")
             .size([512.0, 400.0], Condition::Appearing)
             .build(ui, || {
                 // Render
-                let label;
-                if let Some(ps) = &state.process {
-                    label = format!("Process: {}", ps.id);
-                } else {
-                    label = "Process: Not Select".to_owned();
-                }
-                process_name_change = ui
-                    .input_text(label, &mut state.process_name)
+                let process_change = process_picker(
+                    ui,
+                    &mut state.process_name,
+                    &mut state.instances,
+                    &mut state.process,
+                );
+
+                let dump_label = match &state.dump {
+                    Some(dump) => format!("Dump: {} Modules", dump.modules().len()),
+                    None => "Dump: Not Open".to_owned(),
+                };
+                let dump_path_change = ui
+                    .input_text(dump_label, &mut state.dump_path)
                     .enter_returns_true(true)
                     .build();
 
                 // Logic
-                if process_name_change && !state.process_name.is_empty() {
-                    state.process = Process::from_name(state.process_name.as_str());
+                if process_change {
+                    state.dump = None;
+                }
+                if dump_path_change {
+                    state.dump = Minidump::open(state.dump_path.as_str()).ok();
                 }
 
-                if let Some(ps) = &mut state.process {
+                // Pointer chain such as [[DarkSoulsIII.exe+4768E78]+80]+1F90
+                if ui
+                    .input_text("Address", &mut state.root_address)
+                    .enter_returns_true(true)
+                    .build()
+                {
+                    let chain = PointerChain::parse(state.root_address.as_str()).ok();
+                    let address = match (chain, &state.dump, &state.process) {
+                        (Some(chain), Some(dump), _) => chain.resolve(dump).ok(),
+                        (Some(chain), None, Some(ps)) => chain.resolve(ps).ok(),
+                        _ => None,
+                    };
+                    if let (Some(address), Some(tree)) = (address, state.memory_tree.as_mut()) {
+                        tree.address = address;
+                    }
+                }
+
+                if let Some(dump) = &state.dump {
+                    memory_tree_node(dump, None, state.memory_tree.as_mut().unwrap(), ui);
+                } else if let Some(ps) = &state.process {
                     memory_tree_node(ps, None, state.memory_tree.as_mut().unwrap(), ui);
                 }
             });
@@ -787,32 +873,65 @@ fn d3d_test_window(show: bool, state: &mut D3DTestWindowState, ui: &mut Ui) {
 struct RTTISearchWindowState {
     pub process_name: String,
     pub process: Option<Process>,
+    pub instances: Vec<ProcessInfo>,
+    pub synced_pid: Option<u32>,
+    pub module: String,
+    pub task: Option<ScanTask<Vec<RTTIInfo>>>,
+    pub rtti: Vec<RTTIInfo>,
+    pub error: Option<String>,
 }
 
 fn rtti_search_window(show: bool, state: &mut RTTISearchWindowState, ui: &mut Ui) {
     if show {
-        let mut process_name_change = false;
         Window::new("RTTI Search")
             .size([512.0, 400.0], Condition::Appearing)
             .build(ui, || {
                 // Render
-                let label;
-                if let Some(ps) = &state.process {
-                    label = format!("Process: {}", ps.id);
-                } else {
-                    label = "Process: Not Select".to_owned();
-                }
-                process_name_change = ui
-                    .input_text(label, &mut state.process_name)
-                    .enter_returns_true(true)
-                    .build();
+                process_picker(
+                    ui,
+                    &mut state.process_name,
+                    &mut state.instances,
+                    &mut state.process,
+                );
+                ui.input_text("Module", &mut state.module).build();
 
                 // Logic
-                if process_name_change && !state.process_name.is_empty() {
-                    state.process = Process::from_name(state.process_name.as_str());
+                let pid = state.process.as_ref().map(|ps| ps.id);
+                if let (Some(pid), None) = (pid, &state.task) {
+                    if ui.button("Dump RTTI") {
+                        let module = state.module.clone();
+                        state.error = None;
+                        state.task = Some(ScanTask::spawn(ScanExecutor::new(), move |executor| {
+                            let ps = Process::from_pid(pid)
+                                .ok_or_else(|| ProcessError::ProcessNotFound(pid.to_string()))?;
+                            ps.rtti_dump(module.as_str(), executor)
+                        }));
+                    }
+                }
+
+                if let Some(task) = &state.task {
+                    let progress = task.progress();
+                    let text = format!("{} / {} Types", progress.done, progress.total);
+                    ProgressBar::new(progress.fraction())
+                        .overlay_text(&text)
+                        .build(ui);
+                    if ui.button("Cancel") {
+                        task.cancel();
+                    }
+                }
+                if state.task.as_ref().map_or(false, |task| task.is_finished()) {
+                    match state.task.take().unwrap().join() {
+                        Ok(rtti) => state.rtti = rtti,
+                        Err(error) => state.error = Some(error.to_string()),
+                    }
                 }
 
-                if let Some(ps) = &mut state.process {}
+                if let Some(error) = &state.error {
+                    ui.text_colored([1.0, 0.3, 0.3, 1.0], error);
+                }
+                for rtti in &state.rtti {
+                    ui.text(format!("{:X} {}", rtti.vf_ptr, rtti.type_desc));
+                }
             });
     }
 }